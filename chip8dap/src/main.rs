@@ -0,0 +1,46 @@
+mod protocol;
+mod session;
+
+use std::{io, sync::mpsc::{self, TryRecvError}, thread};
+
+use protocol::Output;
+use session::Session;
+
+fn main() -> io::Result<()> {
+    // requests are read on their own thread so that a running program can be paused
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        while let Ok(Some(message)) = protocol::read_message(&mut stdin) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = Session::new(Output::new(io::stdout()));
+    loop {
+        let request = if session.is_running() {
+            match receiver.try_recv() {
+                Ok(request) => Some(request),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        } else {
+            match receiver.recv() {
+                Ok(request) => Some(request),
+                Err(_) => return Ok(()),
+            }
+        };
+
+        if let Some(request) = request {
+            if !session.handle(&request)? {
+                return Ok(());
+            }
+        }
+
+        if session.is_running() {
+            session.run_slice()?;
+        }
+    }
+}
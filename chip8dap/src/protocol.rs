@@ -0,0 +1,148 @@
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value};
+
+/// Reads one `Content-Length` framed message. Returns `None` once the input stream is closed.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length.unwrap_or_default()];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+/// Numbers outgoing messages and writes them to the client.
+pub struct Output<W: Write> {
+    writer: W,
+    seq: u64,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, seq: 0 }
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.writer, &message)
+    }
+
+    pub fn respond(&mut self, request: &Value, body: Value) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }))
+    }
+
+    pub fn respond_error(&mut self, request: &Value, message: &str) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }))
+    }
+
+    pub fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }))
+    }
+}
+
+/// Formats an address the way memory and instruction references are passed over the protocol.
+pub fn format_reference(address: u16) -> String {
+    format!("0x{address:03X}")
+}
+
+/// Parses a memory or instruction reference, accepting either hex (`0x2A4`) or decimal.
+pub fn parse_reference(reference: &str) -> Option<i64> {
+    let reference = reference.trim();
+    if let Some(hex) = reference.strip_prefix("0x").or_else(|| reference.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else {
+        reference.parse().ok()
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// `readMemory` transfers its data base64-encoded.
+pub fn encode_base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = (u32::from(chunk[0]) << 16)
+            | (u32::from(*chunk.get(1).unwrap_or(&0)) << 8)
+            | u32::from(*chunk.get(2).unwrap_or(&0));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(BASE64_ALPHABET[((bits >> (18 - 6 * i)) & 0x3F) as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_message() {
+        let mut input = io::Cursor::new(b"Content-Length: 10\r\n\r\n{\"seq\": 1}Content-Length: 2\r\n\r\n{}".to_vec());
+
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({"seq": 1})));
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({})));
+        assert_eq!(read_message(&mut input).unwrap(), None)
+    }
+
+    #[test]
+    fn test_parse_reference() {
+        assert_eq!(parse_reference("0x2A4"), Some(0x2A4));
+        assert_eq!(parse_reference("512"), Some(512));
+        assert_eq!(parse_reference("main"), None)
+    }
+
+    #[test]
+    fn test_encode_base64() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"f"), "Zg==");
+        assert_eq!(encode_base64(b"fo"), "Zm8=");
+        assert_eq!(encode_base64(b"foo"), "Zm9v");
+        assert_eq!(encode_base64(&[0x12, 0x34, 0xFF, 0x00]), "EjT/AA==")
+    }
+}
@@ -0,0 +1,457 @@
+use std::{collections::BTreeSet, fs, io::{self, Write}, panic::{self, AssertUnwindSafe}, path::{Path, PathBuf}};
+
+use chip8exe::{Chip8State, Reg, coverage::{self, Coverage}, frontend::panic_message, opcode::Opcode, romdb::RomDb, stack, symbols::Symbols};
+use serde_json::{json, Value};
+
+use crate::protocol::{Output, format_reference, parse_reference, encode_base64};
+
+const THREAD_ID: u64 = 1;
+const MEMORY_SIZE: usize = 4096;
+/// The disassembly listing of the loaded ROM is served to the client under this source reference.
+const LISTING_REFERENCE: u64 = 1;
+/// Instructions executed between checks for incoming requests while running.
+const RUN_SLICE: u32 = 10_000;
+
+const REGISTERS_REFERENCE: u64 = 1;
+const TIMERS_REFERENCE: u64 = 2;
+const STACK_REFERENCE: u64 = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum RunMode {
+    Stopped,
+    /// Not yet told to start by `configurationDone`
+    Configuring { stop_on_entry: bool },
+    Running,
+    /// Run until the instruction after a `CALL` is reached at the same stack depth
+    StepOver { return_pc: u16, sp: u8 },
+    /// Run until the stack pointer drops below its current depth
+    StepOut { sp: u8 },
+}
+
+pub struct Session<W: Write> {
+    output: Output<W>,
+    state: Chip8State,
+    program: Option<PathBuf>,
+    rom_len: usize,
+    symbols: Symbols,
+    /// Coverage map given at launch, telling data apart from code
+    data_map: Option<Coverage>,
+    source_breakpoints: BTreeSet<u16>,
+    instruction_breakpoints: BTreeSet<u16>,
+    run_mode: RunMode,
+    /// PC execution resumed from, whose breakpoint was already reported, so it does not stop there again
+    resumed_from: Option<u16>,
+    instr_count: u64,
+    instructions_per_frame: u64,
+}
+
+impl<W: Write> Session<W> {
+    pub fn new(output: Output<W>) -> Self {
+        Self {
+            output,
+            state: Chip8State::default(),
+            program: None,
+            rom_len: 0,
+            symbols: Symbols::default(),
+            data_map: None,
+            source_breakpoints: BTreeSet::new(),
+            instruction_breakpoints: BTreeSet::new(),
+            run_mode: RunMode::Stopped,
+            resumed_from: None,
+            instr_count: 0,
+            instructions_per_frame: 10,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(self.run_mode, RunMode::Running | RunMode::StepOver { .. } | RunMode::StepOut { .. })
+    }
+
+    /// Handles one request from the client. Returns false once the session should end.
+    pub fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        if request["type"] != "request" {
+            return Ok(true);
+        }
+        let args = &request["arguments"];
+
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                self.output.respond(request, json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsDisassembleRequest": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsTerminateRequest": true,
+                }))?;
+                self.output.event("initialized", json!({}))?;
+            },
+            "launch" => match self.launch(args) {
+                Ok(()) => self.output.respond(request, json!({}))?,
+                Err(message) => self.output.respond_error(request, &message)?,
+            },
+            "setBreakpoints" => {
+                let breakpoints = self.set_source_breakpoints(args);
+                self.output.respond(request, json!({ "breakpoints": breakpoints }))?;
+            },
+            "setInstructionBreakpoints" => {
+                let breakpoints = self.set_instruction_breakpoints(args);
+                self.output.respond(request, json!({ "breakpoints": breakpoints }))?;
+            },
+            "setExceptionBreakpoints" => self.output.respond(request, json!({}))?,
+            "configurationDone" => {
+                self.output.respond(request, json!({}))?;
+                if let RunMode::Configuring { stop_on_entry } = self.run_mode {
+                    if stop_on_entry {
+                        self.stop("entry")?;
+                    } else {
+                        self.run_mode = RunMode::Running;
+                    }
+                }
+            },
+            "threads" => self.output.respond(request, json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] }))?,
+            "stackTrace" => {
+                let frames = self.stack_frames();
+                self.output.respond(request, json!({ "totalFrames": frames.len(), "stackFrames": frames }))?;
+            },
+            "scopes" => self.output.respond(request, json!({ "scopes": [
+                { "name": "Registers", "presentationHint": "registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS_REFERENCE, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK_REFERENCE, "expensive": false },
+            ]}))?,
+            "variables" => {
+                let variables = self.variables(args["variablesReference"].as_u64().unwrap_or_default());
+                self.output.respond(request, json!({ "variables": variables }))?;
+            },
+            "source" => {
+                let content = self.listing();
+                self.output.respond(request, json!({ "content": content, "mimeType": "text/x-chip8-disassembly" }))?;
+            },
+            "readMemory" => match self.read_memory(args) {
+                Some(body) => self.output.respond(request, body)?,
+                None => self.output.respond_error(request, "invalid memory reference")?,
+            },
+            "disassemble" => match self.disassemble(args) {
+                Some(instructions) => self.output.respond(request, json!({ "instructions": instructions }))?,
+                None => self.output.respond_error(request, "invalid memory reference")?,
+            },
+            "continue" => {
+                self.output.respond(request, json!({ "allThreadsContinued": true }))?;
+                self.resume(RunMode::Running);
+            },
+            "next" => {
+                self.output.respond(request, json!({}))?;
+                if let Opcode::CALL(_) = self.state.decode_opcode() {
+                    self.resume(RunMode::StepOver { return_pc: self.state.pc.wrapping_add(2), sp: self.state.sp });
+                } else {
+                    self.single_step()?;
+                }
+            },
+            "stepIn" => {
+                self.output.respond(request, json!({}))?;
+                self.single_step()?;
+            },
+            "stepOut" => {
+                self.output.respond(request, json!({}))?;
+                self.resume(RunMode::StepOut { sp: self.state.sp });
+            },
+            "pause" => {
+                self.output.respond(request, json!({}))?;
+                if self.is_running() {
+                    self.stop("pause")?;
+                }
+            },
+            "terminate" => {
+                // the program must not go on running after the client was told it ended
+                self.run_mode = RunMode::Stopped;
+                self.output.respond(request, json!({}))?;
+                self.output.event("terminated", json!({}))?;
+            },
+            "disconnect" => {
+                self.output.respond(request, json!({}))?;
+                return Ok(false);
+            },
+            _ => self.output.respond_error(request, "unsupported request")?,
+        }
+
+        Ok(true)
+    }
+
+    /// Executes a bounded number of instructions, stopping early when a breakpoint or step target is reached.
+    /// Breakpoints stop before their instruction runs.
+    pub fn run_slice(&mut self) -> io::Result<()> {
+        for _ in 0..RUN_SLICE {
+            let pc = self.state.pc;
+            if self.resumed_from.take() != Some(pc) && (self.source_breakpoints.contains(&pc) || self.instruction_breakpoints.contains(&pc)) {
+                return self.stop("breakpoint");
+            }
+            if let Err(message) = self.execute_one() {
+                return self.stop_on_error(&message);
+            }
+
+            let pc = self.state.pc;
+            match self.run_mode {
+                RunMode::StepOver { return_pc, sp } if pc == return_pc && self.state.sp == sp => return self.stop("step"),
+                RunMode::StepOut { sp } if self.state.sp < sp => return self.stop("step"),
+                _ => {},
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Starts running from the stopped PC without stopping at its breakpoint again.
+    fn resume(&mut self, mode: RunMode) {
+        self.resumed_from = Some(self.state.pc);
+        self.run_mode = mode;
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<(), String> {
+        let program = args["program"].as_str().ok_or("launch requires a \"program\" ROM path")?;
+        let rom = fs::read(program).map_err(|err| format!("could not read {program}: {err}"))?;
+
+        self.symbols = match args["symbols"].as_str() {
+            Some(path) => Symbols::load(path).map_err(|err| format!("could not load symbols from {path}: {err}"))?,
+            None => Symbols::default(),
+        };
+        self.data_map = match args["coverage"].as_str() {
+            Some(path) => {
+                let text = fs::read_to_string(path).map_err(|err| format!("could not read coverage from {path}: {err}"))?;
+                Some(Coverage::parse(&text).map_err(|err| format!("could not load coverage from {path}: {err}"))?)
+            },
+            None => None,
+        };
+
+        self.state = Chip8State::default();
+        if let Some(depth) = args["stackDepth"].as_u64() {
+            self.state.stack_config.max_depth = u8::try_from(depth).ok().filter(|depth| (1..=stack::MAX_DEPTH).contains(depth))
+                .ok_or_else(|| format!("stackDepth must be between 1 and {}", stack::MAX_DEPTH))?;
+        }
+        self.state.stack_config.in_memory = args["stackInMemory"].as_bool().unwrap_or(false);
+        // known ROMs get the quirks and speed the ROM database lists for them
+        let db = RomDb::embedded();
+        let found = self.state.load_known_program(&rom, &db).map_err(|err| format!("could not load {program}: {err}"))?;
+        if let Some(found) = &found {
+            self.output.event("output", json!({ "category": "console", "output": format!("Loaded {}\n", found.describe()) }))
+                .map_err(|err| err.to_string())?;
+        }
+        self.rom_len = rom.len();
+        self.program = Some(PathBuf::from(program));
+        self.instr_count = 0;
+        if let Some(ipf) = args["instructionsPerFrame"].as_u64().or(found.and_then(|found| found.tickrate).map(u64::from)) {
+            self.instructions_per_frame = ipf.max(1);
+        }
+        self.run_mode = RunMode::Configuring { stop_on_entry: args["stopOnEntry"].as_bool().unwrap_or(false) };
+
+        Ok(())
+    }
+
+    fn execute_one(&mut self) -> Result<(), String> {
+        let timer_ticks = u32::from(self.instr_count != 0 && self.instr_count.is_multiple_of(self.instructions_per_frame));
+        let state = &mut self.state;
+        panic::catch_unwind(AssertUnwindSafe(|| state.tick(timer_ticks))).map_err(|panic| panic_message(&*panic))?;
+        if let Some(err) = state.stack_error {
+            return Err(err.to_string());
+        }
+        self.instr_count += 1;
+
+        Ok(())
+    }
+
+    fn single_step(&mut self) -> io::Result<()> {
+        match self.execute_one() {
+            Ok(()) => self.stop("step"),
+            Err(message) => self.stop_on_error(&message),
+        }
+    }
+
+    fn stop(&mut self, reason: &str) -> io::Result<()> {
+        self.run_mode = RunMode::Stopped;
+        self.output.event("stopped", json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }))
+    }
+
+    fn stop_on_error(&mut self, message: &str) -> io::Result<()> {
+        self.output.event("output", json!({ "category": "stderr", "output": format!("Emulator crashed: {message}\n") }))?;
+        self.run_mode = RunMode::Stopped;
+        self.output.event("stopped", json!({ "reason": "exception", "description": message, "threadId": THREAD_ID, "allThreadsStopped": true }))
+    }
+
+    fn set_source_breakpoints(&mut self, args: &Value) -> Vec<Value> {
+        self.source_breakpoints.clear();
+
+        let mut breakpoints = vec![];
+        for bp in args["breakpoints"].as_array().into_iter().flatten() {
+            let line = bp["line"].as_u64().unwrap_or_default();
+            let start = self.state.layout.program_start;
+            let address = line_to_address(start, line).filter(|addr| usize::from(addr - start) < self.rom_len);
+            if let Some(address) = address {
+                self.source_breakpoints.insert(address);
+            }
+            breakpoints.push(json!({
+                "verified": address.is_some(),
+                "line": line,
+                "instructionReference": address.map(format_reference),
+            }));
+        }
+
+        breakpoints
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Vec<Value> {
+        self.instruction_breakpoints.clear();
+
+        let mut breakpoints = vec![];
+        for bp in args["breakpoints"].as_array().into_iter().flatten() {
+            let address = bp["instructionReference"].as_str()
+                .and_then(|reference| parse_reference(reference).or_else(|| self.symbols.address(reference).map(i64::from)))
+                .and_then(|addr| addr.checked_add(bp["offset"].as_i64().unwrap_or_default()))
+                .and_then(|addr| u16::try_from(addr).ok())
+                .filter(|addr| usize::from(*addr) < MEMORY_SIZE);
+            if let Some(address) = address {
+                self.instruction_breakpoints.insert(address);
+            }
+            breakpoints.push(json!({
+                "verified": address.is_some(),
+                "instructionReference": address.map(format_reference),
+            }));
+        }
+
+        breakpoints
+    }
+
+    fn source(&self) -> Value {
+        let name = self.program.as_deref().and_then(Path::file_name).map_or_else(|| String::from("program"), |name| name.to_string_lossy().into_owned());
+        json!({ "name": format!("{name}.lst"), "sourceReference": LISTING_REFERENCE })
+    }
+
+    /// Frames are listed innermost first, each named after the subroutine it is executing in.
+    fn stack_frames(&self) -> Vec<Value> {
+        let call_stack = self.state.call_stack();
+
+        let mut frames = vec![];
+        let mut pc = self.state.pc;
+        for depth in (0..=call_stack.len()).rev() {
+            let name = match depth.checked_sub(1).map(|i| call_stack[i].target) {
+                Some(Some(target)) => self.symbols.name(target).map_or_else(|| format!("sub_{target:03X}"), String::from),
+                Some(None) => String::from("<unknown>"),
+                None => String::from("main"),
+            };
+            frames.push(json!({
+                "id": frames.len(),
+                "name": name,
+                "source": self.source(),
+                "line": address_to_line(self.state.layout.program_start, pc).unwrap_or_default(),
+                "column": 0,
+                "instructionPointerReference": format_reference(pc),
+            }));
+            if let Some(i) = depth.checked_sub(1) {
+                pc = call_stack[i].call_site;
+            }
+        }
+
+        frames
+    }
+
+    fn variables(&self, reference: u64) -> Vec<Value> {
+        let state = &self.state;
+        match reference {
+            REGISTERS_REFERENCE => {
+                let mut variables: Vec<Value> = (0..16).map(|i| {
+                    let register = Reg::from(i);
+                    variable(&format!("{register:?}"), format!("0x{:02X}", state.registers[register as usize]))
+                }).collect();
+                variables.push(json!({ "name": "I", "value": format_reference(state.index), "variablesReference": 0, "memoryReference": format_reference(state.index) }));
+                variables.push(json!({ "name": "PC", "value": format_reference(state.pc), "variablesReference": 0, "memoryReference": format_reference(state.pc) }));
+                variables.push(variable("SP", format!("0x{:02X}", state.sp)));
+                variables
+            },
+            TIMERS_REFERENCE => vec![
+                variable("DT", format!("0x{:02X}", state.dt)),
+                variable("ST", format!("0x{:02X}", state.st)),
+                variable("Input", format!("{:016b}", state.input)),
+            ],
+            STACK_REFERENCE => state.call_stack().iter().enumerate()
+                .map(|(i, frame)| variable(&format!("[{i}]"), format_reference(frame.call_site)))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    fn listing(&self) -> String {
+        let mut listing = String::new();
+        for offset in (0..self.rom_len).step_by(2) {
+            let address = self.state.layout.program_start + u16::try_from(offset).unwrap_or_default();
+            let instruction = fetch(&self.state, address);
+            listing.push_str(&format!("{address:03X}: {instruction:04X}  {}\n", self.disassemble_at(address, instruction)));
+        }
+
+        listing
+    }
+
+    fn read_memory(&self, args: &Value) -> Option<Value> {
+        let start = args["memoryReference"].as_str().and_then(parse_reference)?.saturating_add(args["offset"].as_i64().unwrap_or_default());
+        // no read can cover more than the whole address space
+        let count = args["count"].as_i64().unwrap_or_default().clamp(0, MEMORY_SIZE as i64);
+
+        let readable = usize::try_from(start).ok().filter(|start| *start < MEMORY_SIZE).map(|start| {
+            let end = start.saturating_add(usize::try_from(count).unwrap_or_default()).min(MEMORY_SIZE);
+            &self.state.memory[start..end]
+        }).unwrap_or_default();
+
+        Some(json!({
+            "address": format!("0x{start:03X}"),
+            "data": encode_base64(readable),
+            "unreadableBytes": count - i64::try_from(readable.len()).unwrap_or_default(),
+        }))
+    }
+
+    /// Decodes an instruction, unless the coverage map shows only data was ever accessed there.
+    fn disassemble_at(&self, address: u16, instruction: u16) -> String {
+        let is_data = |addr| self.data_map.as_ref().is_some_and(|map| map.get(addr) == coverage::DATA);
+        if is_data(address) && is_data(address + 1) {
+            format!("DATA {:02X} {:02X}", instruction >> 8, instruction & 0xFF)
+        } else {
+            format!("{:X?}", Opcode::decode(instruction, self.state.variant))
+        }
+    }
+
+    fn disassemble(&self, args: &Value) -> Option<Vec<Value>> {
+        let base = args["memoryReference"].as_str().and_then(parse_reference)?
+            .saturating_add(args["offset"].as_i64().unwrap_or_default())
+            .saturating_add(args["instructionOffset"].as_i64().unwrap_or_default().saturating_mul(2));
+        let count = args["instructionCount"].as_i64().unwrap_or_default().clamp(0, MEMORY_SIZE as i64 / 2);
+
+        Some((0..count).map(|i| base.saturating_add(2 * i)).map(|address| match u16::try_from(address).ok().filter(|addr| usize::from(*addr) + 1 < MEMORY_SIZE) {
+            Some(address) => {
+                let instruction = fetch(&self.state, address);
+                json!({
+                    "address": format_reference(address),
+                    "instructionBytes": format!("{instruction:04X}"),
+                    "instruction": self.disassemble_at(address, instruction),
+                    "symbol": self.symbols.name(address),
+                    "location": self.source(),
+                    "line": address_to_line(self.state.layout.program_start, address),
+                })
+            },
+            None => json!({ "address": format!("0x{address:03X}"), "instruction": "??", "presentationHint": "invalid" }),
+        }).collect())
+    }
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+fn fetch(state: &Chip8State, address: u16) -> u16 {
+    let address = usize::from(address) % MEMORY_SIZE;
+    (u16::from(state.memory[address]) << 8) | u16::from(state.memory[(address + 1) % MEMORY_SIZE])
+}
+
+/// Listing lines are 1-based and hold one instruction each, beginning at the program start address `start`.
+fn address_to_line(start: u16, address: u16) -> Option<u64> {
+    address.checked_sub(start).map(|offset| u64::from(offset / 2) + 1)
+}
+
+fn line_to_address(start: u16, line: u64) -> Option<u16> {
+    let offset = line.checked_sub(1)? * 2;
+    u16::try_from(offset).ok().and_then(|offset| start.checked_add(offset))
+}
@@ -0,0 +1,216 @@
+use std::{env, fs, io::{BufRead, BufReader, Read, Write}, process::{Child, ChildStdin, ChildStdout, Command, Stdio}};
+
+use serde_json::{json, Value};
+
+// 200: LDVB(VA, 5)  202: CALL(208)  204: ADDVB(VA, 1)  206: JP(206)  208: LDVB(VB, 7)  20A: RET
+const ROM: [u8; 12] = [0x6A, 0x05, 0x22, 0x08, 0x7A, 0x01, 0x12, 0x06, 0x6B, 0x07, 0x00, 0xEE];
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    seq: u64,
+    /// Events read while waiting for a response
+    skipped: Vec<Value>,
+}
+
+impl Client {
+    fn spawn() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_chip8dap"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+
+        Self { child, stdin, stdout, seq: 0, skipped: vec![] }
+    }
+
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        self.seq += 1;
+        let body = json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments }).to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        self.stdin.flush().unwrap();
+
+        loop {
+            let message = self.next_message();
+            if message["type"] == "response" && message["request_seq"] == self.seq {
+                assert_eq!(message["success"], true, "{command} failed: {message}");
+                return message["body"].clone();
+            }
+            self.skipped.push(message);
+        }
+    }
+
+    fn expect_event(&mut self, event: &str) -> Value {
+        loop {
+            let message = self.next_message();
+            if message["type"] == "event" && message["event"] == event {
+                return message["body"].clone();
+            }
+        }
+    }
+
+    fn next_message(&mut self) -> Value {
+        let mut header = String::new();
+        self.stdout.read_line(&mut header).unwrap();
+        let length = header.trim().strip_prefix("Content-Length: ").expect("adapter closed its output").parse().unwrap();
+        self.stdout.read_line(&mut header).unwrap();
+
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+}
+
+#[test]
+fn test_scripted_session() {
+    let rom_path = env::temp_dir().join(format!("chip8dap_test_{}.ch8", std::process::id()));
+    fs::write(&rom_path, ROM).unwrap();
+
+    let mut client = Client::spawn();
+
+    let capabilities = client.request("initialize", json!({ "adapterID": "chip8" }));
+    assert_eq!(capabilities["supportsReadMemoryRequest"], true);
+    client.expect_event("initialized");
+
+    client.request("launch", json!({ "program": rom_path, "stopOnEntry": true }));
+    let breakpoints = client.request("setBreakpoints", json!({ "source": { "sourceReference": 1 }, "breakpoints": [{ "line": 5 }, { "line": 40 }] }));
+    assert_eq!(breakpoints["breakpoints"][0]["verified"], true);
+    assert_eq!(breakpoints["breakpoints"][0]["instructionReference"], "0x208");
+    assert_eq!(breakpoints["breakpoints"][1]["verified"], false);
+    client.request("configurationDone", json!({}));
+    assert_eq!(client.expect_event("stopped")["reason"], "entry");
+
+    // run into the subroutine breakpoint
+    client.request("continue", json!({ "threadId": 1 }));
+    assert_eq!(client.expect_event("stopped")["reason"], "breakpoint");
+    let trace = client.request("stackTrace", json!({ "threadId": 1 }));
+    assert_eq!(trace["totalFrames"], 2);
+    assert_eq!(trace["stackFrames"][0]["name"], "sub_208");
+    assert_eq!(trace["stackFrames"][0]["instructionPointerReference"], "0x208");
+    assert_eq!(trace["stackFrames"][1]["name"], "main");
+    assert_eq!(trace["stackFrames"][1]["line"], 2);
+
+    client.request("stepOut", json!({ "threadId": 1 }));
+    assert_eq!(client.expect_event("stopped")["reason"], "step");
+    let registers = client.request("variables", json!({ "variablesReference": 1 }));
+    let find = |name: &str| registers["variables"].as_array().unwrap().iter().find(|v| v["name"] == name).unwrap()["value"].clone();
+    assert_eq!(find("VA"), "0x05");
+    assert_eq!(find("VB"), "0x07");
+    assert_eq!(find("PC"), "0x204");
+
+    client.request("next", json!({ "threadId": 1 }));
+    assert_eq!(client.expect_event("stopped")["reason"], "step");
+    let timers = client.request("variables", json!({ "variablesReference": 2 }));
+    assert_eq!(timers["variables"][0]["name"], "DT");
+
+    let memory = client.request("readMemory", json!({ "memoryReference": "0x200", "offset": 0, "count": 4 }));
+    assert_eq!(memory["data"], "agUiCA==");
+    assert_eq!(memory["unreadableBytes"], 0);
+
+    let disassembly = client.request("disassemble", json!({ "memoryReference": "0x200", "instructionCount": 2 }));
+    assert_eq!(disassembly["instructions"][1]["instruction"], "CALL(208)");
+
+    // the program ends in an infinite loop, so it only stops when asked to
+    client.request("setBreakpoints", json!({ "source": { "sourceReference": 1 }, "breakpoints": [] }));
+    client.request("continue", json!({ "threadId": 1 }));
+    client.request("pause", json!({ "threadId": 1 }));
+    assert_eq!(client.expect_event("stopped")["reason"], "pause");
+
+    client.request("disconnect", json!({}));
+    assert!(client.child.wait().unwrap().success());
+
+    fs::remove_file(rom_path).unwrap();
+}
+
+#[test]
+fn test_breakpoints_stop_before_executing() {
+    let rom_path = env::temp_dir().join(format!("chip8dap_breakpoints_{}.ch8", std::process::id()));
+    fs::write(&rom_path, ROM).unwrap();
+
+    let mut client = Client::spawn();
+    client.request("initialize", json!({ "adapterID": "chip8" }));
+    client.request("launch", json!({ "program": rom_path }));
+    client.request("setBreakpoints", json!({ "source": { "sourceReference": 1 }, "breakpoints": [{ "line": 1 }, { "line": 4 }] }));
+    client.request("configurationDone", json!({}));
+
+    // the entry point's breakpoint stops before LDVB runs
+    assert_eq!(client.expect_event("stopped")["reason"], "breakpoint");
+    let registers = client.request("variables", json!({ "variablesReference": 1 }));
+    let find = |registers: &Value, name: &str| registers["variables"].as_array().unwrap().iter().find(|v| v["name"] == name).unwrap()["value"].clone();
+    assert_eq!(find(&registers, "VA"), "0x00");
+    assert_eq!(find(&registers, "PC"), "0x200");
+
+    // resuming runs the instruction it stopped at, and the loop stops at its own breakpoint every time around
+    for _ in 0..2 {
+        client.request("continue", json!({ "threadId": 1 }));
+        assert_eq!(client.expect_event("stopped")["reason"], "breakpoint");
+        let registers = client.request("variables", json!({ "variablesReference": 1 }));
+        assert_eq!(find(&registers, "VA"), "0x06");
+        assert_eq!(find(&registers, "PC"), "0x206");
+    }
+
+    client.request("disconnect", json!({}));
+    assert!(client.child.wait().unwrap().success());
+
+    fs::remove_file(rom_path).unwrap();
+}
+
+#[test]
+fn test_coverage_marks_data() {
+    let rom_path = env::temp_dir().join(format!("chip8dap_coverage_{}.ch8", std::process::id()));
+    let coverage_path = rom_path.with_extension("cov");
+    fs::write(&rom_path, ROM).unwrap();
+    fs::write(&coverage_path, "200-207 code\n208-209 data\n").unwrap();
+
+    let mut client = Client::spawn();
+    client.request("initialize", json!({ "adapterID": "chip8" }));
+    client.request("launch", json!({ "program": rom_path, "coverage": coverage_path, "stopOnEntry": true }));
+
+    let disassembly = client.request("disassemble", json!({ "memoryReference": "0x206", "instructionCount": 3 }));
+    assert_eq!(disassembly["instructions"][0]["instruction"], "JP(206)");
+    assert_eq!(disassembly["instructions"][1]["instruction"], "DATA 6B 07");
+    // untouched bytes are still decoded
+    assert_eq!(disassembly["instructions"][2]["instruction"], "RET");
+
+    client.request("disconnect", json!({}));
+    assert!(client.child.wait().unwrap().success());
+
+    fs::remove_file(rom_path).unwrap();
+    fs::remove_file(coverage_path).unwrap();
+}
+
+#[test]
+fn test_out_of_range_requests_and_terminate() {
+    let rom_path = env::temp_dir().join(format!("chip8dap_terminate_{}.ch8", std::process::id()));
+    fs::write(&rom_path, ROM).unwrap();
+
+    let mut client = Client::spawn();
+    client.request("initialize", json!({ "adapterID": "chip8" }));
+    client.request("launch", json!({ "program": rom_path, "stopOnEntry": true }));
+    client.request("configurationDone", json!({}));
+    client.expect_event("stopped");
+
+    // offsets that would overflow saturate, and counts are capped at the 4 KiB address space
+    let memory = client.request("readMemory", json!({ "memoryReference": "0xFFE", "offset": i64::MAX, "count": i64::MAX }));
+    assert_eq!(memory["data"], "");
+    assert_eq!(memory["unreadableBytes"], 4096);
+    let disassembly = client.request("disassemble", json!({ "memoryReference": "0x200", "instructionOffset": i64::MAX, "instructionCount": i64::MAX }));
+    let instructions = disassembly["instructions"].as_array().unwrap();
+    assert_eq!(instructions.len(), 2048);
+    assert!(instructions.iter().all(|instruction| instruction["presentationHint"] == "invalid"));
+
+    // once terminated, the program is no longer running, so there is nothing to pause
+    client.request("continue", json!({ "threadId": 1 }));
+    client.request("terminate", json!({}));
+    client.expect_event("terminated");
+    client.skipped.clear();
+    client.request("pause", json!({ "threadId": 1 }));
+    client.request("disconnect", json!({}));
+    assert!(client.skipped.iter().all(|event| event["event"] != "stopped"), "{:?}", client.skipped);
+    assert!(client.child.wait().unwrap().success());
+
+    fs::remove_file(rom_path).unwrap();
+}
@@ -1,53 +1,430 @@
-use std::{time::Duration, fs::File, io::{self, Read}};
+use std::{collections::BTreeSet, time::{Duration, Instant}, fs, io, path::{Path, PathBuf}};
 
-use chip8exe::{Chip8State, chip8_tick, chip8_reset};
+use chip8exe::{Chip8State, StackFrame, movie::{Movie, MoviePlayer, MovieRecorder}, phosphor::Phosphor, romdb::RomDb, symbols::Symbols};
+use rand::{SeedableRng, rngs::StdRng};
 use ratatui::widgets::{ListState, TableState};
 
-//                           0.5 Hz         1 Hz           5 Hz         10 Hz        100 Hz      1000 Hz    1 MHz
-const DURATIONS: [u64; 7] = [2_000_000_000, 1_000_000_000, 200_000_000, 100_000_000, 10_000_000, 1_000_000, 1000];
-//                           60 Hz
-pub const TIMER_RATE: u128 = 16_666_666;
+use crate::{browser::RomBrowser, config::Settings, display::RenderMode, script::Scripts};
 
 pub struct Failure {
     pub panic_message: String,
     pub last_instr_count: u64,
 }
 
+/// What the ROM database knows about the loaded ROM
+pub struct RomDetails {
+    /// Title and authors
+    pub title: String,
+    pub platform: Option<String>,
+    /// What the ROM uses CHIP-8 keys for, e.g. `("up", 5)`
+    pub keys: Vec<(String, u8)>,
+}
+
+/// What the hot spots panel lists
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum ProfileView {
+    /// Addresses, most executed first
+    #[default]
+    Hits,
+    /// Executed addresses in address order
+    Address,
+    /// Executions per opcode
+    Opcodes,
+    /// Loops closed by backward jumps
+    Loops,
+}
+
+impl ProfileView {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Hits => Self::Address,
+            Self::Address => Self::Opcodes,
+            Self::Opcodes => Self::Loops,
+            Self::Loops => Self::Hits,
+        }
+    }
+}
+
+/// A call stack entry as shown in the debugger
+pub struct CallFrame {
+    /// Where execution currently is (innermost frame) or will resume (outer frames)
+    pub address: u16,
+    /// The stack entry that entered this frame's subroutine; `None` for the top level
+    pub entered_by: Option<StackFrame>,
+}
+
 #[derive(Default)]
 pub struct App {
     pub last_failure: Option<Failure>,
     pub should_quit: bool,
+    pub settings: Settings,
     tick_rate: Option<Duration>,
+    /// Speed to continue at when unpausing
+    resume_rate: Duration,
     selected_rate: usize,
     pub instr_count: u64,
     pub chip_state: Chip8State,
+    pub rom_db: RomDb,
+    /// Set when the loaded ROM is in the database
+    pub rom_details: Option<RomDetails>,
+
+    /// Whether the terminal reports key releases; if not, keys are released after the configured timeout
+    pub key_release_events: bool,
+    /// When each held CHIP-8 key was last pressed or repeated
+    key_pressed_at: [Option<Instant>; 16],
 
-    pub stack_state: ListState,
+    pub symbols: Symbols,
+    pub breakpoints: BTreeSet<u16>,
+    /// Text typed into the command prompt, while it is open
+    pub prompt: Option<String>,
+    /// Feedback from the last command
+    pub message: Option<String>,
+    /// The ROM browser popup, while it is open
+    pub browser: Option<RomBrowser>,
+
+    pub profile_view: ProfileView,
+    /// `None` picks the largest mode that fits the terminal
+    pub render_mode: Option<RenderMode>,
+    /// Records every instruction run, while a movie is being recorded
+    recorder: Option<MovieRecorder>,
+    /// Supplies the input and timing of every instruction run, while a movie is being replayed
+    movie_player: Option<MoviePlayer>,
+    /// Rhai scripts, once one has been loaded
+    pub scripts: Option<Scripts>,
+
+    pub call_stack_state: ListState,
     pub memory_state: TableState,
     pub mem_row_sel_override: Option<usize>,
+    pub disasm_addr_override: Option<u16>,
 }
 
 impl App {
-    pub fn new(last_failure: Option<Failure>) -> Self {
-        Self {
-            last_failure,
-            ..Default::default()
+    pub fn new(settings: Settings) -> Self {
+        let mut app = Self::default();
+        app.chip_state.quirks = settings.quirks.unwrap_or_default();
+        app.chip_state.stack_config = settings.stack;
+        app.chip_state.variant = settings.variant.unwrap_or_default();
+        app.chip_state.layout = settings.layout(app.chip_state.variant);
+        app.chip_state.vip = settings.vip.clone().map(Box::new);
+        // puts the font where the layout has it, and the VIP interpreter image over it
+        app.chip_state.reset();
+        app.chip_state.smc = Some(Box::default());
+        app.chip_state.phosphor = settings.phosphor.map(|mode| Box::new(Phosphor::new(mode)));
+        app.render_mode = settings.render_mode;
+        if let Some(seed) = settings.seed {
+            app.chip_state.rng = StdRng::seed_from_u64(seed);
+        }
+        app.settings = settings;
+        app.set_resume_rate(app.settings.default_speed());
+
+        app
+    }
+
+    fn set_resume_rate(&mut self, rate: Duration) {
+        self.resume_rate = rate;
+        // the U/J controls continue from the listed speed closest to this one
+        self.selected_rate = (0..self.settings.speeds.len())
+            .min_by_key(|i| self.settings.speeds[*i].abs_diff(rate))
+            .unwrap_or_default();
+    }
+
+    /// Loads the ROM, symbols and breakpoints given in the settings, and starts running unless configured to start paused.
+    pub fn start(&mut self) -> io::Result<()> {
+        if let Some(dir) = &self.settings.rom_database {
+            self.rom_db = RomDb::load_dir(dir)?;
+        } else {
+            self.rom_db = RomDb::embedded();
+        }
+        self.reload_rom()?;
+        self.start_movie()?;
+        if let Some(path) = self.settings.symbols.clone() {
+            self.load_symbols(path)?;
+        }
+        for breakpoint in self.settings.breakpoints.clone() {
+            let addr = self.symbols.resolve(&breakpoint)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown breakpoint address {breakpoint}")))?;
+            self.breakpoints.insert(addr);
+        }
+        for path in self.settings.scripts.clone() {
+            self.load_script(&path).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {err}", path.display())))?;
+        }
+        if !self.settings.start_paused {
+            self.toggle_pause();
+        }
+        Ok(())
+    }
+
+    /// Starts recording or replaying the movie given in the settings, from the ROM just loaded.
+    fn start_movie(&mut self) -> io::Result<()> {
+        if self.settings.record.is_none() && self.settings.replay.is_none() {
+            return Ok(());
         }
+        let rom = fs::read(self.settings.rom.as_ref().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "recording or replaying a movie needs a ROM"))?)?;
+
+        if let Some(path) = &self.settings.replay {
+            let invalid = |err: &dyn std::error::Error| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {err}", path.display()));
+            let movie = fs::read_to_string(path)?.parse::<Movie>().map_err(|err| invalid(&err))?;
+            self.movie_player = Some(MoviePlayer::start(movie, &mut self.chip_state, &rom).map_err(|err| invalid(&err))?);
+        } else {
+            let seed = self.settings.seed.unwrap_or_else(rand::random);
+            self.recorder = Some(MovieRecorder::start(&mut self.chip_state, &rom, seed));
+        }
+        Ok(())
+    }
+
+    /// Saves the movie being recorded, ending the recording.
+    pub fn stop_recording(&mut self) {
+        let (Some(recorder), Some(path)) = (self.recorder.take(), &self.settings.record) else {
+            return
+        };
+        let instructions = recorder.instructions();
+        self.message = Some(match fs::write(path, recorder.finish(&self.chip_state).to_string()) {
+            Ok(()) => format!("Saved a movie of {instructions} instructions to {}", path.display()),
+            Err(err) => format!("Could not save the movie to {}: {err}", path.display()),
+        });
+    }
+
+    /// What is happening with a movie, if one is being recorded or replayed
+    pub fn movie_status(&self) -> Option<String> {
+        match (&self.recorder, &self.movie_player) {
+            (Some(recorder), _) => Some(format!("Recording {}", recorder.instructions())),
+            (None, Some(player)) => Some(format!("Replaying {}/{}", player.instructions(), player.movie().instructions())),
+            (None, None) => None,
+        }
+    }
+
+    pub fn press_key(&mut self, key: u8) {
+        self.chip_state.input |= 1 << key;
+        self.key_pressed_at[usize::from(key)] = Some(Instant::now());
     }
 
-    pub fn load_program(&mut self, path: &str) -> io::Result<()> {
-        let mut f = File::open(path)?;
-        let mut bytes_read = 0;
-        loop {
-            let read = f.read(&mut self.chip_state.memory[0x200 + bytes_read..])?;
-            if read == 0 {
-                break;
+    pub fn release_key(&mut self, key: u8) {
+        self.chip_state.input &= !(1 << key);
+        self.key_pressed_at[usize::from(key)] = None;
+    }
+
+    /// Emulates key releases on terminals that only report presses.
+    pub fn release_expired_keys(&mut self) {
+        if self.key_release_events {
+            return;
+        }
+
+        for key in 0..16 {
+            if self.key_pressed_at[usize::from(key)].is_some_and(|at| at.elapsed() >= self.settings.key_release_timeout) {
+                self.release_key(key);
             }
-            bytes_read += read;
+        }
+    }
+
+    /// Loads a ROM, taking its quirks, speed and instruction set from the ROM database unless they are configured.
+    /// A ROM for a variant loaded elsewhere resets the machine to load it there.
+    /// A ROM too large to fit at its load address fails with `InvalidData`, leaving the machine as it was.
+    pub fn load_program<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let rom = fs::read(path)?;
+        let found = self.rom_db.lookup(&rom);
+        let variant = self.settings.variant.or(found.as_ref().map(|found| found.variant)).unwrap_or_default();
+        let layout = self.settings.layout(variant);
+        let mut state = self.chip_state.clone();
+        state.variant = variant;
+        if layout != state.layout {
+            state.layout = layout;
+            state.reset();
+        }
+        state.load_program(&rom).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.chip_state = state;
+
+        let quirks = self.settings.quirks.or(found.as_ref().map(|found| found.quirks)).unwrap_or_default();
+        let speed = self.settings.initial_speed
+            .or(found.as_ref().and_then(|found| found.tickrate).filter(|ipf| *ipf > 0).map(|ipf| self.settings.timer_rate / ipf))
+            .unwrap_or(self.settings.default_speed());
+        self.rom_details = found.map(|found| RomDetails {
+            title: found.describe(),
+            platform: found.platform.map(|platform| platform.name.clone()),
+            keys: found.rom.keys.iter().map(|(name, key)| (name.clone(), *key)).collect(),
+        });
+        self.chip_state.quirks = quirks;
+        self.set_resume_rate(speed);
+
+        Ok(())
+    }
+
+    /// Loads the configured ROM file again, if there is one.
+    pub fn reload_rom(&mut self) -> io::Result<()> {
+        if let Some(path) = self.settings.rom.clone() {
+            self.load_program(path)?;
         }
         Ok(())
     }
 
+    /// Lists the call stack innermost frame first.
+    pub fn frames(&self) -> Vec<CallFrame> {
+        let call_stack = self.chip_state.call_stack();
+
+        let mut frames = vec![];
+        let mut address = self.chip_state.pc;
+        for entry in call_stack.iter().rev() {
+            frames.push(CallFrame { address, entered_by: Some(*entry) });
+            address = entry.return_address;
+        }
+        frames.push(CallFrame { address, entered_by: None });
+
+        frames
+    }
+
+    pub fn select_frame(&mut self, offset: isize) {
+        let last = self.frames().len() - 1;
+        let selected = self.call_stack_state.selected().unwrap_or_default().saturating_add_signed(offset);
+        self.call_stack_state.select(Some(selected.min(last)));
+    }
+
+    /// Points the memory and disassembly views at the selected call stack frame.
+    pub fn goto_selected_frame(&mut self) {
+        let frames = self.frames();
+        if let Some(frame) = frames.get(self.call_stack_state.selected().unwrap_or_default()) {
+            self.mem_row_sel_override = Some(usize::from(frame.address / 16));
+            self.disasm_addr_override = Some(frame.address);
+        }
+    }
+
+    /// Starts profiling, or stops and discards the profile.
+    pub fn toggle_profiler(&mut self) {
+        self.chip_state.profiler = match self.chip_state.profiler {
+            Some(_) => None,
+            None => Some(Box::default()),
+        };
+    }
+
+    /// Steps through the display modes, from automatic to each fixed mode from the largest down.
+    pub fn cycle_render_mode(&mut self) {
+        self.render_mode = match self.render_mode {
+            None => Some(RenderMode::ALL[0]),
+            Some(mode) => RenderMode::ALL.get(mode as usize + 1).copied(),
+        };
+        self.message = Some(format!("Display: {}", self.render_mode.map_or("auto", RenderMode::name)));
+    }
+
+    /// Starts classifying memory as code or data, or stops and discards the classification.
+    pub fn toggle_coverage(&mut self) {
+        self.chip_state.coverage = match self.chip_state.coverage {
+            Some(_) => None,
+            None => Some(Box::default()),
+        };
+    }
+
+    fn export_coverage(&self, path: &str) -> String {
+        let Some(coverage) = &self.chip_state.coverage else {
+            return String::from("Coverage is not being recorded; press K to start")
+        };
+        match fs::write(path, coverage.export()) {
+            Ok(()) => format!("Saved coverage to {path}"),
+            Err(err) => format!("Could not save coverage: {err}"),
+        }
+    }
+
+    /// Opens the ROM browser in the directory of the current ROM, or the working directory.
+    pub fn open_browser(&mut self) {
+        let dir = self.settings.rom.as_deref()
+            .and_then(Path::parent)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+        self.browse(&dir);
+    }
+
+    fn browse(&mut self, dir: &Path) {
+        match RomBrowser::open(dir) {
+            Ok(browser) => self.browser = Some(browser),
+            Err(err) => self.message = Some(format!("Could not list {}: {err}", dir.display())),
+        }
+    }
+
+    /// Enters the selected directory, or loads the selected ROM and closes the browser.
+    pub fn browser_choose(&mut self) {
+        let Some(entry) = self.browser.as_ref().and_then(RomBrowser::selected) else {
+            return
+        };
+        let (path, is_dir) = (entry.path.clone(), entry.is_dir());
+
+        if is_dir {
+            self.browse(&path);
+        } else {
+            self.browser = None;
+            self.message = Some(match self.switch_rom(path.clone()) {
+                Ok(()) => format!("Loaded {}", path.display()),
+                Err(err) => format!("Could not load {}: {err}", path.display()),
+            });
+        }
+    }
+
+    /// Resets the emulator and loads a different ROM, which Ctrl-R then reloads.
+    /// Symbols and breakpoints belong to the previous ROM, so they are dropped.
+    pub fn switch_rom(&mut self, path: PathBuf) -> io::Result<()> {
+        self.reset();
+        self.last_failure = None;
+        self.symbols = Symbols::default();
+        self.breakpoints.clear();
+        self.mem_row_sel_override = None;
+        self.disasm_addr_override = None;
+        self.settings.rom = Some(path);
+        self.reload_rom()
+    }
+
+    pub fn load_symbols<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.symbols = Symbols::load(path)?;
+        self.breakpoints.extend(self.symbols.breakpoints());
+        Ok(())
+    }
+
+    /// Loads a Rhai script and runs it, letting it register its callbacks.
+    pub fn load_script(&mut self, path: &Path) -> Result<(), String> {
+        let scripts = self.scripts.get_or_insert_with(Scripts::new);
+        scripts.set_movie(self.recorder.is_some() || self.movie_player.is_some());
+        let result = scripts.load(path, &mut self.chip_state);
+        if let Some(reason) = scripts.take_pause() {
+            self.pause_tick();
+            self.message = Some(reason);
+        }
+        result
+    }
+
+    /// Names an address by label where possible, e.g. `draw+4`, falling back to hex.
+    pub fn describe_addr(&self, addr: u16) -> String {
+        self.symbols.describe(addr).unwrap_or_else(|| format!("{addr:03X}"))
+    }
+
+    /// Runs a command entered at the prompt. Addresses may be given as labels, `label+offset` or hex.
+    pub fn run_command(&mut self, command: &str) {
+        let (name, arg) = command.trim().split_once(' ').unwrap_or((command.trim(), ""));
+
+        self.message = Some(match (name, self.symbols.resolve(arg)) {
+            ("b", Some(addr)) => if self.breakpoints.remove(&addr) {
+                format!("Removed breakpoint at {}", self.describe_addr(addr))
+            } else {
+                self.breakpoints.insert(addr);
+                format!("Added breakpoint at {}", self.describe_addr(addr))
+            },
+            ("g", Some(addr)) => {
+                self.mem_row_sel_override = Some(usize::from(addr / 16));
+                self.disasm_addr_override = Some(addr);
+                format!("Showing {}", self.describe_addr(addr))
+            },
+            ("b" | "g", None) => format!("Unknown address: {arg}"),
+            ("sym", _) => match self.load_symbols(arg.trim()) {
+                Ok(()) => format!("Loaded symbols from {}", arg.trim()),
+                Err(err) => format!("Could not load symbols: {err}"),
+            },
+            ("cov", _) => self.export_coverage(arg.trim()),
+            ("script", _) => match self.load_script(Path::new(arg.trim())) {
+                Ok(()) => format!("Loaded script {}", arg.trim()),
+                Err(err) => format!("Could not load script {}: {err}", arg.trim()),
+            },
+            _ => format!("Unknown command: {name}"),
+        });
+    }
+
+    pub fn at_breakpoint(&self) -> bool {
+        self.breakpoints.contains(&self.chip_state.pc)
+    }
+
     pub fn get_tick_rate(&self) -> Duration {
         self.tick_rate.unwrap_or(Duration::from_millis(16))
     }
@@ -57,51 +434,98 @@ impl App {
     }
 
     pub fn inc_tick_rate(&mut self) {
+        let speeds = &self.settings.speeds;
         if let Some(duration) = &mut self.tick_rate {
-            if let Some(rate) = DURATIONS.get(self.selected_rate + 1) {
+            if let Some(rate) = speeds.get(self.selected_rate + 1) {
                 self.selected_rate += 1;
-                *duration = Duration::from_nanos(*rate);
+                *duration = *rate;
             }
         } else {
-            let sel_rate = DURATIONS.len() - 1;
-            self.tick_rate = Some(Duration::from_nanos(DURATIONS[sel_rate])); // 1 MHz
+            let sel_rate = speeds.len() - 1;
+            self.tick_rate = Some(speeds[sel_rate]); // fastest
             self.selected_rate = sel_rate;
         }
     }
 
     pub fn dec_tick_rate(&mut self) {
+        let speeds = &self.settings.speeds;
         if let Some(duration) = &mut self.tick_rate {
             let sub = self.selected_rate.saturating_sub(1);
-            if let Some(rate) = DURATIONS.get(sub) {
+            if let Some(rate) = speeds.get(sub) {
                 self.selected_rate = sub;
-                *duration = Duration::from_nanos(*rate);
+                *duration = *rate;
             }
         } else {
-            self.tick_rate = Some(Duration::from_nanos(DURATIONS[0])); // 0.5 Hz
+            self.tick_rate = Some(speeds[0]); // slowest
             self.selected_rate = 0;
         }
     }
 
     pub fn pause_tick(&mut self) {
-        self.tick_rate = None;
+        if let Some(rate) = self.tick_rate.take() {
+            self.resume_rate = rate;
+        }
     }
 
-    pub fn on_tick(mut self, time_passed: u32) -> Self {
-        chip8_tick(&mut self.chip_state, time_passed);
+    pub fn toggle_pause(&mut self) {
+        if self.is_paused() {
+            self.tick_rate = Some(self.resume_rate);
+        } else {
+            self.pause_tick();
+        }
+    }
+
+    pub fn on_tick(&mut self, time_passed: u32) {
+        let watch = self.scripts.as_ref().map(|scripts| scripts.before_tick(&self.chip_state));
+        let smc_count = self.chip_state.smc.as_ref().map_or(0, |smc| smc.count());
+        if let Some(player) = &mut self.movie_player {
+            // the movie holds the timing, so the replay does not depend on the speed it runs at
+            let ended = match player.tick(&mut self.chip_state) {
+                Ok(true) => None,
+                Ok(false) => Some(format!("Replay finished after {} instructions; the keyboard takes over from here", player.instructions())),
+                Err(err) => Some(format!("Replay stopped: {err}")),
+            };
+            if let Some(message) = ended {
+                self.movie_player = None;
+                self.pause_tick();
+                self.message = Some(message);
+                return;
+            }
+        } else if let Some(recorder) = &mut self.recorder {
+            recorder.tick(&mut self.chip_state, time_passed);
+        } else {
+            self.chip_state.tick(time_passed);
+        }
         self.instr_count = self.instr_count.saturating_add(1);
 
+        if let Some(event) = self.chip_state.smc.as_ref().filter(|smc| smc.count() > smc_count).and_then(|smc| smc.events().back()) {
+            self.message = Some(format!("Self-modifying code: {} wrote {:02X} over {:02X} at {}",
+                self.describe_addr(event.pc), event.new_value, event.old_value, self.describe_addr(event.address)));
+        }
+
+        if let (Some(scripts), Some(watch)) = (&mut self.scripts, watch) {
+            scripts.set_movie(self.recorder.is_some() || self.movie_player.is_some());
+            scripts.after_tick(&mut self.chip_state, &watch, time_passed);
+            if let Some(reason) = scripts.take_pause() {
+                self.pause_tick();
+                self.message = Some(reason);
+            }
+        }
+
         if self.last_failure.is_some() {
             self.last_failure = None;
         }
-
-        self
     }
 
+    /// Resets the emulator, which ends a recording or replay.
     pub fn reset(&mut self) {
-        chip8_reset(&mut self.chip_state);
+        self.stop_recording();
+        self.movie_player = None;
+        self.chip_state.reset();
         self.pause_tick();
         self.instr_count = 0;
         self.chip_state.input = 0;
+        self.key_pressed_at = [None; 16];
     }
 
     pub fn disp_frequency(&self) -> String {
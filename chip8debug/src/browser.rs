@@ -0,0 +1,91 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+use ratatui::widgets::TableState;
+
+/// File extensions listed by the browser
+pub const ROM_EXTENSIONS: [&str; 4] = ["ch8", "c8", "sc8", "xo8"];
+
+pub struct RomEntry {
+    pub path: PathBuf,
+    pub name: String,
+    /// `None` for directories
+    pub size: Option<u64>,
+    /// SHA-1 of the file contents, in hex
+    pub hash: Option<String>,
+}
+
+impl RomEntry {
+    pub fn is_dir(&self) -> bool {
+        self.size.is_none()
+    }
+}
+
+/// Popup listing the subdirectories and ROM files of one directory.
+pub struct RomBrowser {
+    pub dir: PathBuf,
+    pub entries: Vec<RomEntry>,
+    pub state: TableState,
+}
+
+impl RomBrowser {
+    /// # Errors
+    ///
+    /// Fails if the directory cannot be listed.
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let dir = dir.canonicalize()?;
+
+        let mut entries = vec![];
+        if let Some(parent) = dir.parent() {
+            entries.push(RomEntry { path: parent.to_path_buf(), name: String::from(".."), size: None, hash: None });
+        }
+
+        let mut dirs = vec![];
+        let mut roms = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+
+            if path.is_dir() {
+                dirs.push(RomEntry { path, name: format!("{name}/"), size: None, hash: None });
+            } else if is_rom(&path) {
+                // unreadable files are still listed, so that loading them reports the error
+                let contents = fs::read(&path).ok();
+                roms.push(RomEntry {
+                    size: Some(contents.as_ref().map_or(0, |bytes| bytes.len() as u64)),
+                    hash: contents.map(|bytes| sha1_smol::Sha1::from(bytes).digest().to_string()),
+                    path,
+                    name,
+                });
+            }
+        }
+        dirs.sort_by(|a, b| a.name.cmp(&b.name));
+        roms.sort_by(|a, b| a.name.cmp(&b.name));
+        entries.extend(dirs);
+        entries.extend(roms);
+
+        let mut state = TableState::default();
+        state.select(Some(0));
+
+        Ok(Self { dir, entries, state })
+    }
+
+    pub fn select(&mut self, offset: isize) {
+        let last = self.entries.len().saturating_sub(1);
+        let selected = self.state.selected().unwrap_or_default().saturating_add_signed(offset);
+        self.state.select(Some(selected.min(last)));
+    }
+
+    pub fn selected(&self) -> Option<&RomEntry> {
+        self.entries.get(self.state.selected().unwrap_or_default())
+    }
+}
+
+fn is_rom(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ROM_EXTENSIONS.iter().any(|rom_ext| ext.eq_ignore_ascii_case(rom_ext)))
+}
@@ -0,0 +1,362 @@
+use std::{fs, io, path::{Path, PathBuf}, collections::BTreeMap, str::FromStr, time::Duration};
+
+use chip8exe::{frontend::{DEFAULT_KEYMAP, DEFAULT_KEY_RELEASE_TIMEOUT}, layout::MemoryLayout, phosphor::PhosphorMode, quirks::Quirks, stack::{self, StackConfig}, variant::Variant, vip::Vip};
+use clap::Parser;
+use ratatui::style::Color;
+use serde::Deserialize;
+
+use crate::display::RenderMode;
+
+/// Looked for in the working directory when no `--config` is given
+pub const DEFAULT_CONFIG_FILE: &str = "chip8debug.toml";
+
+//                                   0.5 Hz 1 Hz 5 Hz 10 Hz 100 Hz 1000 Hz 1 MHz
+pub const DEFAULT_SPEEDS: [f64; 7] = [0.5, 1.0, 5.0, 10.0, 100.0, 1000.0, 1_000_000.0];
+pub const DEFAULT_TIMER_RATE: f64 = 60.0;
+
+/// Terminal UI interactive debugger for the CHIP-8 emulator. Command line options override the configuration file.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// ROM file to load
+    pub rom: Option<PathBuf>,
+    /// Symbol file naming addresses in the ROM
+    pub symbols: Option<PathBuf>,
+    /// TOML configuration file [default: chip8debug.toml, if present]
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// Initial speed in instructions per second
+    #[arg(short, long, conflicts_with = "ipf")]
+    pub frequency: Option<f64>,
+    /// Initial speed in instructions per timer frame
+    #[arg(long)]
+    pub ipf: Option<u32>,
+    /// Quirk preset: default, vip, schip or xochip
+    #[arg(short, long)]
+    pub quirks: Option<String>,
+    /// Nested calls allowed before a CALL fails: 12 on the COSMAC VIP, 16 on SUPER-CHIP [default: 32]
+    #[arg(long, value_name = "DEPTH", value_parser = clap::value_parser!(u8).range(1..=i64::from(stack::MAX_DEPTH)))]
+    pub stack_depth: Option<u8>,
+    /// Keep return addresses in emulated memory below ECF, like the COSMAC VIP; holds at most 24 calls
+    #[arg(long)]
+    pub stack_in_memory: bool,
+    /// Instruction set: chip8, chip8x or chip8e [default: from the ROM database, or chip8]
+    #[arg(long)]
+    pub variant: Option<String>,
+    /// Address the ROM is loaded at, in hex; 600 for ETI-660 programs [default: 300 for CHIP-8X, otherwise 200]
+    #[arg(long, value_name = "ADDR", value_parser = parse_hex_address)]
+    pub load_address: Option<u16>,
+    /// Address the font is stored at, in hex; many interpreters use 050 [default: 000]
+    #[arg(long, value_name = "ADDR", value_parser = parse_hex_address)]
+    pub font_address: Option<u16>,
+    /// Address execution starts at, in hex [default: the load address]
+    #[arg(long, value_name = "ADDR", value_parser = parse_hex_address)]
+    pub initial_pc: Option<u16>,
+    /// Run 0NNN machine code subroutines on an emulated COSMAC VIP's 1802, instead of skipping them
+    #[arg(long)]
+    pub machine_code: bool,
+    /// CHIP-8 interpreter image, like the original VIP one, that the 1802 runs programs through [implies --machine-code]
+    #[arg(long, value_name = "FILE")]
+    pub vip_interpreter: Option<PathBuf>,
+    /// COSMAC VIP operating system ROM, mapped at 8000; the original interpreter draws its digits from it
+    #[arg(long, value_name = "FILE")]
+    pub vip_monitor: Option<PathBuf>,
+    /// Start running at the initial speed
+    #[arg(long, conflicts_with = "paused")]
+    pub run: bool,
+    /// Start paused
+    #[arg(long)]
+    pub paused: bool,
+    /// Breakpoint address or label; may be repeated
+    #[arg(short, long = "break", value_name = "ADDR")]
+    pub breakpoints: Vec<String>,
+    /// Milliseconds a key stays held on terminals that do not report key releases
+    #[arg(long, value_name = "MS")]
+    pub key_release_timeout: Option<u64>,
+    /// Directory holding platforms.json and programs.json of the CHIP-8 database, used instead of the built-in copy
+    #[arg(long, value_name = "DIR")]
+    pub rom_db: Option<PathBuf>,
+    /// Display rendering: full, half (half blocks) or braille, or auto for the largest that fits [default: auto]
+    #[arg(long, value_name = "MODE")]
+    pub display: Option<String>,
+    /// Reduce flicker by letting pixels fade by N per frame (decay:N) or stay lit for N frames (max:N), or off
+    #[arg(long, value_name = "MODE")]
+    pub phosphor: Option<String>,
+    /// Seed for the random number generator, making runs reproducible [default: random]
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Record the session's input and timing to this movie file, saved on reset or quit
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    /// Replay a movie recorded here or with chip8run, pausing at its end or at the first desync
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+    /// Rhai script to load at start; may be repeated
+    #[arg(short, long = "script", value_name = "FILE")]
+    pub scripts: Vec<PathBuf>,
+}
+
+/// Contents of the configuration file. Every setting is optional.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ConfigFile {
+    rom: Option<PathBuf>,
+    symbols: Option<PathBuf>,
+    frequency: Option<f64>,
+    ipf: Option<u32>,
+    /// Instructions per second for each step of the U/J speed controls
+    speeds: Option<Vec<f64>>,
+    /// Delay and sound timer frequency in Hz
+    timer_rate: Option<f64>,
+    quirks: Option<String>,
+    stack_depth: Option<u8>,
+    stack_in_memory: Option<bool>,
+    variant: Option<String>,
+    load_address: Option<u16>,
+    font_address: Option<u16>,
+    initial_pc: Option<u16>,
+    machine_code: Option<bool>,
+    vip_interpreter: Option<PathBuf>,
+    vip_monitor: Option<PathBuf>,
+    start_paused: Option<bool>,
+    breakpoints: Option<Vec<String>>,
+    key_release_timeout: Option<u64>,
+    rom_database: Option<PathBuf>,
+    display: Option<String>,
+    phosphor: Option<String>,
+    seed: Option<u64>,
+    scripts: Option<Vec<PathBuf>>,
+    /// CHIP-8 key (hex digit) to keyboard character
+    keys: BTreeMap<String, char>,
+    colors: ColorsFile,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ColorsFile {
+    on: Option<String>,
+    off: Option<String>,
+}
+
+/// Debugger settings after merging the configuration file with the command line.
+pub struct Settings {
+    pub rom: Option<PathBuf>,
+    pub symbols: Option<PathBuf>,
+    pub speeds: Vec<Duration>,
+    /// `None` leaves the choice to the ROM database, falling back to the fastest speed
+    pub initial_speed: Option<Duration>,
+    pub timer_rate: Duration,
+    /// `None` leaves the choice to the ROM database, falling back to the default quirks
+    pub quirks: Option<Quirks>,
+    pub stack: StackConfig,
+    /// `None` leaves the choice to the ROM database, falling back to plain CHIP-8
+    pub variant: Option<Variant>,
+    pub font_address: u16,
+    /// `None` loads programs where their variant does
+    pub load_address: Option<u16>,
+    /// `None` starts execution at the load address
+    pub initial_pc: Option<u16>,
+    /// `None` skips 0NNN machine code calls
+    pub vip: Option<Vip>,
+    pub start_paused: bool,
+    pub breakpoints: Vec<String>,
+    pub key_release_timeout: Duration,
+    pub keymap: [char; 16],
+    pub pixel_on: Color,
+    pub pixel_off: Color,
+    pub rom_database: Option<PathBuf>,
+    /// `None` picks the largest mode that fits the terminal
+    pub render_mode: Option<RenderMode>,
+    /// `None` shows the framebuffer as it is
+    pub phosphor: Option<PhosphorMode>,
+    /// `None` seeds the random number generator randomly
+    pub seed: Option<u64>,
+    /// Movie file to record to
+    pub record: Option<PathBuf>,
+    /// Movie file to replay
+    pub replay: Option<PathBuf>,
+    /// Rhai scripts to load at start
+    pub scripts: Vec<PathBuf>,
+}
+
+impl Settings {
+    /// Where the font and the ROM go, and where execution starts, for programs of `variant`.
+    pub fn layout(&self, variant: Variant) -> MemoryLayout {
+        let program_start = self.load_address.unwrap_or(variant.program_start());
+        MemoryLayout { font_address: self.font_address, program_start, initial_pc: self.initial_pc.unwrap_or(program_start) }
+    }
+
+    /// The configured initial speed, or the fastest listed one.
+    pub fn default_speed(&self) -> Duration {
+        self.initial_speed.unwrap_or(self.speeds[self.speeds.len() - 1])
+    }
+
+    /// # Errors
+    ///
+    /// Fails if the configuration file cannot be read or holds invalid settings.
+    pub fn load(cli: Cli) -> io::Result<Self> {
+        let file = match &cli.config {
+            Some(path) => read_config(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => read_config(Path::new(DEFAULT_CONFIG_FILE))?,
+            None => ConfigFile::default(),
+        };
+
+        let timer_hz = file.timer_rate.unwrap_or(DEFAULT_TIMER_RATE);
+        let speeds = file.speeds.unwrap_or_else(|| DEFAULT_SPEEDS.to_vec());
+        if speeds.is_empty() {
+            return Err(invalid("speeds must not be empty"));
+        }
+        let frequency = match (cli.frequency, cli.ipf) {
+            (Some(hz), _) => Some(hz),
+            (None, Some(ipf)) => Some(f64::from(ipf) * timer_hz),
+            (None, None) => file.frequency.or(file.ipf.map(|ipf| f64::from(ipf) * timer_hz)),
+        };
+
+        let quirks = match cli.quirks.or(file.quirks) {
+            Some(name) => Some(Quirks::preset(&name).ok_or_else(|| invalid(&format!("unknown quirk preset {name}, expected one of {}", Quirks::PRESET_NAMES.join(", "))))?),
+            None => None,
+        };
+
+        let stack_depth = cli.stack_depth.or(file.stack_depth).unwrap_or(stack::MAX_DEPTH);
+        if !(1..=stack::MAX_DEPTH).contains(&stack_depth) {
+            return Err(invalid(&format!("stack-depth must be between 1 and {}", stack::MAX_DEPTH)));
+        }
+        let stack = StackConfig { max_depth: stack_depth, in_memory: cli.stack_in_memory || file.stack_in_memory.unwrap_or(false) };
+
+        let variant = match cli.variant.or(file.variant) {
+            Some(name) => Some(Variant::from_name(&name).ok_or_else(|| invalid(&format!("unknown variant {name}, expected one of {}", Variant::NAMES.join(", "))))?),
+            None => None,
+        };
+
+        let interpreter = cli.vip_interpreter.or(file.vip_interpreter);
+        let monitor = cli.vip_monitor.or(file.vip_monitor);
+        let vip = match interpreter {
+            Some(path) => Some(Vip::with_interpreter(&fs::read(&path)?).map_err(|err| invalid(&format!("{}: {err}", path.display())))?),
+            None if cli.machine_code || file.machine_code.unwrap_or(false) || monitor.is_some() => Some(Vip::new()),
+            None => None,
+        };
+        let vip = match (vip, monitor) {
+            (Some(vip), Some(path)) => Some(vip.with_monitor(&fs::read(path)?)),
+            (vip, _) => vip,
+        };
+
+        let render_mode = match cli.display.or(file.display).as_deref() {
+            None | Some("auto") => None,
+            Some(name) => Some(RenderMode::from_name(name).ok_or_else(|| invalid(&format!("unknown display mode {name}, expected auto, {}", RenderMode::NAMES.join(", "))))?),
+        };
+
+        let phosphor = match cli.phosphor.or(file.phosphor).as_deref() {
+            None | Some("off") => None,
+            Some(mode) => Some(mode.parse::<PhosphorMode>().map_err(|err| invalid(&err.to_string()))?),
+        };
+
+        let mut keymap = DEFAULT_KEYMAP;
+        let mut rebound = [false; 16];
+        for (key, binding) in &file.keys {
+            let key = u8::from_str_radix(key, 16).ok().filter(|key| *key < 16).ok_or_else(|| invalid(&format!("{key} is not a CHIP-8 key")))?;
+            if rebound[usize::from(key)] {
+                return Err(invalid(&format!("CHIP-8 key {key:X} is bound more than once")));
+            }
+            // a character can only be bound to one key, so a default binding it takes over is cleared
+            if let Some(old) = keymap.iter().position(|c| c == binding) {
+                if rebound[old] {
+                    return Err(invalid(&format!("{binding:?} is bound to both CHIP-8 keys {old:X} and {key:X}")));
+                }
+                keymap[old] = '\0';
+            }
+            keymap[usize::from(key)] = *binding;
+            rebound[usize::from(key)] = true;
+        }
+
+        let settings = Self {
+            rom: cli.rom.or(file.rom),
+            symbols: cli.symbols.or(file.symbols),
+            speeds: speeds.iter().map(|hz| hz_to_duration(*hz)).collect::<io::Result<_>>()?,
+            initial_speed: frequency.map(hz_to_duration).transpose()?,
+            timer_rate: hz_to_duration(timer_hz)?,
+            quirks,
+            stack,
+            variant,
+            font_address: cli.font_address.or(file.font_address).unwrap_or(MemoryLayout::default().font_address),
+            load_address: cli.load_address.or(file.load_address),
+            initial_pc: cli.initial_pc.or(file.initial_pc),
+            vip,
+            start_paused: if cli.run { false } else { cli.paused || file.start_paused.unwrap_or(true) },
+            breakpoints: if cli.breakpoints.is_empty() { file.breakpoints.unwrap_or_default() } else { cli.breakpoints },
+            key_release_timeout: cli.key_release_timeout.or(file.key_release_timeout).map_or(DEFAULT_KEY_RELEASE_TIMEOUT, Duration::from_millis),
+            keymap,
+            pixel_on: parse_color(file.colors.on.as_deref())?,
+            pixel_off: parse_color(file.colors.off.as_deref())?,
+            rom_database: cli.rom_db.or(file.rom_database),
+            render_mode,
+            phosphor,
+            seed: cli.seed.or(file.seed),
+            record: cli.record,
+            replay: cli.replay,
+            scripts: if cli.scripts.is_empty() { file.scripts.unwrap_or_default() } else { cli.scripts },
+        };
+        // the variants' own load addresses all fit, so checking one checks the configured addresses
+        settings.layout(Variant::default()).validate().map_err(|err| invalid(&err.to_string()))?;
+        Ok(settings)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            rom: None,
+            symbols: None,
+            speeds: DEFAULT_SPEEDS.iter().map(|hz| Duration::from_secs_f64(1.0 / hz)).collect(),
+            initial_speed: None,
+            timer_rate: Duration::from_secs_f64(1.0 / DEFAULT_TIMER_RATE),
+            quirks: None,
+            stack: StackConfig::default(),
+            variant: None,
+            font_address: MemoryLayout::default().font_address,
+            load_address: None,
+            initial_pc: None,
+            vip: None,
+            start_paused: true,
+            breakpoints: vec![],
+            key_release_timeout: DEFAULT_KEY_RELEASE_TIMEOUT,
+            keymap: DEFAULT_KEYMAP,
+            pixel_on: Color::Reset,
+            pixel_off: Color::Reset,
+            rom_database: None,
+            render_mode: None,
+            phosphor: None,
+            seed: None,
+            record: None,
+            replay: None,
+            scripts: vec![],
+        }
+    }
+}
+
+fn read_config(path: &Path) -> io::Result<ConfigFile> {
+    let text = fs::read_to_string(path)?;
+    toml::from_str(&text).map_err(|err| invalid(&format!("{}: {err}", path.display())))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn hz_to_duration(hz: f64) -> io::Result<Duration> {
+    // frequencies so low the period overflows a Duration, or so high it rounds to nothing, are refused too
+    Duration::try_from_secs_f64(1.0 / hz)
+        .ok()
+        .filter(|period| hz.is_finite() && !period.is_zero())
+        .ok_or_else(|| invalid(&format!("{hz:?} is not a valid frequency")))
+}
+
+fn parse_color(color: Option<&str>) -> io::Result<Color> {
+    color.map_or(Ok(Color::Reset), |color| Color::from_str(color).map_err(|_| invalid(&format!("unknown color {color}"))))
+}
+
+fn parse_hex_address(text: &str) -> Result<u16, String> {
+    u16::from_str_radix(text.trim_start_matches("0x").trim_start_matches("0X"), 16)
+        .ok()
+        .filter(|addr| *addr < 0x1000)
+        .ok_or_else(|| format!("{text} is not an address between 0 and FFF"))
+}
@@ -0,0 +1,191 @@
+use std::mem;
+
+use chip8exe::{phosphor, variant::color};
+use ratatui::{style::{Color, Style}, text::{Line, Span}};
+
+/// How many pixels the display pane packs into each terminal cell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    /// One pixel per cell
+    Full,
+    /// Two pixels per cell, one above the other, drawn with `▀`, `▄` and `█`
+    HalfBlock,
+    /// Two by four pixels per cell, drawn as braille dots
+    Braille,
+}
+
+impl RenderMode {
+    /// From the largest to the most compact
+    pub const ALL: [Self; 3] = [Self::Full, Self::HalfBlock, Self::Braille];
+    pub const NAMES: [&'static str; 3] = ["full", "half", "braille"];
+
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter().position(|known| *known == name).map(|i| Self::ALL[i])
+    }
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+
+    /// Cells taken by a `width` by `height` pixel display, as columns and rows
+    #[must_use]
+    pub fn size(self, width: u16, height: u16) -> (u16, u16) {
+        match self {
+            Self::Full => (width, height),
+            Self::HalfBlock => (width, height.div_ceil(2)),
+            Self::Braille => (width.div_ceil(2), height.div_ceil(4)),
+        }
+    }
+
+    /// The largest mode that shows a `width` by `height` pixel display in `columns` by `rows` cells, or the most compact if none does.
+    #[must_use]
+    pub fn largest_fitting(width: u16, height: u16, columns: u16, rows: u16) -> Self {
+        Self::ALL.into_iter()
+            .find(|mode| {
+                let (needed_columns, needed_rows) = mode.size(width, height);
+                needed_columns <= columns && needed_rows <= rows
+            })
+            .unwrap_or(Self::Braille)
+    }
+
+    /// Draws a `width` by `height` pixel display, where `intensity` gives the brightness of the pixel at x, y, up to `phosphor::FULL`,
+    /// and `on` its color. Full mode shades fading pixels; the compact modes show them lit until they fade below a quarter, in
+    /// the color of the top left pixel of their cell.
+    pub fn render(self, width: u16, height: u16, intensity: impl Fn(u16, u16) -> u8, on: impl Fn(u16, u16) -> Color, off: Color) -> Vec<Line<'static>> {
+        let intensity = |x: u16, y: u16| if x < width && y < height { intensity(x, y) } else { 0 };
+        let lit = |x: u16, y: u16| intensity(x, y) >= phosphor::FULL / 4;
+        let (columns, rows) = self.size(width, height);
+
+        (0..rows).map(|row| {
+            // cells are grouped into a span per run of one color
+            let mut spans = vec![];
+            let mut text = String::new();
+            let mut color = None;
+            for column in 0..columns {
+                let (x, y) = self.origin(column, row);
+                let cell_color = on(x.min(width - 1), y.min(height - 1));
+                if let Some(color) = color.filter(|color| *color != cell_color) {
+                    spans.push(Span::styled(mem::take(&mut text), Style::default().fg(color)));
+                }
+                color = Some(cell_color);
+                text.push(match self {
+                    Self::Full => shade(intensity(column, row)),
+                    _ => self.cell(column, row, lit),
+                });
+            }
+            if let Some(color) = color {
+                spans.push(Span::styled(text, Style::default().fg(color)));
+            }
+            Line::from(spans).style(Style::default().bg(off))
+        }).collect()
+    }
+
+    /// The top left pixel of a cell
+    fn origin(self, column: u16, row: u16) -> (u16, u16) {
+        match self {
+            Self::Full => (column, row),
+            Self::HalfBlock => (column, 2 * row),
+            Self::Braille => (2 * column, 4 * row),
+        }
+    }
+
+    fn cell(self, column: u16, row: u16, lit: impl Fn(u16, u16) -> bool) -> char {
+        // braille dot bits for each pixel of a cell, by x then y
+        const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+        match self {
+            Self::Full => if lit(column, row) { '█' } else { ' ' },
+            Self::HalfBlock => match (lit(column, 2 * row), lit(column, 2 * row + 1)) {
+                (false, false) => ' ',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (true, true) => '█',
+            },
+            Self::Braille => {
+                let mut dots = 0;
+                for (dx, column_dots) in (0..).zip(DOTS) {
+                    for (dy, dot) in (0..).zip(column_dots) {
+                        if lit(2 * column + dx, 4 * row + dy) {
+                            dots |= dot;
+                        }
+                    }
+                }
+                // a blank braille pattern is not a space to every font
+                if dots == 0 { ' ' } else { char::from_u32(0x2800 + dots).unwrap_or(' ') }
+            },
+        }
+    }
+}
+
+/// The terminal color closest to a CHIP-8X color
+#[must_use]
+pub fn chip8x_color(chip8x: u8) -> Color {
+    match chip8x {
+        color::BLACK => Color::Black,
+        color::RED => Color::Red,
+        color::BLUE => Color::Blue,
+        color::MAGENTA => Color::Magenta,
+        color::GREEN => Color::Green,
+        color::YELLOW => Color::Yellow,
+        color::CYAN => Color::Cyan,
+        _ => Color::White,
+    }
+}
+
+/// A full block for a lit pixel, lighter shades as it fades, and a space once dark
+fn shade(intensity: u8) -> char {
+    match intensity {
+        0 => ' ',
+        1..=63 => '░',
+        64..=127 => '▒',
+        phosphor::FULL => '█',
+        _ => '▓',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders a display with only the pixels in `lit` on, as text lines
+    fn render(mode: RenderMode, width: u16, height: u16, lit: &[(u16, u16)]) -> Vec<String> {
+        let lines = mode.render(width, height, |x, y| if lit.contains(&(x, y)) { phosphor::FULL } else { 0 }, |_, _| Color::White, Color::Black);
+        lines.iter().map(|line| line.spans.iter().map(|span| span.content.as_ref()).collect()).collect()
+    }
+
+    #[test]
+    fn test_largest_fitting() {
+        assert_eq!(RenderMode::largest_fitting(64, 32, 80, 32), RenderMode::Full);
+        assert_eq!(RenderMode::largest_fitting(64, 32, 64, 31), RenderMode::HalfBlock);
+        assert_eq!(RenderMode::largest_fitting(64, 32, 63, 16), RenderMode::Braille);
+        assert_eq!(RenderMode::largest_fitting(64, 32, 10, 5), RenderMode::Braille);
+
+        assert_eq!(RenderMode::largest_fitting(128, 64, 128, 64), RenderMode::Full);
+        assert_eq!(RenderMode::largest_fitting(128, 64, 128, 32), RenderMode::HalfBlock);
+        // a screen that shows 64x32 in full only shows 128x64 as braille
+        assert_eq!(RenderMode::largest_fitting(128, 64, 64, 32), RenderMode::Braille);
+        assert_eq!(RenderMode::Braille.size(128, 64), (64, 16));
+    }
+
+    #[test]
+    fn test_braille_dots() {
+        let dots = [(0, 0, 0x01), (0, 1, 0x02), (0, 2, 0x04), (0, 3, 0x40), (1, 0, 0x08), (1, 1, 0x10), (1, 2, 0x20), (1, 3, 0x80)];
+        for (x, y, dot) in dots {
+            assert_eq!(render(RenderMode::Braille, 2, 4, &[(x, y)]), [char::from_u32(0x2800 + dot).unwrap().to_string()], "{x}, {y}");
+        }
+
+        let all: Vec<_> = dots.iter().map(|(x, y, _)| (*x, *y)).collect();
+        assert_eq!(render(RenderMode::Braille, 2, 4, &all), ["⣿"]);
+        assert_eq!(render(RenderMode::Braille, 4, 4, &[(2, 0)]), [" ⠁"]);
+        assert_eq!(render(RenderMode::Braille, 2, 4, &[]), [" "]);
+    }
+
+    #[test]
+    fn test_half_blocks() {
+        assert_eq!(render(RenderMode::HalfBlock, 4, 2, &[(0, 0), (1, 1), (2, 0), (2, 1)]), ["▀▄█ "]);
+        // an odd row count leaves the bottom half of the last row dark
+        assert_eq!(render(RenderMode::HalfBlock, 1, 3, &[(0, 2)]), [" ", "▀"]);
+    }
+}
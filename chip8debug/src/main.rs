@@ -1,26 +1,51 @@
 mod app;
+mod browser;
+mod config;
+mod display;
+mod script;
 mod ui;
 
-use std::{io, time::{Duration, UNIX_EPOCH, SystemTime, SystemTimeError}, env, panic, any::Any};
+use std::{io, time::{Duration, UNIX_EPOCH, SystemTime, SystemTimeError}, panic::{self, AssertUnwindSafe}, process};
 
-use app::{App, Failure, TIMER_RATE};
-use crossterm::{self, terminal::{enable_raw_mode, EnterAlternateScreen, disable_raw_mode, LeaveAlternateScreen}, execute, event::{EnableMouseCapture, DisableMouseCapture, Event, KeyCode, KeyModifiers, KeyEventKind, KeyEvent}};
+use app::{App, Failure};
+use chip8exe::frontend::panic_message;
+use clap::Parser;
+use config::{Cli, Settings};
+use crossterm::{self, terminal::{enable_raw_mode, EnterAlternateScreen, disable_raw_mode, LeaveAlternateScreen, supports_keyboard_enhancement}, execute, event::{EnableMouseCapture, DisableMouseCapture, Event, KeyCode, KeyModifiers, KeyEventKind, KeyEvent, KeyboardEnhancementFlags, PushKeyboardEnhancementFlags, PopKeyboardEnhancementFlags}};
 use ratatui::{backend::{CrosstermBackend, Backend}, Terminal};
 
 fn main() -> io::Result<()> {
+    // settings errors are reported before the terminal is taken over
+    let settings = match Settings::load(Cli::parse()) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("chip8debug: {err}");
+            process::exit(2);
+        },
+    };
+
     // set up terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    // key release events are only reported by terminals supporting the kitty keyboard protocol
+    let key_release_events = supports_keyboard_enhancement().unwrap_or(false);
+    if key_release_events {
+        execute!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES | KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+    }
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let app = App::new(None);
+    let mut app = App::new(settings);
+    app.key_release_events = key_release_events;
 
     let res = run_app(&mut terminal, app);
 
     // restore terminal
     disable_raw_mode()?;
+    if key_release_events {
+        execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
+    }
     execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture)?;
     terminal.show_cursor()?;
     
@@ -31,17 +56,8 @@ fn main() -> io::Result<()> {
     res
 }
 
-fn load_rom_cmdl(app: &mut App) -> io::Result<()> {
-    // check command line for rom file
-    let args: Vec<String> = env::args().collect();
-    if let Some(arg) = args.get(1) {
-        app.load_program(arg)?;
-    }
-    Ok(())
-}
-
 fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<()> {
-    load_rom_cmdl(&mut app)?;
+    app.start()?;
 
     let mut last_refresh = SystemTime::now();
     loop {
@@ -55,14 +71,20 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<(
             .checked_sub(last_refresh.elapsed().unwrap_or_default())
             .unwrap_or_else(|| Duration::from_secs(0));
 
+        app.release_expired_keys();
+
         if crossterm::event::poll(timeout)? {
             if let Event::Key(key) = crossterm::event::read()? {
-                if key.modifiers.contains(KeyModifiers::CONTROL) && key.kind == KeyEventKind::Press {
+                if app.prompt.is_some() {
+                    process_prompt_input(key, &mut app);
+                } else if app.browser.is_some() {
+                    process_browser_input(key, &mut app);
+                } else if key.modifiers.contains(KeyModifiers::CONTROL) && key.kind == KeyEventKind::Press {
                     match key.code {
                         KeyCode::Char('q') => app.should_quit = true,
                         KeyCode::Char('r') => {
                             app.reset();
-                            load_rom_cmdl(&mut app)?;
+                            app.reload_rom()?;
                         },
                         _ => {},
                     }
@@ -78,10 +100,22 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<(
                         },
                         KeyCode::Char('n') => {
                             let this_tick = SystemTime::now();
-                            app = try_tick(app, last_refresh, this_tick)?;
+                            try_tick(&mut app, last_refresh, this_tick)?;
                             last_refresh = this_tick;
                         }
-                        KeyCode::Char('m') => app.mem_row_sel_override = None,
+                        KeyCode::Char(':') => app.prompt = Some(String::new()),
+                        KeyCode::Char('o') => app.open_browser(),
+                        KeyCode::Char('h') => app.toggle_profiler(),
+                        KeyCode::Char('k') => app.toggle_coverage(),
+                        KeyCode::Char('l') => app.cycle_render_mode(),
+                        KeyCode::Char('t') => app.profile_view = app.profile_view.next(),
+                        KeyCode::Char('m') => {
+                            app.mem_row_sel_override = None;
+                            app.disasm_addr_override = None;
+                        },
+                        KeyCode::Char('[') => app.select_frame(-1),
+                        KeyCode::Char(']') => app.select_frame(1),
+                        KeyCode::Char('g') => app.goto_selected_frame(),
                         KeyCode::Char('u') => app.inc_tick_rate(),
                         KeyCode::Char('j') => app.dec_tick_rate(),
                         KeyCode::Char('p') => app.pause_tick(),
@@ -92,82 +126,135 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<(
         }
 
         if !app.is_paused() && last_refresh.elapsed().unwrap_or_default() >= app.get_tick_rate() {
-            app = multi_tick(app, last_refresh)?;
+            multi_tick(&mut app, last_refresh)?;
             last_refresh = SystemTime::now();
         }
 
         if app.should_quit {
+            app.stop_recording();
             return Ok(());
         }
     }
 }
 
 fn process_chip8_input(key: KeyEvent, app: &mut App) -> bool {
-    if key.kind != KeyEventKind::Press {
+    let KeyCode::Char(c) = key.code else {
         return false
-    }
-    
-    let bitval = match key.code {
-        KeyCode::Char('1') => 2,    // 1
-        KeyCode::Char('2') => 4,    // 2
-        KeyCode::Char('3') => 8,    // 3
-        KeyCode::Char('4') => 4096, // C
-        KeyCode::Char('q') => 16,   // 4
-        KeyCode::Char('w') => 32,   // 5
-        KeyCode::Char('e') => 64,   // 6
-        KeyCode::Char('r') => 8192, // D
-        KeyCode::Char('a') => 128,  // 7
-        KeyCode::Char('s') => 256,  // 8
-        KeyCode::Char('d') => 512,  // 9
-        KeyCode::Char('f') => 16384,// E
-        KeyCode::Char('z') => 1024, // A
-        KeyCode::Char('x') => 1,    // 0
-        KeyCode::Char('c') => 2048, // B
-        KeyCode::Char('v') => 32768,// F
-        _ => return false
     };
+    let Some(chip8_key) = app.settings.keymap.iter().position(|bound| *bound == c) else {
+        return false
+    };
+    #[allow(clippy::cast_possible_truncation)]
+    let chip8_key = chip8_key as u8;
 
-    app.chip_state.input ^= bitval;
+    match key.kind {
+        KeyEventKind::Press | KeyEventKind::Repeat => app.press_key(chip8_key),
+        KeyEventKind::Release => app.release_key(chip8_key),
+    }
 
     true
 }
 
-fn try_tick(app: App, last_tick: SystemTime, this_tick: SystemTime) -> io::Result<App> {
-    let timer_ticks = ticks_between(TIMER_RATE, last_tick, this_tick).unwrap_or_default();
+fn process_prompt_input(key: KeyEvent, app: &mut App) {
+    if key.kind != KeyEventKind::Press {
+        return
+    }
+
+    match key.code {
+        KeyCode::Enter => {
+            if let Some(command) = app.prompt.take() {
+                app.run_command(&command);
+            }
+        },
+        KeyCode::Esc => app.prompt = None,
+        KeyCode::Backspace => {
+            if let Some(prompt) = &mut app.prompt {
+                prompt.pop();
+            }
+        },
+        KeyCode::Char(c) => {
+            if let Some(prompt) = &mut app.prompt {
+                prompt.push(c);
+            }
+        },
+        _ => {},
+    }
+}
+
+fn process_browser_input(key: KeyEvent, app: &mut App) {
+    if key.kind != KeyEventKind::Press {
+        return
+    }
+
+    match key.code {
+        KeyCode::Enter => app.browser_choose(),
+        KeyCode::Esc => app.browser = None,
+        KeyCode::Up | KeyCode::Down => {
+            if let Some(browser) = &mut app.browser {
+                browser.select(if key.code == KeyCode::Up { -1 } else { 1 });
+            }
+        },
+        KeyCode::Backspace => {
+            if let Some(browser) = &mut app.browser {
+                // ".." is listed first whenever there is a parent directory
+                if browser.entries.first().is_some_and(|entry| entry.name == "..") {
+                    browser.state.select(Some(0));
+                    app.browser_choose();
+                }
+            }
+        },
+        _ => {},
+    }
+}
+
+fn try_tick(app: &mut App, last_tick: SystemTime, this_tick: SystemTime) -> io::Result<()> {
+    let timer_ticks = ticks_between(app.settings.timer_rate.as_nanos(), last_tick, this_tick).unwrap_or_default();
 
     let old_hook = panic::take_hook();
     panic::set_hook(Box::new(|_| {
         // do nothing
     }));
     let last_instr_count = app.instr_count;
-    let app = panic::catch_unwind(|| Ok(app.on_tick(timer_ticks))).unwrap_or_else(|panic| {
-        let mut new_app = App::new(Some(Failure { panic_message: format!("{:?}", display_caught_panic(&panic)), last_instr_count }));
-        load_rom_cmdl(&mut new_app)?;
-        Ok(new_app)
-    });
+    let result = panic::catch_unwind(AssertUnwindSafe(|| app.on_tick(timer_ticks)));
     panic::set_hook(old_hook);
 
-    app
+    if let Err(panic) = result {
+        // the debugger state (symbols, breakpoints) survives; the emulation starts over
+        app.reset();
+        app.last_failure = Some(Failure { panic_message: format!("{:?}", panic_message(&*panic)), last_instr_count });
+        app.reload_rom()?;
+    } else if let Some(err) = app.chip_state.stack_error {
+        // unlike a crash, the state is intact, so it stays on the failing instruction for inspection
+        app.pause_tick();
+        app.last_failure = Some(Failure { panic_message: err.to_string(), last_instr_count });
+    }
+
+    Ok(())
 }
 
-fn multi_tick(mut app: App, last_multi: SystemTime) -> io::Result<App> {
+fn multi_tick(app: &mut App, last_multi: SystemTime) -> io::Result<()> {
     let cpu_ticks = ticks_between(app.get_tick_rate().as_nanos(), last_multi, SystemTime::now()).unwrap_or(1);
 
     let mut last_tick = last_multi;
 
     for _ in 0..cpu_ticks {
         let this_tick = SystemTime::now();
-        app = try_tick(app, last_tick, this_tick)?;
+        try_tick(app, last_tick, this_tick)?;
+
+        if app.at_breakpoint() {
+            app.pause_tick();
+            app.message = Some(format!("Hit breakpoint at {}", app.describe_addr(app.chip_state.pc)));
+        }
 
         if app.is_paused() {
-            // Indicates a crash leading to the emulation being reset and paused
+            // Indicates a crash leading to the emulation being reset and paused, a stack error, or a breakpoint
             break;
         }
 
         last_tick = this_tick;
     }
 
-    Ok(app)
+    Ok(())
 }
 
 fn ticks_between(duration_nanos: u128, last_tick: SystemTime, this_tick: SystemTime) -> Result<u32, SystemTimeError> {
@@ -179,13 +266,3 @@ fn ticks_between(duration_nanos: u128, last_tick: SystemTime, this_tick: SystemT
 
     Ok(u32::try_from(this_pos.saturating_sub(last_pos)).unwrap_or_default())
 }
-
-fn display_caught_panic(panic: &Box<dyn Any + Send>) -> String {
-    if let Some(msg) = panic.downcast_ref::<&'static str>() {
-        String::from(*msg)
-    } else if let Some(msg) = panic.downcast_ref::<String>() {
-        String::from(msg)
-    } else {
-        String::from("Unknown Error")
-    }
-}
\ No newline at end of file
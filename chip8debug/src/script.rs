@@ -0,0 +1,297 @@
+use std::{cell::{Ref, RefCell}, collections::{HashMap, VecDeque}, fs, mem, path::Path, rc::Rc};
+
+use chip8exe::{Chip8State, opcode::Opcode};
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, FuncArgs, AST};
+
+/// Lines of script output kept for the output panel
+const MAX_OUTPUT_LINES: usize = 200;
+/// Operations a script's top level, or one callback, may run before it is stopped, so an endless loop cannot freeze the debugger
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// A callback, with the script it was registered by
+type Hook = (usize, FnPtr);
+
+/// Callbacks registered by the loaded scripts
+#[derive(Default)]
+struct Hooks {
+    frame: Vec<Hook>,
+    pc: HashMap<u16, Vec<Hook>>,
+    draw: Vec<Hook>,
+    sound: Vec<Hook>,
+}
+
+/// What scripts share with the functions they call
+struct Shared {
+    /// The emulator, swapped in from the debugger while a script runs
+    state: Chip8State,
+    output: VecDeque<String>,
+    hooks: Hooks,
+    /// The script running, which callbacks are registered for
+    script: usize,
+    /// Set when a script asks the debugger to pause, with the reason given
+    pause: Option<String>,
+    /// 60 Hz frames since the scripts were first loaded
+    frames: i64,
+    /// Set while a movie is recorded or replayed, which changes made behind its back would desync
+    movie: bool,
+}
+
+impl Shared {
+    fn print(&mut self, line: String) {
+        if self.output.len() == MAX_OUTPUT_LINES {
+            self.output.pop_front();
+        }
+        self.output.push_back(line);
+    }
+
+    /// The emulator, for a script function about to change it
+    fn state_mut(&mut self) -> ScriptResult<&mut Chip8State> {
+        if self.movie {
+            return Err("the machine cannot be changed while a movie is recorded or replayed".into());
+        }
+        Ok(&mut self.state)
+    }
+}
+
+/// What happened in an instruction that scripts may want to hear about, noted before it ran
+pub struct TickWatch {
+    /// Where a `DRW` draws, and how many rows
+    draw: Option<(u8, u8, u8)>,
+    sound_timer: u8,
+}
+
+/// Rhai scripts driving the debugger: they can read and change the machine, hold keys, pause, and register callbacks run
+/// each frame, when execution reaches an address, after each draw and when a sound starts. While a movie is recorded or
+/// replayed they can still hold keys, which the movie records, but not change the machine.
+pub struct Scripts {
+    engine: Engine,
+    /// Name and compiled code of each loaded script
+    scripts: Vec<(String, AST)>,
+    shared: Rc<RefCell<Shared>>,
+}
+
+impl Scripts {
+    pub fn new() -> Self {
+        let shared = Rc::new(RefCell::new(Shared {
+            state: Chip8State::default(),
+            output: VecDeque::new(),
+            hooks: Hooks::default(),
+            script: 0,
+            pause: None,
+            frames: 0,
+            movie: false,
+        }));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+
+        let out = shared.clone();
+        engine.on_print(move |text| out.borrow_mut().print(String::from(text)));
+        let out = shared.clone();
+        engine.on_debug(move |text, _, pos| out.borrow_mut().print(format!("{pos:?}: {text}")));
+
+        let s = shared.clone();
+        engine.register_fn("reg", move |x: i64| -> ScriptResult<i64> { Ok(i64::from(s.borrow().state.registers[register(x)?])) });
+        let s = shared.clone();
+        engine.register_fn("set_reg", move |x: i64, value: i64| -> ScriptResult<()> {
+            s.borrow_mut().state_mut()?.registers[register(x)?] = byte(value)?;
+            Ok(())
+        });
+        let s = shared.clone();
+        engine.register_fn("index", move || i64::from(s.borrow().state.index));
+        let s = shared.clone();
+        engine.register_fn("set_index", move |value: i64| -> ScriptResult<()> {
+            s.borrow_mut().state_mut()?.index = u16::try_from(value).map_err(|_| format!("{value} is not a 16-bit value"))? & 0x0FFF;
+            Ok(())
+        });
+        let s = shared.clone();
+        engine.register_fn("pc", move || i64::from(s.borrow().state.pc));
+        let s = shared.clone();
+        engine.register_fn("set_pc", move |addr: i64| -> ScriptResult<()> {
+            s.borrow_mut().state_mut()?.pc = address(addr)?;
+            Ok(())
+        });
+        let s = shared.clone();
+        engine.register_fn("dt", move || i64::from(s.borrow().state.dt));
+        let s = shared.clone();
+        engine.register_fn("set_dt", move |value: i64| -> ScriptResult<()> {
+            s.borrow_mut().state_mut()?.dt = byte(value)?;
+            Ok(())
+        });
+        let s = shared.clone();
+        engine.register_fn("st", move || i64::from(s.borrow().state.st));
+        let s = shared.clone();
+        engine.register_fn("set_st", move |value: i64| -> ScriptResult<()> {
+            s.borrow_mut().state_mut()?.st = byte(value)?;
+            Ok(())
+        });
+        let s = shared.clone();
+        engine.register_fn("peek", move |addr: i64| -> ScriptResult<i64> { Ok(i64::from(s.borrow().state.memory[usize::from(address(addr)?)])) });
+        let s = shared.clone();
+        engine.register_fn("poke", move |addr: i64, value: i64| -> ScriptResult<()> {
+            s.borrow_mut().state_mut()?.memory[usize::from(address(addr)?)] = byte(value)?;
+            Ok(())
+        });
+        let s = shared.clone();
+        engine.register_fn("keys", move || i64::from(s.borrow().state.input));
+        let s = shared.clone();
+        engine.register_fn("press", move |key: i64| -> ScriptResult<()> {
+            s.borrow_mut().state.input |= 1 << chip8_key(key)?;
+            Ok(())
+        });
+        let s = shared.clone();
+        engine.register_fn("release", move |key: i64| -> ScriptResult<()> {
+            s.borrow_mut().state.input &= !(1 << chip8_key(key)?);
+            Ok(())
+        });
+        let s = shared.clone();
+        engine.register_fn("frames", move || s.borrow().frames);
+        let s = shared.clone();
+        engine.register_fn("pause", move || s.borrow_mut().pause = Some(String::from("Paused by a script")));
+        let s = shared.clone();
+        engine.register_fn("pause", move |reason: &str| s.borrow_mut().pause = Some(format!("Paused by a script: {reason}")));
+
+        let s = shared.clone();
+        engine.register_fn("on_frame", move |f: FnPtr| {
+            let mut shared = s.borrow_mut();
+            let hook = (shared.script, f);
+            shared.hooks.frame.push(hook);
+        });
+        let s = shared.clone();
+        engine.register_fn("on_pc", move |addr: i64, f: FnPtr| -> ScriptResult<()> {
+            let addr = address(addr)?;
+            let mut shared = s.borrow_mut();
+            let hook = (shared.script, f);
+            shared.hooks.pc.entry(addr).or_default().push(hook);
+            Ok(())
+        });
+        let s = shared.clone();
+        engine.register_fn("on_draw", move |f: FnPtr| {
+            let mut shared = s.borrow_mut();
+            let hook = (shared.script, f);
+            shared.hooks.draw.push(hook);
+        });
+        let s = shared.clone();
+        engine.register_fn("on_sound", move |f: FnPtr| {
+            let mut shared = s.borrow_mut();
+            let hook = (shared.script, f);
+            shared.hooks.sound.push(hook);
+        });
+
+        Self { engine, scripts: vec![], shared }
+    }
+
+    /// Compiles a script and runs its top level, which usually registers callbacks.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read, or the script does not compile or fails while running.
+    pub fn load(&mut self, path: &Path, state: &mut Chip8State) -> Result<(), String> {
+        let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let ast = self.engine.compile(text).map_err(|err| err.to_string())?;
+
+        self.scripts.push((name, ast));
+        let script = self.scripts.len() - 1;
+        self.shared.borrow_mut().script = script;
+        self.swap_state(state);
+        let result = self.engine.run_ast(&self.scripts[script].1);
+        self.swap_state(state);
+        result.map_err(|err| err.to_string())
+    }
+
+    /// Notes what the instruction about to run at the program counter does.
+    pub fn before_tick(&self, state: &Chip8State) -> TickWatch {
+        let draw = match state.decode_opcode() {
+            Opcode::DRW(x, y, rows) => Some((state.registers[x as usize], state.registers[y as usize], rows)),
+            _ => None,
+        };
+        TickWatch { draw, sound_timer: state.st }
+    }
+
+    /// Runs the callbacks for what happened in the instruction just run: frames passing, a draw, a sound starting, and
+    /// execution reaching a watched address.
+    pub fn after_tick(&mut self, state: &mut Chip8State, watch: &TickWatch, timer_ticks: u32) {
+        let mut shared = self.shared.borrow_mut();
+        let hooks = &shared.hooks;
+        let mut calls: Vec<(Hook, Vec<Dynamic>)> = vec![];
+        if timer_ticks > 0 {
+            let frames = shared.frames + i64::from(timer_ticks);
+            calls.extend(hooks.frame.iter().map(|hook| (hook.clone(), vec![Dynamic::from(frames)])));
+            shared.frames = frames;
+        }
+        let hooks = &shared.hooks;
+        if let Some((x, y, rows)) = watch.draw {
+            let args = vec![Dynamic::from(i64::from(x)), Dynamic::from(i64::from(y)), Dynamic::from(i64::from(rows))];
+            calls.extend(hooks.draw.iter().map(|hook| (hook.clone(), args.clone())));
+        }
+        if watch.sound_timer == 0 && state.st > 0 {
+            calls.extend(hooks.sound.iter().map(|hook| (hook.clone(), vec![Dynamic::from(i64::from(state.st))])));
+        }
+        if let Some(pc_hooks) = hooks.pc.get(&state.pc) {
+            calls.extend(pc_hooks.iter().map(|hook| (hook.clone(), vec![Dynamic::from(i64::from(state.pc))])));
+        }
+        drop(shared);
+
+        if calls.is_empty() {
+            return;
+        }
+        self.swap_state(state);
+        for ((script, f), args) in calls {
+            self.shared.borrow_mut().script = script;
+            let (name, ast) = &self.scripts[script];
+            if let Err(err) = f.call::<Dynamic>(&self.engine, ast, Args(args)) {
+                let mut shared = self.shared.borrow_mut();
+                shared.print(format!("{name}: {err}"));
+                shared.pause = Some(format!("Script error in {name}: {err}"));
+            }
+        }
+        self.swap_state(state);
+    }
+
+    /// Tells scripts whether a movie is being recorded or replayed, which keeps them from changing the machine.
+    pub fn set_movie(&mut self, movie: bool) {
+        self.shared.borrow_mut().movie = movie;
+    }
+
+    /// The reason a script asked the debugger to pause, once.
+    pub fn take_pause(&mut self) -> Option<String> {
+        self.shared.borrow_mut().pause.take()
+    }
+
+    /// Printed lines, oldest first
+    pub fn output(&self) -> Ref<'_, VecDeque<String>> {
+        Ref::map(self.shared.borrow(), |shared| &shared.output)
+    }
+
+    /// Puts the debugger's emulator where script functions reach it, or back.
+    fn swap_state(&self, state: &mut Chip8State) {
+        mem::swap(state, &mut self.shared.borrow_mut().state);
+    }
+}
+
+/// Callback arguments, which differ in number between callbacks
+struct Args(Vec<Dynamic>);
+
+impl FuncArgs for Args {
+    fn parse<C: Extend<Dynamic>>(self, args: &mut C) {
+        args.extend(self.0);
+    }
+}
+
+fn register(x: i64) -> ScriptResult<usize> {
+    usize::try_from(x).ok().filter(|x| *x < 16).ok_or_else(|| format!("V{x} is not a register").into())
+}
+
+fn byte(value: i64) -> ScriptResult<u8> {
+    u8::try_from(value).map_err(|_| format!("{value} is not a byte").into())
+}
+
+fn address(addr: i64) -> ScriptResult<u16> {
+    u16::try_from(addr).ok().filter(|addr| *addr < 0x1000).ok_or_else(|| format!("{addr} is not an address between 0 and 0xFFF").into())
+}
+
+fn chip8_key(key: i64) -> ScriptResult<u16> {
+    u16::try_from(key).ok().filter(|key| *key < 16).ok_or_else(|| format!("{key} is not a CHIP-8 key").into())
+}
@@ -1,16 +1,51 @@
-use chip8exe::{Reg, Chip8State};
-use ratatui::{Frame, layout::{Layout, Constraint, Rect, Direction, Alignment}, widgets::{Block, Borders, Row, Cell, Table, BorderType, Paragraph, ListItem, List}, text::{Span, Line}, style::{Style, Modifier, Color}};
+use chip8exe::{Reg, Chip8State, coverage, opcode::Opcode, symbols::Symbols, variant::Variant};
+use ratatui::{Frame, layout::{Layout, Constraint, Rect, Direction, Alignment}, widgets::{Block, Borders, Row, Cell, Table, BorderType, Paragraph, ListItem, List, Clear}, text::{Span, Line}, style::{Style, Modifier, Color}};
 
-use crate::app::App;
+use crate::{app::{App, CallFrame, ProfileView}, browser::RomBrowser, display::{self, RenderMode}};
+
+const DISPLAY_WIDTH: u16 = 64;
+const DISPLAY_HEIGHT: u16 = 32;
+const STACK_WIDTH: u16 = 24;
+/// The memory table's address column and 16 byte columns, with spacing and borders
+const MEMORY_WIDTH: u16 = 62;
+/// Rows under the display for the keypad and timers
+const KEYPAD_HEIGHT: u16 = 5;
+/// Rows under the memory table for script output, with borders
+const SCRIPT_OUTPUT_HEIGHT: u16 = 10;
 
 pub fn draw(f: &mut Frame, app: &mut App) {
     let chunks = Layout::default()
-        .constraints([Constraint::Length(2), Constraint::Min(36), Constraint::Length(4)].as_ref())
+        .constraints([Constraint::Length(2), Constraint::Min(0), Constraint::Length(4)].as_ref())
         .split(f.size());
 
     draw_status(f, app, chunks[0]);
     draw_mem_fb(f, app, chunks[1]);
     draw_reg_dis(f, app, chunks[2]);
+
+    if let Some(browser) = &mut app.browser {
+        draw_browser(f, browser, f.size());
+    }
+}
+
+fn draw_browser(f: &mut Frame, browser: &mut RomBrowser, area: Rect) {
+    let width = area.width.min(80);
+    let height = area.height.min(24);
+    let popup = Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height);
+
+    let table = Table::new(gen_browser_view(browser), [Constraint::Min(20), Constraint::Length(7), Constraint::Length(40)])
+        .block(Block::default().title(format!("Open ROM: {}", browser.dir.display())).title_bottom("Enter: Open Backspace: Up Esc: Cancel").borders(Borders::ALL))
+        .header(Row::new(["Name", "Size", "SHA-1"]).style(Style::default().add_modifier(Modifier::BOLD)))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).bg(Color::Cyan));
+    f.render_widget(Clear, popup);
+    f.render_stateful_widget(table, popup, &mut browser.state);
+}
+
+fn gen_browser_view(browser: &RomBrowser) -> Vec<Row<'static>> {
+    browser.entries.iter().map(|entry| Row::new([
+        entry.name.clone(),
+        entry.size.map(|size| size.to_string()).unwrap_or_default(),
+        entry.hash.clone().unwrap_or_default(),
+    ])).collect()
 }
 
 fn draw_status(f: &mut Frame, app: &App, area: Rect) {
@@ -21,51 +56,111 @@ fn draw_status(f: &mut Frame, app: &App, area: Rect) {
 }
 
 fn draw_mem_fb(f: &mut Frame, app: &mut App, area: Rect) {
+    // the display takes what the stack and memory panes leave, in the largest render mode that fits there
+    let mode = app.render_mode.unwrap_or_else(|| RenderMode::largest_fitting(
+        DISPLAY_WIDTH,
+        DISPLAY_HEIGHT,
+        area.width.saturating_sub(STACK_WIDTH + MEMORY_WIDTH + 2),
+        area.height.saturating_sub(KEYPAD_HEIGHT + 2),
+    ));
+    let (columns, rows) = mode.size(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+
     let chunks = Layout::default()
-        .constraints(vec![Constraint::Length(10), Constraint::Min(0), Constraint::Length(66)])
+        .constraints(vec![Constraint::Length(STACK_WIDTH), Constraint::Min(0), Constraint::Length(columns + 2)])
         .direction(Direction::Horizontal)
         .split(area);
 
     draw_stack(f, app, chunks[0]);
 
-    let table = Table::new(gen_mem_view(&app.chip_state), [Constraint::Length(4); 16])
+    let mut widths = vec![Constraint::Length(12)];
+    widths.extend([Constraint::Length(2); 16]);
+    let table = Table::new(gen_mem_view(app), widths)
         .block(Block::default().title("Memory").borders(Borders::ALL))
         .highlight_style(Style::default().add_modifier(Modifier::BOLD).bg(Color::Cyan))
-        .header(Row::new((0..16).map(|i| Cell::from(format!("xx{i:X?}")))));
+        .header(Row::new(std::iter::once(Cell::from("")).chain((0..16).map(|i| Cell::from(format!("x{i:X?}"))))));
     app.memory_state.select(Some(app.mem_row_sel_override.unwrap_or((app.chip_state.pc / 16) as usize)));
-    f.render_stateful_widget(table, chunks[1], &mut app.memory_state);
+    let memory_area = if let Some(scripts) = &app.scripts {
+        let memory_chunks = Layout::default()
+            .constraints(vec![Constraint::Min(0), Constraint::Length(SCRIPT_OUTPUT_HEIGHT)])
+            .direction(Direction::Vertical)
+            .split(chunks[1]);
+        // the latest lines that fit
+        let output = scripts.output();
+        let shown = output.iter().skip(output.len().saturating_sub(usize::from(SCRIPT_OUTPUT_HEIGHT - 2)));
+        let output_view = Paragraph::new(shown.map(|line| Line::from(line.as_str())).collect::<Vec<_>>())
+            .block(Block::default().title("Script Output").borders(Borders::ALL));
+        f.render_widget(output_view, memory_chunks[1]);
+        memory_chunks[0]
+    } else {
+        chunks[1]
+    };
+    f.render_stateful_widget(table, memory_area, &mut app.memory_state);
 
-    draw_display(f, app, chunks[2]);
+    draw_display(f, app, chunks[2], mode, rows);
 }
 
 fn draw_stack(f: &mut Frame, app: &mut App, area: Rect) {
     let chunks = Layout::default()
-        .constraints(vec![Constraint::Min(0), Constraint::Length(3)])
+        .constraints(vec![Constraint::Min(0), Constraint::Length(8), Constraint::Length(3)])
         .direction(Direction::Vertical)
         .split(area);
 
-    let stack_view = List::new(gen_stack_view(&app.chip_state))
-        .block(Block::default().title("Stack").borders(Borders::ALL))
+    let chunks = if app.chip_state.profiler.is_some() {
+        let stack_chunks = Layout::default()
+            .constraints(vec![Constraint::Percentage(50), Constraint::Percentage(50)])
+            .direction(Direction::Vertical)
+            .split(chunks[0]);
+        let hot_spots = List::new(gen_hot_spots_view(app))
+            .block(Block::default().title(hot_spots_title(app.profile_view)).borders(Borders::ALL));
+        f.render_widget(hot_spots, stack_chunks[1]);
+        vec![stack_chunks[0], chunks[1], chunks[2]]
+    } else {
+        chunks.to_vec()
+    };
+
+    let frames = app.frames();
+    let stack_view = List::new(gen_call_stack_view(&frames, &app.symbols))
+        .block(Block::default().title("Call Stack").borders(Borders::ALL))
         .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Yellow))
-        .highlight_symbol("SP>");
-    app.stack_state.select(Some(app.chip_state.sp as usize));
-    f.render_stateful_widget(stack_view, chunks[0], &mut app.stack_state);
+        .highlight_symbol(">");
+    let selected = app.call_stack_state.selected().unwrap_or_default().min(frames.len() - 1);
+    app.call_stack_state.select(Some(selected));
+    f.render_stateful_widget(stack_view, chunks[0], &mut app.call_stack_state);
+
+    let breakpoints = List::new(gen_breakpoint_view(app))
+        .block(Block::default().title("Breakpoints").borders(Borders::ALL));
+    f.render_widget(breakpoints, chunks[1]);
 
     let sp_area = Paragraph::new(gen_sp_view(&app.chip_state));
-    f.render_widget(sp_area, chunks[1]);
+    f.render_widget(sp_area, chunks[2]);
 }
 
-fn draw_display(f: &mut Frame, app: &mut App, area: Rect) {
+fn draw_display(f: &mut Frame, app: &mut App, area: Rect, mode: RenderMode, rows: u16) {
     let chunks = Layout::default()
-        .constraints(vec![Constraint::Length(34), Constraint::Min(0)])
+        .constraints(vec![Constraint::Length(rows + 2), Constraint::Min(0)])
         .direction(Direction::Vertical)
         .split(area);
 
-    let display = Paragraph::new(render_display(&app.chip_state))
-        .block(Block::default().title("Display").borders(Borders::ALL))
+    let state = &app.chip_state;
+    let intensity = |x: u16, y: u16| state.pixel_intensity(usize::from(x), usize::from(y));
+    // CHIP-8X colors the display itself, in 8 pixel wide areas
+    let chip8x = state.variant == Variant::Chip8X;
+    let on = |x: u16, y: u16| if chip8x { display::chip8x_color(state.colors[usize::from(y) * 8 + usize::from(x / 8)]) } else { app.settings.pixel_on };
+    let off = if chip8x { display::chip8x_color(state.background) } else { app.settings.pixel_off };
+    let display = Paragraph::new(mode.render(DISPLAY_WIDTH, DISPLAY_HEIGHT, intensity, on, off))
+        .block(gen_display_block(app))
         .alignment(Alignment::Center);
     f.render_widget(display, chunks[0]);
 
+    let chunks = Layout::default()
+        .constraints(vec![Constraint::Length(11), Constraint::Min(0)])
+        .direction(Direction::Horizontal)
+        .split(chunks[1]);
+
+    let keypad = Paragraph::new(gen_keypad_view(&app.chip_state))
+        .block(Block::default().title("Keys").borders(Borders::LEFT));
+    f.render_widget(keypad, chunks[0]);
+
     let timers = Paragraph::new(gen_timer_view(&app.chip_state))
         .alignment(Alignment::Right);
     f.render_widget(timers, chunks[1]);
@@ -81,20 +176,24 @@ fn draw_reg_dis(f: &mut Frame, app: &mut App, area: Rect) {
         .block(Block::default().title("Registers").borders(Borders::LEFT).border_type(BorderType::Thick));
     f.render_widget(table, chunks[0]);
 
-    let disassembly = Paragraph::new(vec![Line::default(), Line::from(vec![Span::raw(format!("{:X?}", app.chip_state.decode_opcode()))])])
+    let disassembly = Paragraph::new(gen_disassembly_view(app, app.disasm_addr_override.unwrap_or(app.chip_state.pc)))
         .block(Block::default().title("Disassembly").borders(Borders::RIGHT).border_type(BorderType::Thick));
     f.render_widget(disassembly, chunks[1]);
 }
 
-fn gen_status_view(app: &App) -> Vec<Line>{
+fn gen_status_view(app: &App) -> Vec<Line<'_>>{
     let mut spans = vec![];
 
-    if let Some(failure) = &app.last_failure {
+    if let Some(prompt) = &app.prompt {
+        spans.push(Line::from(format!(":{prompt}█")).alignment(Alignment::Left));
+    } else if let Some(failure) = &app.last_failure {
         spans.push(Span::styled(format!("Emulator crashed! Error details: {} | Instruction Count: {}", failure.panic_message, failure.last_instr_count), Style::default().bg(Color::Red)).into());
     } else {
         spans.push(vec![
+            Span::raw(app.message.as_ref().map(|message| format!("{message} | ")).unwrap_or_default()),
             Span::styled(format!("Instruction Count: {} ", app.instr_count), style_warn_overrun(app.instr_count, u64::MAX)),
             Span::raw(format!("| {}", app.disp_frequency())),
+            Span::styled(app.movie_status().map(|status| format!(" | {status}")).unwrap_or_default(), Style::default().fg(Color::Red)),
         ].into());
     }
 
@@ -102,10 +201,10 @@ fn gen_status_view(app: &App) -> Vec<Line>{
 }
 
 fn shortcuts_view() -> String {
-    String::from("Shortcuts | ^Q: Quit ^R: Reset N: Step to next instruction ↕: Scroll memory view M: Return memory view to PC U/J: Inc/Dec Frequency P: Pause")
+    String::from("Shortcuts | ^Q: Quit ^R: Reset N: Step to next instruction ↕: Scroll memory view M: Return views to PC [/]: Select frame G: Go to frame O: Open ROM H: Profiler T: Hot spot view K: Coverage L: Display size Colon: Command (b/g ADDR, sym/cov/script FILE) U/J: Inc/Dec Frequency P: Pause")
 }
 
-fn gen_reg_view(state: &Chip8State) -> Vec<Row> {
+fn gen_reg_view(state: &Chip8State) -> Vec<Row<'_>> {
     let mut row1 = vec![];
     let mut row2 = vec![];
 
@@ -121,20 +220,33 @@ fn gen_reg_view(state: &Chip8State) -> Vec<Row> {
     vec![Row::new(row1), Row::new(row2)]
 }
 
-fn render_display(state: &Chip8State) -> Vec<Line> {
-    let mut spans = vec![];
-    for y in 0..32 {
-        let mut inner_spans = vec![];
-        for x in 0..64 {
-            inner_spans.push(if state.framebuffer[(8 * y) + (x / 8)] & 0x80 >> (x % 8) == 0 {Span::raw(" ")} else {Span::raw("█")});
-        }
-        spans.push(Line::from(inner_spans));
-    }
+/// Titles the display with what the ROM database knows about the loaded ROM.
+fn gen_display_block(app: &App) -> Block<'_> {
+    let block = Block::default().borders(Borders::ALL);
+    let Some(details) = &app.rom_details else {
+        return block.title("Display")
+    };
 
-    spans
+    let title = match &details.platform {
+        Some(platform) => format!("Display: {} ({platform})", details.title),
+        None => format!("Display: {}", details.title),
+    };
+    let keys = details.keys.iter().map(|(name, key)| format!("{name} {key:X}")).collect::<Vec<_>>().join(", ");
+
+    block.title(title).title_bottom(keys)
 }
 
-fn gen_timer_view(state: &Chip8State) -> Vec<Line> {
+/// Shows the COSMAC VIP keypad with the keys currently held down highlighted.
+fn gen_keypad_view(state: &Chip8State) -> Vec<Line<'_>> {
+    const LAYOUT: [[u8; 4]; 4] = [[0x1, 0x2, 0x3, 0xC], [0x4, 0x5, 0x6, 0xD], [0x7, 0x8, 0x9, 0xE], [0xA, 0x0, 0xB, 0xF]];
+
+    LAYOUT.iter().map(|row| Line::from(row.iter().flat_map(|key| {
+        let style = if state.input & (1 << key) == 0 { Style::default().add_modifier(Modifier::DIM) } else { Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED) };
+        [Span::raw(" "), Span::styled(format!("{key:X}"), style)]
+    }).collect::<Vec<_>>())).collect()
+}
+
+fn gen_timer_view(state: &Chip8State) -> Vec<Line<'_>> {
     let mut spans = vec![];
 
     let val = state.dt;
@@ -146,16 +258,28 @@ fn gen_timer_view(state: &Chip8State) -> Vec<Line> {
     spans.push(Line::from(vec![
         Span::raw(format!("Sound Timer: {val:02X?}  "))
     ]));
+    if state.variant != Variant::Chip8 {
+        let output = state.io.output.map_or_else(|| String::from("--"), |byte| format!("{byte:02X}"));
+        spans.push(Line::from(vec![
+            Span::raw(format!("Variant: {}  I/O Out: {output}  In: {:02X}", state.variant.name(), state.io.input))
+        ]));
+    }
+    if let Some(vip) = &state.vip {
+        let cpu = &vip.cpu;
+        spans.push(Line::from(vec![
+            Span::raw(format!("1802 P: {:X}  X: {:X}  R(P): {:04X}  D: {:02X}  DF: {}  Q: {}", cpu.p, cpu.x, cpu.r[usize::from(cpu.p)], cpu.d, u8::from(cpu.df), u8::from(cpu.q)))
+        ]));
+    }
 
     spans
 }
 
-fn gen_sp_view(state: &Chip8State) -> Vec<Line> {
+fn gen_sp_view(state: &Chip8State) -> Vec<Line<'_>> {
     let mut spans = vec![];
 
     let val = state.sp;
     spans.push(Line::from(vec![
-        Span::styled(format!(" SP: {val:02X?}"), style_warn_overrun(val, 64)),
+        Span::styled(format!(" SP: {val:02X?}"), style_warn_overrun(val, 2 * state.stack_config.depth())),
     ]));
     let val = state.pc;
     spans.push(Line::from(vec![
@@ -165,24 +289,123 @@ fn gen_sp_view(state: &Chip8State) -> Vec<Line> {
     spans
 }
 
-fn gen_stack_view(state: &Chip8State) -> Vec<ListItem> {
+fn gen_call_stack_view(frames: &[CallFrame], symbols: &Symbols) -> Vec<ListItem<'static>> {
     let mut items = vec![];
-    for i in 0..64 {
-        let val = state.stack[i];
-        items.push(ListItem::new(format!("{val:02X?}")).style(style_fade_default(val)));
+    for frame in frames {
+        let name = match frame.entered_by {
+            Some(entry) => entry.target.map_or_else(|| String::from("<unknown>"), |target| {
+                symbols.name(target).map_or_else(|| format!("sub_{target:03X}"), String::from)
+            }),
+            None => String::from("main"),
+        };
+        let call_site = frame.entered_by.map(|entry| format!("@{:03X}", entry.call_site)).unwrap_or_default();
+        items.push(ListItem::new(format!("{:03X} {name:<10}{call_site}", frame.address)));
     }
 
     items
 }
 
-fn gen_mem_view(state: &Chip8State) -> Vec<Row> {
+fn hot_spots_title(view: ProfileView) -> &'static str {
+    match view {
+        ProfileView::Hits => "Hot Spots (hits)",
+        ProfileView::Address => "Hot Spots (address)",
+        ProfileView::Opcodes => "Hot Spots (opcodes)",
+        ProfileView::Loops => "Hot Spots (loops)",
+    }
+}
+
+fn gen_hot_spots_view(app: &App) -> Vec<ListItem<'static>> {
+    let Some(profiler) = &app.chip_state.profiler else {
+        return vec![]
+    };
+
+    match app.profile_view {
+        ProfileView::Hits | ProfileView::Address => {
+            let mut spots = profiler.hot_spots();
+            if app.profile_view == ProfileView::Address {
+                spots.sort_by_key(|(addr, _)| *addr);
+            }
+            spots.into_iter().map(|(addr, hits)| {
+                let label = app.symbols.describe(addr).unwrap_or_default();
+                ListItem::new(format!("{addr:03X} {hits:>9} {label}"))
+            }).collect()
+        },
+        ProfileView::Opcodes => profiler.opcode_histogram().into_iter().map(|(name, hits)| {
+            #[allow(clippy::cast_precision_loss)]
+            let share = hits as f64 * 100.0 / profiler.total() as f64;
+            ListItem::new(format!("{name:<5} {hits:>9} {share:>3.0}%"))
+        }).collect(),
+        ProfileView::Loops => profiler.hot_loops().into_iter().map(|hot_loop| {
+            ListItem::new(format!("{:03X}-{:03X} {:>9}x", hot_loop.start, hot_loop.end, hot_loop.iterations))
+        }).collect(),
+    }
+}
+
+fn gen_breakpoint_view(app: &App) -> Vec<ListItem<'static>> {
+    app.breakpoints.iter().map(|addr| {
+        let label = app.symbols.describe(*addr).unwrap_or_default();
+        ListItem::new(format!("{addr:03X} {label}")).style(if *addr == app.chip_state.pc { Style::default().fg(Color::Red) } else { Style::default() })
+    }).collect()
+}
+
+/// Lists the instructions around `addr`, marking it and the program counter, and naming labeled addresses.
+fn gen_disassembly_view(app: &App, addr: u16) -> Vec<Line<'static>> {
+    let state = &app.chip_state;
+
+    let mut lines = vec![];
+    for i in 0..4u16 {
+        let line_addr = addr.wrapping_sub(2).wrapping_add(i * 2) & 0x0FFF;
+        let instruction = u16::from(state.memory[line_addr as usize]) << 8 | u16::from(state.memory[((line_addr + 1) % 4096) as usize]);
+        let opcode = Opcode::decode(instruction, state.variant);
+
+        let marker = if line_addr == state.pc { "PC>" } else if app.breakpoints.contains(&line_addr) { " ● " } else { "   " };
+        let label = app.symbols.name(line_addr).map(|name| format!("{name}: ")).unwrap_or_default();
+        let target = opcode.target_address().and_then(|target| app.symbols.describe(target)).map(|name| format!(" ; {name}")).unwrap_or_default();
+        let writer = state.smc.as_ref().and_then(|smc| smc.modified_by(line_addr).or_else(|| smc.modified_by(line_addr + 1)));
+        let modified = writer.map(|pc| format!(" ; modified by {pc:03X}")).unwrap_or_default();
+        let mut style = if line_addr == addr { Style::default().add_modifier(Modifier::BOLD) } else { Style::default().add_modifier(Modifier::DIM) };
+        if writer.is_some() {
+            style = style.fg(Color::Magenta);
+        }
+        lines.push(Line::from(Span::styled(format!("{marker}{line_addr:03X}: {label}{opcode:X?}{target}{modified}"), style)));
+    }
+
+    lines
+}
+
+fn gen_mem_view(app: &App) -> Vec<Row<'static>> {
+    let state = &app.chip_state;
+    let max_hits = state.profiler.as_ref().map(|profiler| profiler.max_hits()).unwrap_or_default();
     let mut rows = vec![];
 
     for y in 0..256 {
-        let mut row = vec![];
+        let label = app.symbols.first_in(y * 16, (y + 1) * 16).map(|(_, name)| name).unwrap_or_default();
+        let mut row = vec![Cell::from(format!("{:03X} {label}", y * 16)).style(Style::default().add_modifier(Modifier::DIM))];
         for x in 0..16 {
             let val = state.memory[(16 * y as usize) + x as usize];
-            let style = style_fade_default(val).add_modifier(if state.pc / 16 == y && (state.pc % 16 == x || state.pc % 16 == x.saturating_sub(1)) { Modifier::REVERSED } else { Modifier::empty() });
+            let mut style = style_fade_default(val).add_modifier(if state.pc / 16 == y && (state.pc % 16 == x || state.pc % 16 == x.saturating_sub(1)) { Modifier::REVERSED } else { Modifier::empty() });
+            if let Some(profiler) = &state.profiler {
+                // an instruction's hits are counted at its first byte, so the second byte takes them from there
+                let addr = 16 * y + x;
+                let hits = profiler.hits(addr).max(profiler.hits(addr.wrapping_sub(1)));
+                if let Some(color) = heat_color(hits, max_hits) {
+                    style = style.bg(color);
+                }
+            }
+            if let Some(coverage) = &state.coverage {
+                style = match coverage.get(16 * y + x) {
+                    0 => style,
+                    coverage::CODE => style.fg(Color::Green),
+                    coverage::DATA => style.fg(Color::Yellow),
+                    _ => style.fg(Color::Cyan),
+                };
+            }
+            if state.smc.as_ref().is_some_and(|smc| smc.modified_by(16 * y + x).is_some()) {
+                style = style.fg(Color::Magenta).add_modifier(Modifier::UNDERLINED);
+            }
+            if app.breakpoints.contains(&(16 * y + x)) {
+                style = style.fg(Color::Red);
+            }
             row.push(Cell::from(format!("{val:02X?}")).style(style));
         }
         rows.push(Row::new(row));
@@ -191,6 +414,18 @@ fn gen_mem_view(state: &Chip8State) -> Vec<Row> {
     rows
 }
 
+/// Tints executed memory on a logarithmic scale from dark red (rarely) to bright red (as often as the hottest address).
+fn heat_color(hits: u64, max_hits: u64) -> Option<Color> {
+    const RAMP: [u8; 5] = [52, 88, 124, 160, 196];
+
+    if hits == 0 {
+        return None
+    }
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let level = ((hits as f64).ln_1p() / (max_hits as f64).ln_1p() * (RAMP.len() - 1) as f64).round() as usize;
+    Some(Color::Indexed(RAMP[level.min(RAMP.len() - 1)]))
+}
+
 fn style_fade_default<T: Default + PartialEq + Copy>(val: T) -> Style {
     if val == T::default() {
         Style::default().add_modifier(Modifier::DIM)
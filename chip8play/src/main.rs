@@ -0,0 +1,208 @@
+mod player;
+mod screen;
+
+use std::{io, path::PathBuf, process, time::{Duration, Instant}};
+
+use clap::Parser;
+use crossterm::{execute, terminal::{enable_raw_mode, disable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen, supports_keyboard_enhancement}, event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags, PushKeyboardEnhancementFlags, PopKeyboardEnhancementFlags}};
+use chip8exe::{frontend::DEFAULT_KEYMAP, phosphor};
+use player::Player;
+use ratatui::{backend::{Backend, CrosstermBackend}, layout::{Alignment, Constraint, Direction, Layout, Rect}, style::{Color, Modifier, Style}, text::{Line, Span}, widgets::Paragraph, Frame, Terminal};
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// Frames the player may fall behind, after a stall, before it gives up catching up
+const MAX_LAG: u32 = 3;
+const PIXEL_ON: Color = Color::White;
+const PIXEL_OFF: Color = Color::Black;
+/// Background while the sound timer runs
+const PIXEL_BEEP: Color = Color::DarkGray;
+/// The 24 grays at the end of the 256-color palette, from near black to near white, which fading pixels pass through
+const GRAYS: u8 = 232;
+
+/// Terminal CHIP-8 player: shows only the display, scaled to fit the terminal.
+/// Keys 1234/QWER/ASDF/ZXCV are the CHIP-8 keypad; P pauses, Ctrl+R resets, F5 saves the state, F9 loads it, and Esc quits.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// ROM file to play
+    pub rom: PathBuf,
+    /// Instructions per 60 Hz frame [default: from the ROM database, or 10]
+    #[arg(long)]
+    pub ipf: Option<u32>,
+    /// Quirk preset: default, vip, schip or xochip [default: from the ROM database]
+    #[arg(short, long)]
+    pub quirks: Option<String>,
+    /// File F5 saves the state to and F9 loads it from [default: the ROM's path ending in .state]
+    #[arg(long, value_name = "FILE")]
+    pub state_file: Option<PathBuf>,
+    /// Milliseconds a key stays held on terminals that do not report key releases
+    #[arg(long, value_name = "MS")]
+    pub key_release_timeout: Option<u64>,
+    /// Directory holding platforms.json and programs.json of the CHIP-8 database, used instead of the built-in copy
+    #[arg(long, value_name = "DIR")]
+    pub rom_db: Option<PathBuf>,
+    /// Reduce flicker by letting pixels fade by N per frame (decay:N) or stay lit for N frames (max:N)
+    #[arg(long, value_name = "MODE")]
+    pub phosphor: Option<String>,
+    /// Host a two-player game on this port, as player 1; the other player joins with --connect
+    #[arg(long, value_name = "PORT", conflicts_with = "connect")]
+    pub host: Option<u16>,
+    /// Join the two-player game hosted at this address, such as 192.168.1.5:6464, as player 2
+    #[arg(long, value_name = "HOST:PORT")]
+    pub connect: Option<String>,
+    /// Hosting: CHIP-8 keys player 1 controls, as hex digits such as 14 [default: all]
+    #[arg(long, value_name = "KEYS")]
+    pub player1_keys: Option<String>,
+    /// Hosting: CHIP-8 keys player 2 controls, as hex digits such as CD [default: all]
+    #[arg(long, value_name = "KEYS")]
+    pub player2_keys: Option<String>,
+    /// Hosting: frames every key press is held back, so it reaches the other player in time
+    #[arg(long, value_name = "FRAMES", default_value_t = 2)]
+    pub input_delay: u8,
+}
+
+fn main() -> io::Result<()> {
+    // load errors are reported before the terminal is taken over
+    let mut player = match Player::new(&Cli::parse()) {
+        Ok(player) => player,
+        Err(err) => {
+            eprintln!("chip8play: {err}");
+            process::exit(2);
+        },
+    };
+
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    // key release events are only reported by terminals supporting the kitty keyboard protocol
+    player.key_release_events = supports_keyboard_enhancement().unwrap_or(false);
+    if player.key_release_events {
+        execute!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES | KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+    }
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let res = run(&mut terminal, &mut player);
+
+    disable_raw_mode()?;
+    if player.key_release_events {
+        execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
+    }
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+
+    res
+}
+
+/// Runs frames at a steady 60 Hz, handling input in between, until quit.
+fn run<B: Backend>(terminal: &mut Terminal<B>, player: &mut Player) -> io::Result<()> {
+    let mut next_frame = Instant::now();
+    loop {
+        terminal.draw(|f| draw(f, player))?;
+
+        loop {
+            let now = Instant::now();
+            if now >= next_frame {
+                break;
+            }
+            if event::poll(next_frame - now)? {
+                if let Event::Key(key) = event::read()? {
+                    if !handle_key(key, player) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        player.release_expired_keys();
+        player.run_frame();
+
+        next_frame += FRAME;
+        // after a stall, such as the terminal being suspended, drop the missed frames instead of racing through them
+        if next_frame.elapsed() > FRAME * MAX_LAG {
+            next_frame = Instant::now();
+        }
+    }
+}
+
+/// Applies a key event. Returns false to quit.
+fn handle_key(key: KeyEvent, player: &mut Player) -> bool {
+    if let (KeyCode::Char(c), false) = (key.code, key.modifiers.contains(KeyModifiers::CONTROL)) {
+        // caps lock should not stop the keypad working
+        if let Some(chip8_key) = DEFAULT_KEYMAP.iter().position(|bound| *bound == c.to_ascii_lowercase()) {
+            #[allow(clippy::cast_possible_truncation)]
+            let chip8_key = chip8_key as u8;
+            match key.kind {
+                KeyEventKind::Press | KeyEventKind::Repeat => player.press_key(chip8_key),
+                KeyEventKind::Release => player.release_key(chip8_key),
+            }
+            return true;
+        }
+    }
+    if key.kind != KeyEventKind::Press {
+        return true;
+    }
+
+    match key.code {
+        KeyCode::Esc => return false,
+        KeyCode::Char('q' | 'c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+        KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => player.reset(),
+        KeyCode::Char('p' | 'P') => player.toggle_pause(),
+        KeyCode::F(5) => player.save_state(),
+        KeyCode::F(9) => player.load_state(),
+        _ => {},
+    }
+    true
+}
+
+fn draw(f: &mut Frame, player: &Player) {
+    let chunks = Layout::default()
+        .constraints(vec![Constraint::Min(0), Constraint::Length(1)])
+        .direction(Direction::Vertical)
+        .split(f.size());
+
+    let area = chunks[0];
+    let scale = screen::fit(area.width, area.height);
+    if scale == 0 {
+        let message = format!("The terminal needs to be at least {}x{} to show the display", screen::WIDTH, screen::HEIGHT / 2 + 1);
+        f.render_widget(Paragraph::new(message).alignment(Alignment::Center), area);
+    } else {
+        let off = if player.beeping() { PIXEL_BEEP } else { PIXEL_OFF };
+        let color = |intensity| match intensity {
+            0 => off,
+            phosphor::FULL => PIXEL_ON,
+            #[allow(clippy::cast_possible_truncation)]
+            fading => Color::Indexed(GRAYS + (u16::from(fading) * 23 / u16::from(phosphor::FULL)) as u8),
+        };
+        let (width, height) = (screen::WIDTH * scale, screen::HEIGHT / 2 * scale);
+        let display = Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height);
+        let intensity = |x, y| player.state.pixel_intensity(x, y);
+        f.render_widget(Paragraph::new(screen::render(intensity, scale, color)), display);
+    }
+
+    f.render_widget(Paragraph::new(status_line(player)), chunks[1]);
+}
+
+fn status_line(player: &Player) -> Line<'_> {
+    let mut spans = vec![Span::styled(format!(" {} ", player.title), Style::default().add_modifier(Modifier::BOLD))];
+    if player.beeping() {
+        spans.push(Span::styled(" ♪ ", Style::default().add_modifier(Modifier::REVERSED)));
+    }
+    if player.paused {
+        spans.push(Span::styled(" PAUSED ", Style::default().fg(Color::Yellow).add_modifier(Modifier::REVERSED)));
+    }
+    if let Some(netplay) = &player.netplay {
+        spans.push(Span::styled(format!(" P{} ", netplay.player()), Style::default().fg(Color::Cyan).add_modifier(Modifier::REVERSED)));
+    }
+
+    let text = match (&player.crash, player.message()) {
+        (Some(crash), _) => Span::styled(format!(" {crash} (Ctrl+R resets)"), Style::default().fg(Color::Red)),
+        (None, Some(message)) => Span::raw(format!(" {message}")),
+        (None, None) => Span::styled(
+            format!(" {} IPF  P pause  Ctrl+R reset  F5 save  F9 load  Esc quit", player.ipf),
+            Style::default().add_modifier(Modifier::DIM),
+        ),
+    };
+    spans.push(text);
+
+    Line::from(spans)
+}
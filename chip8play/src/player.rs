@@ -0,0 +1,236 @@
+use std::{fs, net::TcpListener, panic::{self, AssertUnwindSafe}, path::{Path, PathBuf}, time::{Duration, Instant}};
+
+use chip8exe::{frontend::{panic_message, DEFAULT_KEY_RELEASE_TIMEOUT}, netplay::{self, Netplay, NetplayConfig}, phosphor::{Phosphor, PhosphorMode}, quirks::Quirks, romdb::RomDb, Chip8State};
+
+use crate::Cli;
+
+pub const DEFAULT_IPF: u32 = 10;
+/// How long hotkey feedback stays on the status line
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub struct Player {
+    pub state: Chip8State,
+    rom: Vec<u8>,
+    pub title: String,
+    pub ipf: u32,
+    pub paused: bool,
+    /// Set when the program crashes, which stops it until reset or a state is loaded
+    pub crash: Option<String>,
+    /// Feedback from the last hotkey, and when it was given
+    message: Option<(String, Instant)>,
+    state_file: PathBuf,
+    /// Whether the terminal reports key releases, so held keys need no timeout
+    pub key_release_events: bool,
+    key_release_timeout: Duration,
+    /// When each held CHIP-8 key was last pressed or repeated
+    key_pressed_at: [Option<Instant>; 16],
+    /// CHIP-8 keys held on this keyboard, as an `input` bitfield
+    keys: u16,
+    /// The connection to the other player, in a two-player game
+    pub netplay: Option<Netplay>,
+}
+
+impl Player {
+    /// Loads the ROM, taking its quirks and speed from the ROM database unless they are given.
+    pub fn new(cli: &Cli) -> Result<Self, String> {
+        let rom = fs::read(&cli.rom).map_err(|err| format!("could not read {}: {err}", cli.rom.display()))?;
+        let db = match &cli.rom_db {
+            Some(dir) => RomDb::load_dir(dir).map_err(|err| format!("could not load the ROM database from {}: {err}", dir.display()))?,
+            None => RomDb::embedded(),
+        };
+
+        let mut state = Chip8State::default();
+        let found = state.load_known_program(&rom, &db).map_err(|err| format!("could not load {}: {err}", cli.rom.display()))?;
+        if let Some(name) = &cli.quirks {
+            state.quirks = Quirks::preset(name)
+                .ok_or_else(|| format!("unknown quirk preset {name}, expected one of {}", Quirks::PRESET_NAMES.join(", ")))?;
+        }
+        if let Some(mode) = &cli.phosphor {
+            let mode = mode.parse::<PhosphorMode>().map_err(|err| err.to_string())?;
+            state.phosphor = Some(Box::new(Phosphor::new(mode)));
+        }
+        let mut ipf = cli.ipf.or(found.as_ref().and_then(|found| found.tickrate)).unwrap_or(DEFAULT_IPF);
+        let netplay = start_netplay(cli, &mut state, &rom, ipf)?;
+        if let Some(netplay) = &netplay {
+            ipf = netplay.config().ipf;
+        }
+        let title = match &found {
+            Some(found) => found.program.title.clone(),
+            None => file_name(&cli.rom),
+        };
+
+        Ok(Self {
+            state,
+            rom,
+            title,
+            ipf,
+            paused: false,
+            crash: None,
+            message: None,
+            state_file: cli.state_file.clone().unwrap_or_else(|| cli.rom.with_extension("state")),
+            key_release_events: false,
+            key_release_timeout: cli.key_release_timeout.map_or(DEFAULT_KEY_RELEASE_TIMEOUT, Duration::from_millis),
+            key_pressed_at: [None; 16],
+            keys: 0,
+            netplay,
+        })
+    }
+
+    /// Runs one 60 Hz frame, unless paused or crashed.
+    pub fn run_frame(&mut self) {
+        if self.paused || self.crash.is_some() {
+            return;
+        }
+
+        let old_hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| {
+            // reported on the status line instead
+        }));
+        let (state, netplay) = (&mut self.state, &mut self.netplay);
+        let (ipf, keys) = (self.ipf, self.keys);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| match netplay {
+            Some(netplay) => netplay.run_frame(state, keys),
+            None => {
+                state.input = keys;
+                state.run_frame(ipf);
+                Ok(())
+            },
+        }));
+        panic::set_hook(old_hook);
+
+        self.crash = match result {
+            Err(panic) => Some(format!("Crashed at {:03X}: {}", self.state.pc, panic_message(&*panic))),
+            Ok(Err(err)) => {
+                self.netplay = None;
+                Some(format!("Netplay stopped: {err}"))
+            },
+            Ok(Ok(())) => self.state.stack_error.map(|err| format!("Crashed at {:03X}: {err}", self.state.pc)),
+        };
+    }
+
+    /// Whether the sound timer is running, which the display shows in place of a tone
+    pub fn beeping(&self) -> bool {
+        self.state.st > 0
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.refuse_in_netplay() {
+            return;
+        }
+        self.paused = !self.paused;
+    }
+
+    /// Starts the ROM over, keeping the quirks and speed.
+    pub fn reset(&mut self) {
+        if self.refuse_in_netplay() {
+            return;
+        }
+        self.state.reset();
+        // it fit when it was first loaded
+        let _ = self.state.load_program(&self.rom);
+        self.crash = None;
+        self.set_message(String::from("Reset"));
+    }
+
+    pub fn save_state(&mut self) {
+        self.set_message(match fs::write(&self.state_file, self.state.save_state()) {
+            Ok(()) => format!("Saved state to {}", self.state_file.display()),
+            Err(err) => format!("Could not save state to {}: {err}", self.state_file.display()),
+        });
+    }
+
+    pub fn load_state(&mut self) {
+        if self.refuse_in_netplay() {
+            return;
+        }
+        let result = fs::read(&self.state_file)
+            .map_err(|err| err.to_string())
+            .and_then(|data| self.state.load_state(&data).map_err(|err| err.to_string()));
+
+        let message = match result {
+            Ok(()) => {
+                self.crash = None;
+                format!("Loaded state from {}", self.state_file.display())
+            },
+            Err(err) => format!("Could not load state from {}: {err}", self.state_file.display()),
+        };
+        self.set_message(message);
+    }
+
+    /// Feedback from the last hotkey, until it gets old
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_ref().filter(|(_, at)| at.elapsed() < MESSAGE_TIMEOUT).map(|(message, _)| message.as_str())
+    }
+
+    fn set_message(&mut self, message: String) {
+        self.message = Some((message, Instant::now()));
+    }
+
+    /// Whether a two-player game is on, which rules out anything that would put the players out of step
+    fn refuse_in_netplay(&mut self) -> bool {
+        if self.netplay.is_some() {
+            self.set_message(String::from("Not available in a two-player game"));
+        }
+        self.netplay.is_some()
+    }
+
+    pub fn press_key(&mut self, key: u8) {
+        self.keys |= 1 << key;
+        self.key_pressed_at[usize::from(key)] = Some(Instant::now());
+    }
+
+    pub fn release_key(&mut self, key: u8) {
+        self.keys &= !(1 << key);
+        self.key_pressed_at[usize::from(key)] = None;
+    }
+
+    /// Emulates key releases on terminals that only report presses.
+    pub fn release_expired_keys(&mut self) {
+        if self.key_release_events {
+            return;
+        }
+
+        for key in 0..16 {
+            if self.key_pressed_at[usize::from(key)].is_some_and(|at| at.elapsed() >= self.key_release_timeout) {
+                self.release_key(key);
+            }
+        }
+    }
+}
+
+/// Hosts or joins a two-player game if asked to, waiting for the other player.
+fn start_netplay(cli: &Cli, state: &mut Chip8State, rom: &[u8], ipf: u32) -> Result<Option<Netplay>, String> {
+    if let Some(port) = cli.host {
+        let listener = TcpListener::bind(("0.0.0.0", port)).map_err(|err| format!("could not listen on port {port}: {err}"))?;
+        let config = NetplayConfig {
+            ipf,
+            seed: rand::random(),
+            keys: [parse_keys(cli.player1_keys.as_deref())?, parse_keys(cli.player2_keys.as_deref())?],
+            delay: cli.input_delay,
+        };
+        eprintln!("Waiting for player 2 to connect to port {port}...");
+        let netplay = Netplay::host(&listener, state, rom, config).map_err(|err| format!("could not start the game: {err}"))?;
+        return Ok(Some(netplay));
+    }
+    if let Some(address) = &cli.connect {
+        eprintln!("Connecting to {address}...");
+        let netplay = Netplay::join(address.as_str(), state, rom).map_err(|err| format!("could not join the game at {address}: {err}"))?;
+        return Ok(Some(netplay));
+    }
+    Ok(None)
+}
+
+/// Reads CHIP-8 keys given as hex digits, such as `14C`, as an `input` bitfield.
+fn parse_keys(keys: Option<&str>) -> Result<u16, String> {
+    let Some(keys) = keys else {
+        return Ok(netplay::ALL_KEYS)
+    };
+    keys.chars().try_fold(0, |keys, key| match key.to_digit(16) {
+        Some(key) => Ok(keys | 1 << key),
+        None => Err(format!("{key} is not a CHIP-8 key, expected hex digits such as 14C")),
+    })
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned())
+}
@@ -0,0 +1,73 @@
+//! Draws the display with half-block characters, two pixels to a terminal cell, scaled up by a whole number.
+
+use ratatui::{style::{Color, Style}, text::{Line, Span}};
+
+pub const WIDTH: u16 = 64;
+pub const HEIGHT: u16 = 32;
+
+/// The largest scale at which the display fits in `columns` by `rows` cells, or 0 if it does not fit at all.
+/// Each cell holds one pixel across and two down, so at scale 1 the display takes 64 by 16 cells.
+#[must_use]
+pub fn fit(columns: u16, rows: u16) -> u16 {
+    (columns / WIDTH).min(rows / (HEIGHT / 2))
+}
+
+/// The display at `scale`, as `16 * scale` lines of `64 * scale` cells.
+/// `intensity` gives the brightness of the pixel at x, y, which `color` turns into the color it shows in.
+#[must_use]
+pub fn render(intensity: impl Fn(usize, usize) -> u8, scale: u16, color: impl Fn(u8) -> Color) -> Vec<Line<'static>> {
+    let scale = usize::from(scale.max(1));
+    let pixel = |x: usize, y: usize| color(intensity(x / scale, y / scale));
+
+    (0..usize::from(HEIGHT) * scale / 2).map(|row| {
+        let mut spans: Vec<Span> = vec![];
+        let mut run = String::new();
+        let mut run_style = None;
+        for x in 0..usize::from(WIDTH) * scale {
+            // the upper half block takes the foreground color, and the lower half shows the background
+            let style = Style::default().fg(pixel(x, 2 * row)).bg(pixel(x, 2 * row + 1));
+            if run_style.is_some_and(|run_style| run_style != style) {
+                spans.push(Span::styled(std::mem::take(&mut run), run_style.unwrap_or_default()));
+            }
+            run_style = Some(style);
+            run.push('▀');
+        }
+        spans.push(Span::styled(run, run_style.unwrap_or_default()));
+
+        Line::from(spans)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit(80, 24), 1);
+        assert_eq!(fit(200, 60), 3);
+        assert_eq!(fit(300, 40), 2);
+        assert_eq!(fit(63, 50), 0);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut framebuffer = [0; 256];
+        // top left pixel, and the one below it in the second row
+        framebuffer[0] = 0x80;
+        framebuffer[8] = 0x80;
+        // bottom right pixel
+        framebuffer[255] = 0x01;
+
+        let intensity = |x: usize, y: usize| if framebuffer[y * 8 + x / 8] & (0x80 >> (x % 8)) != 0 { 255 } else { 0 };
+        let lines = render(intensity, 2, |intensity| if intensity > 0 { Color::White } else { Color::Black });
+
+        assert_eq!(lines.len(), 32);
+        assert!(lines.iter().all(|line| line.width() == 128));
+        let first = &lines[0].spans[0];
+        assert_eq!((first.content.as_ref(), first.style.fg, first.style.bg), ("▀▀", Some(Color::White), Some(Color::White)));
+        let last = lines[31].spans.last().unwrap();
+        assert_eq!((last.content.as_ref(), last.style.fg, last.style.bg), ("▀▀", Some(Color::White), Some(Color::White)));
+        assert_eq!(lines[2].spans.len(), 1);
+    }
+}
@@ -0,0 +1,177 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use chip8exe::{Chip8State, frontend::panic_message, quirks::Quirks};
+use pyo3::{create_exception, exceptions::{PyException, PyValueError}, prelude::*, types::PyBytes};
+use rand::{SeedableRng, rngs::StdRng};
+
+create_exception!(chip8, EmulatorError, PyException, "The emulated program crashed.");
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+/// A CHIP-8 emulator.
+///
+/// `quirks` names a preset (default, vip, schip or xochip); `seed` makes the random number generator reproducible.
+#[pyclass]
+struct Chip8 {
+    state: Chip8State,
+}
+
+#[pymethods]
+impl Chip8 {
+    #[new]
+    #[pyo3(signature = (quirks=None, seed=None))]
+    fn new(quirks: Option<&str>, seed: Option<u64>) -> PyResult<Self> {
+        let mut state = Chip8State::default();
+        if let Some(name) = quirks {
+            state.quirks = Quirks::preset(name)
+                .ok_or_else(|| PyValueError::new_err(format!("unknown quirk preset {name}, expected one of {}", Quirks::PRESET_NAMES.join(", "))))?;
+        }
+        if let Some(seed) = seed {
+            state.rng = StdRng::seed_from_u64(seed);
+        }
+
+        Ok(Self { state })
+    }
+
+    /// Loads a program at 0x200 and points the program counter at it.
+    fn load_rom(&mut self, rom: &[u8]) -> PyResult<()> {
+        self.state.load_program(rom).map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+
+    /// Clears the registers, timers, display and memory, unloading the ROM.
+    fn reset(&mut self) {
+        self.state.reset();
+    }
+
+    fn press_key(&mut self, key: u8) -> PyResult<()> {
+        self.state.input |= 1 << check_key(key)?;
+        Ok(())
+    }
+
+    fn release_key(&mut self, key: u8) -> PyResult<()> {
+        self.state.input &= !(1 << check_key(key)?);
+        Ok(())
+    }
+
+    /// Executes one instruction, after counting the timers down `timer_ticks` times.
+    #[pyo3(signature = (timer_ticks=0))]
+    fn step(&mut self, timer_ticks: u32) -> PyResult<()> {
+        self.run(|state| state.tick(timer_ticks))
+    }
+
+    /// Runs `frames` 60 Hz frames of `ipf` instructions each.
+    #[pyo3(signature = (frames=1, ipf=10))]
+    fn run_frames(&mut self, frames: u32, ipf: u32) -> PyResult<()> {
+        self.run(|state| {
+            for _ in 0..frames {
+                state.run_frame(ipf);
+                if state.stack_error.is_some() {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// V0 to VF
+    #[getter]
+    fn registers<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.registers)
+    }
+
+    /// All 4 KiB of memory
+    #[getter]
+    fn memory<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.memory)
+    }
+
+    /// `length` bytes of memory from `address`, wrapping around at 0x1000.
+    fn read_memory<'py>(&self, py: Python<'py>, address: u16, length: usize) -> Bound<'py, PyBytes> {
+        let bytes: Vec<_> = (0..length).map(|offset| self.state.memory[(usize::from(address) + offset) % self.state.memory.len()]).collect();
+        PyBytes::new(py, &bytes)
+    }
+
+    #[getter]
+    fn index(&self) -> u16 {
+        self.state.index
+    }
+
+    #[getter]
+    fn pc(&self) -> u16 {
+        self.state.pc
+    }
+
+    /// Stack pointer, in bytes: two per nested call
+    #[getter]
+    fn sp(&self) -> u8 {
+        self.state.sp
+    }
+
+    #[getter]
+    fn delay_timer(&self) -> u8 {
+        self.state.dt
+    }
+
+    #[getter]
+    fn sound_timer(&self) -> u8 {
+        self.state.st
+    }
+
+    /// Return addresses on the stack, outermost call first
+    #[getter]
+    fn call_stack(&self) -> Vec<u16> {
+        self.state.call_stack().iter().map(|frame| frame.return_address).collect()
+    }
+
+    /// The display as 32 rows of 64 pixels, each 0 or 1
+    #[getter]
+    fn framebuffer(&self) -> Vec<Vec<u32>> {
+        // not u8, which would turn each row into bytes
+        (0..HEIGHT).map(|y| (0..WIDTH).map(|x| u32::from(self.state.framebuffer[(y * WIDTH + x) / 8] >> (7 - x % 8)) & 1).collect()).collect()
+    }
+
+    /// The display as packed bits: 8 bytes per row, leftmost pixel in the most significant bit
+    #[getter]
+    fn framebuffer_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.framebuffer)
+    }
+
+    /// Captures the machine, for `load_state`.
+    fn save_state<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.state.save_state())
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> PyResult<()> {
+        self.state.load_state(data).map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+}
+
+impl Chip8 {
+    /// Runs emulation, raising `EmulatorError` if the program crashes.
+    fn run(&mut self, emulate: impl FnOnce(&mut Chip8State)) -> PyResult<()> {
+        let state = &mut self.state;
+        panic::catch_unwind(AssertUnwindSafe(|| emulate(state)))
+            .map_err(|panic| EmulatorError::new_err(format!("crashed at {:03X}: {}", state.pc, panic_message(&*panic))))?;
+
+        match state.stack_error {
+            Some(err) => Err(EmulatorError::new_err(format!("crashed at {:03X}: {err}", state.pc))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn check_key(key: u8) -> PyResult<u8> {
+    if key < 16 {
+        Ok(key)
+    } else {
+        Err(PyValueError::new_err(format!("{key} is not a CHIP-8 key, expected 0 to 15")))
+    }
+}
+
+/// Python bindings for the chip8exe CHIP-8 emulator.
+#[pymodule]
+fn chip8(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Chip8>()?;
+    m.add("EmulatorError", m.py().get_type::<EmulatorError>())?;
+    Ok(())
+}
@@ -0,0 +1,142 @@
+import pytest
+
+import chip8
+
+# 200: LDI(206)  202: DRW(V0, V0, 1)  204: JP(204)  206: sprite 10000001
+DRAW_LINE = bytes([0xA2, 0x06, 0xD0, 0x01, 0x12, 0x04, 0x81])
+# 200: LDVB(V1, 3)  202: SHR(V0, V1)  204: JP(204)
+SHIFT = bytes([0x61, 0x03, 0x80, 0x16, 0x12, 0x04])
+# 200: LDVK(V2)  202: JP(202)
+WAIT_KEY = bytes([0xF2, 0x0A, 0x12, 0x02])
+# 200: CALL(200)
+RECURSE = bytes([0x22, 0x00])
+# 200: RND(V0, FF)  202: JP(200)
+RANDOM = bytes([0xC0, 0xFF, 0x12, 0x00])
+
+
+@pytest.fixture
+def emu():
+    return chip8.Chip8()
+
+
+def test_load_and_step(emu):
+    emu.load_rom(DRAW_LINE)
+    assert emu.pc == 0x200
+
+    emu.step()
+
+    assert emu.pc == 0x202
+    assert emu.index == 0x206
+    assert emu.read_memory(0x206, 1) == DRAW_LINE[6:]
+
+
+def test_framebuffer(emu):
+    emu.load_rom(DRAW_LINE)
+    emu.run_frames(1)
+
+    screen = emu.framebuffer
+    assert len(screen) == 32 and all(len(row) == 64 for row in screen)
+    assert screen[0][:8] == [1, 0, 0, 0, 0, 0, 0, 1]
+    assert not any(screen[1])
+    assert emu.framebuffer_bytes[:2] == bytes([0x81, 0x00])
+    assert len(emu.framebuffer_bytes) == 256
+
+
+def test_quirks():
+    # the VIP shifts VY into VX, where the default shifts VX in place
+    for quirks, shifted in [(None, 0), ("vip", 1)]:
+        emu = chip8.Chip8(quirks=quirks)
+        emu.load_rom(SHIFT)
+        emu.run_frames(1)
+        assert emu.registers[0] == shifted
+        assert emu.registers[0xF] == shifted
+
+
+def test_keys(emu):
+    emu.load_rom(WAIT_KEY)
+    emu.run_frames(2)
+    assert emu.pc == 0x200
+
+    emu.press_key(0xB)
+    emu.step()
+    emu.release_key(0xB)
+
+    assert emu.registers[2] == 0xB
+    assert emu.pc == 0x202
+    with pytest.raises(ValueError):
+        emu.press_key(16)
+
+
+def test_memory_and_registers_are_bytes(emu):
+    emu.load_rom(DRAW_LINE)
+
+    assert isinstance(emu.memory, bytes) and len(emu.memory) == 4096
+    assert emu.memory[0x200:0x207] == DRAW_LINE
+    assert isinstance(emu.registers, bytes) and len(emu.registers) == 16
+
+
+def test_save_and_restore_state(emu):
+    emu.load_rom(DRAW_LINE)
+    emu.step()
+    saved = emu.save_state()
+    emu.run_frames(3)
+    assert emu.pc == 0x204
+
+    emu.load_state(saved)
+
+    assert emu.pc == 0x202
+    assert emu.framebuffer_bytes == bytes(256)
+    other = chip8.Chip8()
+    other.load_state(saved)
+    assert other.memory == emu.memory
+    with pytest.raises(ValueError):
+        emu.load_state(b"not a snapshot")
+
+
+def test_state_keeps_quirks_and_key_waits():
+    vip = chip8.Chip8(quirks="vip")
+    vip.load_rom(SHIFT)
+    saved = vip.save_state()
+
+    # a snapshot carries its quirks into an emulator created without them
+    emu = chip8.Chip8()
+    emu.load_state(saved)
+    emu.run_frames(1)
+    assert emu.registers[0] == 1
+
+    # and a key wait in progress
+    emu.load_rom(WAIT_KEY)
+    emu.run_frames(1)
+    waiting = emu.save_state()
+    emu.press_key(0x3)
+    emu.run_frames(1)
+    assert emu.registers[2] == 0x3
+    emu.release_key(0x3)
+    emu.load_state(waiting)
+    emu.run_frames(1)
+    assert emu.registers[2] == 0
+    assert emu.pc == 0x200
+
+
+def test_crash_raises(emu):
+    emu.load_rom(RECURSE)
+
+    with pytest.raises(chip8.EmulatorError, match="stack overflow"):
+        emu.run_frames(10)
+    assert len(emu.call_stack) == 32
+
+
+def test_seed_is_reproducible():
+    first, second = chip8.Chip8(seed=7), chip8.Chip8(seed=7)
+    for emu in (first, second):
+        emu.load_rom(RANDOM)
+        emu.run_frames(1)
+
+    assert first.registers == second.registers
+
+
+def test_rejects_bad_arguments():
+    with pytest.raises(ValueError):
+        chip8.Chip8(quirks="megachip")
+    with pytest.raises(ValueError):
+        chip8.Chip8().load_rom(bytes(4000))
@@ -0,0 +1,378 @@
+//! A libretro core, so libretro frontends such as RetroArch can run CHIP-8 programs.
+//! A frontend loads one core library at a time and drives it through these entry points, so the loaded game lives in a static.
+
+mod libretro;
+
+use std::{ffi::{c_char, c_void, CString}, panic::{self, AssertUnwindSafe}, ptr, slice, sync::{Mutex, MutexGuard, PoisonError}};
+
+use chip8exe::{frontend::{panic_message, DEFAULT_KEYMAP}, romdb::RomDb, Chip8State};
+use libretro::*;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: u32 = 44_100;
+/// Stereo audio frames played per video frame
+const AUDIO_FRAMES: usize = (SAMPLE_RATE / 60) as usize;
+const TONE_HZ: u32 = 440;
+const VOLUME: i16 = 0x1000;
+const DEFAULT_IPF: u32 = 10;
+const LIT: u32 = 0x00FF_FFFF;
+const UNLIT: u32 = 0x0000_0000;
+
+/// The joypad button for each key. The d-pad gives 2, 4, 6 and 8 and A gives 5, the keys most games move and act with.
+const JOYPAD_MAP: [u32; 16] = [
+    RETRO_DEVICE_ID_JOYPAD_B, RETRO_DEVICE_ID_JOYPAD_L, RETRO_DEVICE_ID_JOYPAD_UP, RETRO_DEVICE_ID_JOYPAD_R,
+    RETRO_DEVICE_ID_JOYPAD_LEFT, RETRO_DEVICE_ID_JOYPAD_A, RETRO_DEVICE_ID_JOYPAD_RIGHT, RETRO_DEVICE_ID_JOYPAD_L2,
+    RETRO_DEVICE_ID_JOYPAD_DOWN, RETRO_DEVICE_ID_JOYPAD_R2, RETRO_DEVICE_ID_JOYPAD_Y, RETRO_DEVICE_ID_JOYPAD_X,
+    RETRO_DEVICE_ID_JOYPAD_L3, RETRO_DEVICE_ID_JOYPAD_R3, RETRO_DEVICE_ID_JOYPAD_SELECT, RETRO_DEVICE_ID_JOYPAD_START,
+];
+
+static FRONTEND: Mutex<Frontend> = Mutex::new(Frontend {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+    log: None,
+});
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+/// The callbacks the frontend registered
+#[derive(Clone, Copy)]
+struct Frontend {
+    environment: Option<retro_environment_t>,
+    video_refresh: Option<retro_video_refresh_t>,
+    audio_sample_batch: Option<retro_audio_sample_batch_t>,
+    input_poll: Option<retro_input_poll_t>,
+    input_state: Option<retro_input_state_t>,
+    log: Option<retro_log_printf_t>,
+}
+
+impl Frontend {
+    /// The keys held on the first joypad or the keyboard, bit N standing for key N
+    fn keys(&self) -> u16 {
+        let Some(input_state) = self.input_state else {
+            return 0;
+        };
+
+        // the keyboard uses the debugger's default layout; libretro numbers these keys by their ASCII codes
+        (0..16).filter(|&key| {
+            input_state(0, RETRO_DEVICE_JOYPAD, 0, JOYPAD_MAP[key]) != 0 || input_state(0, RETRO_DEVICE_KEYBOARD, 0, u32::from(DEFAULT_KEYMAP[key])) != 0
+        }).fold(0, |keys, key| keys | 1 << key)
+    }
+
+    fn log_error(&self, message: &str) {
+        match (self.log, CString::new(message)) {
+            (Some(log), Ok(message)) => unsafe { log(RETRO_LOG_ERROR, c"%s\n".as_ptr(), message.as_ptr()) },
+            _ => eprintln!("{message}"),
+        }
+    }
+}
+
+struct Core {
+    state: Chip8State,
+    rom: Vec<u8>,
+    ipf: u32,
+    crashed: bool,
+    video: Vec<u32>,
+    audio: Vec<i16>,
+    /// Audio frames into the current second of the tone
+    tone_phase: u32,
+}
+
+impl Core {
+    fn new(state: Chip8State, rom: Vec<u8>, ipf: u32) -> Self {
+        Self { state, rom, ipf, crashed: false, video: vec![UNLIT; WIDTH * HEIGHT], audio: vec![0; AUDIO_FRAMES * 2], tone_phase: 0 }
+    }
+
+    /// Runs one frame with `keys` held. Returns the error if the program crashed during it, after which it is halted until reset.
+    fn run_frame(&mut self, keys: u16) -> Option<String> {
+        self.state.input = keys;
+        if self.crashed {
+            return None;
+        }
+
+        let state = &mut self.state;
+        let ipf = self.ipf;
+        let error = match panic::catch_unwind(AssertUnwindSafe(|| state.run_frame(ipf))) {
+            Err(panic) => Some(panic_message(&*panic)),
+            Ok(()) => self.state.stack_error.map(|err| err.to_string()),
+        };
+
+        self.crashed = error.is_some();
+        error.map(|error| format!("CHIP-8 program crashed at {:03X}: {error}", self.state.pc))
+    }
+
+    fn render(&mut self) {
+        for (i, pixel) in self.video.iter_mut().enumerate() {
+            *pixel = if (self.state.framebuffer[i / 8] >> (7 - i % 8)) & 1 != 0 { LIT } else { UNLIT };
+        }
+    }
+
+    /// Fills the audio buffer with a square wave while the sound timer runs, and silence otherwise.
+    fn mix_audio(&mut self) {
+        let beeping = self.state.st > 0;
+        for frame in self.audio.chunks_exact_mut(2) {
+            let high = (self.tone_phase * 2 * TONE_HZ / SAMPLE_RATE).is_multiple_of(2);
+            frame.fill(match (beeping, high) {
+                (false, _) => 0,
+                (true, true) => VOLUME,
+                (true, false) => -VOLUME,
+            });
+            self.tone_phase = (self.tone_phase + 1) % SAMPLE_RATE;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state.reset();
+        // it fit when the game was loaded
+        let _ = self.state.load_program(&self.rom);
+        self.crashed = false;
+    }
+}
+
+fn frontend() -> Frontend {
+    *FRONTEND.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn set_frontend(update: impl FnOnce(&mut Frontend)) {
+    update(&mut FRONTEND.lock().unwrap_or_else(PoisonError::into_inner));
+}
+
+fn core() -> MutexGuard<'static, Option<Core>> {
+    CORE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> u32 {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: retro_environment_t) {
+    let mut log_callback = retro_log_callback { log: None };
+    let log = if callback(RETRO_ENVIRONMENT_GET_LOG_INTERFACE, ptr::addr_of_mut!(log_callback).cast()) { log_callback.log } else { None };
+    set_frontend(|frontend| {
+        frontend.environment = Some(callback);
+        frontend.log = log;
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: retro_video_refresh_t) {
+    set_frontend(|frontend| frontend.video_refresh = Some(callback));
+}
+
+/// Unused: audio is sent a frame at a time through the batch callback.
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: retro_audio_sample_t) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: retro_audio_sample_batch_t) {
+    set_frontend(|frontend| frontend.audio_sample_batch = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: retro_input_poll_t) {
+    set_frontend(|frontend| frontend.input_poll = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: retro_input_state_t) {
+    set_frontend(|frontend| frontend.input_state = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *core() = None;
+}
+
+/// # Safety
+///
+/// `info` must be null or point to a writable `retro_system_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut retro_system_info) {
+    if let Some(info) = info.as_mut() {
+        *info = retro_system_info {
+            library_name: c"chip8exe".as_ptr(),
+            library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast(),
+            valid_extensions: c"ch8|c8".as_ptr(),
+            need_fullpath: false,
+            block_extract: false,
+        };
+    }
+}
+
+/// # Safety
+///
+/// `info` must be null or point to a writable `retro_system_av_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut retro_system_av_info) {
+    if let Some(info) = info.as_mut() {
+        *info = retro_system_av_info {
+            geometry: retro_game_geometry {
+                base_width: WIDTH as u32,
+                base_height: HEIGHT as u32,
+                max_width: WIDTH as u32,
+                max_height: HEIGHT as u32,
+                aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+            },
+            timing: retro_system_timing { fps: FPS, sample_rate: f64::from(SAMPLE_RATE) },
+        };
+    }
+}
+
+/// Only the joypad and keyboard are read, whatever is plugged in.
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: u32, _device: u32) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = core().as_mut() {
+        core.reset();
+    }
+}
+
+/// Runs a frame at the game's instructions per frame, then sends its picture and sound to the frontend.
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let frontend = frontend();
+    if let Some(input_poll) = frontend.input_poll {
+        input_poll();
+    }
+    let keys = frontend.keys();
+
+    let mut core = core();
+    let Some(core) = core.as_mut() else {
+        return;
+    };
+    if let Some(error) = core.run_frame(keys) {
+        frontend.log_error(&error);
+    }
+
+    core.render();
+    core.mix_audio();
+    if let Some(video_refresh) = frontend.video_refresh {
+        video_refresh(core.video.as_ptr().cast(), WIDTH as u32, HEIGHT as u32, WIDTH * 4);
+    }
+    if let Some(audio_sample_batch) = frontend.audio_sample_batch {
+        audio_sample_batch(core.audio.as_ptr(), AUDIO_FRAMES);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    core().as_ref().map_or(0, |core| core.state.save_state().len())
+}
+
+/// Writes a `Chip8State::save_state` snapshot.
+///
+/// # Safety
+///
+/// `data` must point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let core = core();
+    let Some(core) = core.as_ref() else {
+        return false;
+    };
+
+    let snapshot = core.state.save_state();
+    if data.is_null() || size < snapshot.len() {
+        return false;
+    }
+    slice::from_raw_parts_mut(data.cast::<u8>(), snapshot.len()).copy_from_slice(&snapshot);
+    true
+}
+
+/// # Safety
+///
+/// `data` must point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let mut core = core();
+    let (Some(core), false) = (core.as_mut(), data.is_null()) else {
+        return false;
+    };
+
+    let loaded = core.state.load_state(slice::from_raw_parts(data.cast::<u8>(), size)).is_ok();
+    core.crashed = core.state.stack_error.is_some();
+    loaded
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: u32, _enabled: bool, _code: *const c_char) {}
+
+/// Loads a ROM from memory, with the quirks and instructions per frame the ROM database lists for it.
+///
+/// # Safety
+///
+/// `game` must be null or point to a `retro_game_info` whose `data` points to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const retro_game_info) -> bool {
+    let frontend = frontend();
+    let Some(game) = game.as_ref().filter(|game| !game.data.is_null()) else {
+        frontend.log_error("chip8exe needs the ROM's contents to load it");
+        return false;
+    };
+    let rom = slice::from_raw_parts(game.data.cast::<u8>(), game.size);
+
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !frontend.environment.is_some_and(|environment| environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, ptr::addr_of_mut!(format).cast())) {
+        frontend.log_error("the frontend does not support XRGB8888 video");
+        return false;
+    }
+
+    let db = RomDb::embedded();
+    let mut state = Chip8State::default();
+    let found = match state.load_known_program(rom, &db) {
+        Ok(found) => found,
+        Err(err) => {
+            frontend.log_error(&format!("could not load the ROM: {err}"));
+            return false;
+        },
+    };
+    let ipf = found.and_then(|found| found.tickrate).unwrap_or(DEFAULT_IPF);
+
+    *core() = Some(Core::new(state, rom.to_vec(), ipf));
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: u32, _info: *const retro_game_info, _num_info: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *core() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> u32 {
+    RETRO_REGION_NTSC
+}
+
+/// The 4 KiB of CHIP-8 memory count as system RAM, for cheats and achievements.
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: u32) -> *mut c_void {
+    match core().as_mut() {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => core.state.memory.as_mut_ptr().cast(),
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: u32) -> usize {
+    match core().as_ref() {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => core.state.memory.len(),
+        _ => 0,
+    }
+}
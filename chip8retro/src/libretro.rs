@@ -0,0 +1,86 @@
+//! The parts of `libretro.h` this core uses.
+
+#![allow(non_camel_case_types)]
+
+use std::ffi::{c_char, c_void};
+
+pub const RETRO_API_VERSION: u32 = 1;
+
+pub const RETRO_DEVICE_JOYPAD: u32 = 1;
+pub const RETRO_DEVICE_KEYBOARD: u32 = 3;
+
+pub const RETRO_DEVICE_ID_JOYPAD_B: u32 = 0;
+pub const RETRO_DEVICE_ID_JOYPAD_Y: u32 = 1;
+pub const RETRO_DEVICE_ID_JOYPAD_SELECT: u32 = 2;
+pub const RETRO_DEVICE_ID_JOYPAD_START: u32 = 3;
+pub const RETRO_DEVICE_ID_JOYPAD_UP: u32 = 4;
+pub const RETRO_DEVICE_ID_JOYPAD_DOWN: u32 = 5;
+pub const RETRO_DEVICE_ID_JOYPAD_LEFT: u32 = 6;
+pub const RETRO_DEVICE_ID_JOYPAD_RIGHT: u32 = 7;
+pub const RETRO_DEVICE_ID_JOYPAD_A: u32 = 8;
+pub const RETRO_DEVICE_ID_JOYPAD_X: u32 = 9;
+pub const RETRO_DEVICE_ID_JOYPAD_L: u32 = 10;
+pub const RETRO_DEVICE_ID_JOYPAD_R: u32 = 11;
+pub const RETRO_DEVICE_ID_JOYPAD_L2: u32 = 12;
+pub const RETRO_DEVICE_ID_JOYPAD_R2: u32 = 13;
+pub const RETRO_DEVICE_ID_JOYPAD_L3: u32 = 14;
+pub const RETRO_DEVICE_ID_JOYPAD_R3: u32 = 15;
+
+pub const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: u32 = 10;
+pub const RETRO_ENVIRONMENT_GET_LOG_INTERFACE: u32 = 27;
+
+pub const RETRO_PIXEL_FORMAT_XRGB8888: u32 = 1;
+pub const RETRO_LOG_ERROR: u32 = 3;
+pub const RETRO_MEMORY_SYSTEM_RAM: u32 = 2;
+pub const RETRO_REGION_NTSC: u32 = 0;
+
+pub type retro_environment_t = extern "C" fn(cmd: u32, data: *mut c_void) -> bool;
+pub type retro_video_refresh_t = extern "C" fn(data: *const c_void, width: u32, height: u32, pitch: usize);
+pub type retro_audio_sample_t = extern "C" fn(left: i16, right: i16);
+pub type retro_audio_sample_batch_t = extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type retro_input_poll_t = extern "C" fn();
+pub type retro_input_state_t = extern "C" fn(port: u32, device: u32, index: u32, id: u32) -> i16;
+pub type retro_log_printf_t = unsafe extern "C" fn(level: u32, fmt: *const c_char, ...);
+
+#[repr(C)]
+pub struct retro_log_callback {
+    pub log: Option<retro_log_printf_t>,
+}
+
+#[repr(C)]
+pub struct retro_system_info {
+    pub library_name: *const c_char,
+    pub library_version: *const c_char,
+    pub valid_extensions: *const c_char,
+    pub need_fullpath: bool,
+    pub block_extract: bool,
+}
+
+#[repr(C)]
+pub struct retro_game_geometry {
+    pub base_width: u32,
+    pub base_height: u32,
+    pub max_width: u32,
+    pub max_height: u32,
+    pub aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct retro_system_timing {
+    pub fps: f64,
+    pub sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct retro_system_av_info {
+    pub geometry: retro_game_geometry,
+    pub timing: retro_system_timing,
+}
+
+#[repr(C)]
+pub struct retro_game_info {
+    pub path: *const c_char,
+    pub data: *const c_void,
+    pub size: usize,
+    pub meta: *const c_char,
+}
@@ -0,0 +1,224 @@
+//! A minimal libretro frontend: loads the core library the way RetroArch would and runs frames through it.
+
+use std::{env, ffi::{c_char, c_void, CStr}, ptr, slice, sync::{atomic::{AtomicU32, Ordering}, Mutex}};
+
+use libloading::{library_filename, Library, Symbol};
+
+const SET_PIXEL_FORMAT: u32 = 10;
+const PIXEL_FORMAT_XRGB8888: u32 = 1;
+const DEVICE_JOYPAD: u32 = 1;
+const DEVICE_KEYBOARD: u32 = 3;
+const JOYPAD_UP: u32 = 4;
+const MEMORY_SYSTEM_RAM: u32 = 2;
+
+// 200: LDVK(V0)  202: LDF(V0)  204: DRW(V1, V1, 5)  206: JP(206)
+const DRAW_KEY: [u8; 8] = [0xF0, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06];
+// 200: LDVB(V0, 30)  202: LDST(V0)  204: JP(204)
+const BEEP: [u8; 6] = [0x60, 0x1E, 0xF0, 0x18, 0x12, 0x04];
+
+/// The core is a single global instance, so the tests take turns with it
+static CORE_LOCK: Mutex<()> = Mutex::new(());
+static PIXEL_FORMAT: AtomicU32 = AtomicU32::new(u32::MAX);
+static HELD_BUTTONS: AtomicU32 = AtomicU32::new(0);
+static HELD_KEY: AtomicU32 = AtomicU32::new(0);
+static SCREEN: Mutex<Vec<u32>> = Mutex::new(vec![]);
+static AUDIO: Mutex<Vec<i16>> = Mutex::new(vec![]);
+
+#[repr(C)]
+struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+extern "C" fn environment(cmd: u32, data: *mut c_void) -> bool {
+    match cmd {
+        SET_PIXEL_FORMAT => {
+            PIXEL_FORMAT.store(unsafe { *data.cast::<u32>() }, Ordering::SeqCst);
+            true
+        },
+        _ => false,
+    }
+}
+
+extern "C" fn video_refresh(data: *const c_void, width: u32, height: u32, pitch: usize) {
+    assert_eq!((width, height, pitch), (64, 32, 256));
+    *SCREEN.lock().unwrap() = unsafe { slice::from_raw_parts(data.cast::<u32>(), 64 * 32) }.to_vec();
+}
+
+extern "C" fn audio_sample(_left: i16, _right: i16) {}
+
+extern "C" fn audio_sample_batch(data: *const i16, frames: usize) -> usize {
+    AUDIO.lock().unwrap().extend_from_slice(unsafe { slice::from_raw_parts(data, frames * 2) });
+    frames
+}
+
+extern "C" fn input_poll() {}
+
+extern "C" fn input_state(port: u32, device: u32, _index: u32, id: u32) -> i16 {
+    i16::from(port == 0 && match device {
+        DEVICE_JOYPAD => HELD_BUTTONS.load(Ordering::SeqCst) & 1 << id != 0,
+        DEVICE_KEYBOARD => HELD_KEY.load(Ordering::SeqCst) == id,
+        _ => false,
+    })
+}
+
+struct Frontend {
+    core: Library,
+}
+
+impl Frontend {
+    fn load() -> Self {
+        // cargo builds the core next to the test executable, in target/<profile>/deps
+        let dir = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+        let core = unsafe { Library::new(dir.join(library_filename("chip8_libretro"))) }.unwrap();
+        let frontend = Self { core };
+
+        unsafe {
+            frontend.get::<extern "C" fn(extern "C" fn(u32, *mut c_void) -> bool)>(b"retro_set_environment")(environment);
+            frontend.get::<extern "C" fn(extern "C" fn(*const c_void, u32, u32, usize))>(b"retro_set_video_refresh")(video_refresh);
+            frontend.get::<extern "C" fn(extern "C" fn(i16, i16))>(b"retro_set_audio_sample")(audio_sample);
+            frontend.get::<extern "C" fn(extern "C" fn(*const i16, usize) -> usize)>(b"retro_set_audio_sample_batch")(audio_sample_batch);
+            frontend.get::<extern "C" fn(extern "C" fn())>(b"retro_set_input_poll")(input_poll);
+            frontend.get::<extern "C" fn(extern "C" fn(u32, u32, u32, u32) -> i16)>(b"retro_set_input_state")(input_state);
+            frontend.get::<extern "C" fn()>(b"retro_init")();
+        }
+        frontend
+    }
+
+    unsafe fn get<T>(&self, name: &[u8]) -> Symbol<'_, T> {
+        self.core.get(name).unwrap()
+    }
+
+    fn load_game(&self, rom: &[u8]) -> bool {
+        let game = GameInfo { path: ptr::null(), data: rom.as_ptr().cast(), size: rom.len(), meta: ptr::null() };
+        unsafe { self.get::<unsafe extern "C" fn(*const GameInfo) -> bool>(b"retro_load_game")(&game) }
+    }
+
+    fn run(&self, frames: u32) {
+        for _ in 0..frames {
+            unsafe { self.get::<extern "C" fn()>(b"retro_run")() };
+        }
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        unsafe {
+            let mut data = vec![0; self.get::<extern "C" fn() -> usize>(b"retro_serialize_size")()];
+            assert!(self.get::<unsafe extern "C" fn(*mut c_void, usize) -> bool>(b"retro_serialize")(data.as_mut_ptr().cast(), data.len()));
+            data
+        }
+    }
+
+    fn unserialize(&self, data: &[u8]) -> bool {
+        unsafe { self.get::<unsafe extern "C" fn(*const c_void, usize) -> bool>(b"retro_unserialize")(data.as_ptr().cast(), data.len()) }
+    }
+
+    /// The top left corner of the last frame, as rows of `#` and `.`
+    fn corner() -> Vec<String> {
+        let screen = SCREEN.lock().unwrap();
+        screen.chunks(64).take(5).map(|row| row[..4].iter().map(|&pixel| if pixel == 0 { '.' } else { '#' }).collect()).collect()
+    }
+}
+
+impl Drop for Frontend {
+    fn drop(&mut self) {
+        unsafe {
+            self.get::<extern "C" fn()>(b"retro_unload_game")();
+            self.get::<extern "C" fn()>(b"retro_deinit")();
+        }
+    }
+}
+
+#[test]
+fn test_run_and_serialize() {
+    let _lock = CORE_LOCK.lock().unwrap();
+    let frontend = Frontend::load();
+
+    let mut info = SystemInfo { library_name: ptr::null(), library_version: ptr::null(), valid_extensions: ptr::null(), need_fullpath: true, block_extract: true };
+    unsafe { frontend.get::<unsafe extern "C" fn(*mut SystemInfo)>(b"retro_get_system_info")(&mut info) };
+    assert_eq!(unsafe { CStr::from_ptr(info.library_name) }, c"chip8exe");
+    assert!(!info.need_fullpath);
+
+    AUDIO.lock().unwrap().clear();
+    assert!(frontend.load_game(&BEEP));
+    assert_eq!(PIXEL_FORMAT.load(Ordering::SeqCst), PIXEL_FORMAT_XRGB8888);
+    frontend.run(1);
+    let saved = frontend.serialize();
+    frontend.run(40);
+
+    let memory = unsafe { frontend.get::<extern "C" fn(u32) -> *mut c_void>(b"retro_get_memory_data")(MEMORY_SYSTEM_RAM) };
+    let memory = unsafe { slice::from_raw_parts_mut(memory.cast::<u8>(), 4096) };
+    assert_eq!(memory[0x200..0x206], BEEP);
+    memory[0x300] = 0xAA;
+
+    // the restored state is still beeping
+    assert!(frontend.unserialize(&saved));
+    assert_eq!(memory[0x300], 0);
+    AUDIO.lock().unwrap().clear();
+    frontend.run(1);
+    assert!(AUDIO.lock().unwrap().iter().any(|&sample| sample != 0));
+    assert!(!frontend.unserialize(b"not a snapshot"));
+}
+
+#[test]
+fn test_joypad_input() {
+    let _lock = CORE_LOCK.lock().unwrap();
+    let frontend = Frontend::load();
+
+    assert!(frontend.load_game(&DRAW_KEY));
+    frontend.run(2);
+    assert_eq!(Frontend::corner(), ["....", "....", "....", "....", "...."]);
+
+    // up is key 2
+    HELD_BUTTONS.store(1 << JOYPAD_UP, Ordering::SeqCst);
+    frontend.run(1);
+    HELD_BUTTONS.store(0, Ordering::SeqCst);
+    frontend.run(1);
+
+    assert_eq!(Frontend::corner(), ["####", "...#", "####", "#...", "####"]);
+}
+
+#[test]
+fn test_keyboard_input() {
+    let _lock = CORE_LOCK.lock().unwrap();
+    let frontend = Frontend::load();
+
+    assert!(frontend.load_game(&DRAW_KEY));
+    frontend.run(2);
+
+    // W is key 5 in the default layout
+    HELD_KEY.store(u32::from(b'w'), Ordering::SeqCst);
+    frontend.run(1);
+    HELD_KEY.store(0, Ordering::SeqCst);
+    frontend.run(1);
+
+    assert_eq!(Frontend::corner(), ["####", "#...", "####", "...#", "####"]);
+}
+
+#[test]
+fn test_sound() {
+    let _lock = CORE_LOCK.lock().unwrap();
+    let frontend = Frontend::load();
+    AUDIO.lock().unwrap().clear();
+
+    assert!(frontend.load_game(&BEEP));
+    frontend.run(1);
+    let beeping = AUDIO.lock().unwrap().split_off(0);
+    frontend.run(40);
+    let after = AUDIO.lock().unwrap().split_off(0);
+
+    // 735 stereo frames per 60 Hz frame at 44.1 kHz
+    assert_eq!(beeping.len(), 735 * 2);
+    assert!(beeping.iter().any(|&sample| sample != 0));
+    assert!(after[after.len() - 735 * 2..].iter().all(|&sample| sample == 0));
+}
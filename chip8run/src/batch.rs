@@ -0,0 +1,179 @@
+use std::{fmt::Write, fs, io, panic::{self, AssertUnwindSafe}, path::{Path, PathBuf}, sync::{Mutex, atomic::{AtomicUsize, Ordering}}, thread, time::{Duration, Instant}};
+
+use chip8exe::{Chip8State, frontend::panic_message, opcode::Opcode, romdb};
+use serde::Serialize;
+
+/// File extensions of the ROMs picked up from a directory
+const ROM_EXTENSIONS: [&str; 4] = ["ch8", "c8", "sc8", "xo8"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    Ok,
+    Crashed(String),
+    /// Ran out of wall-clock time before finishing its frames
+    Timeout,
+    /// Could not be loaded at all
+    Failed(String),
+}
+
+impl Status {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Crashed(_) => "crashed",
+            Self::Timeout => "timeout",
+            Self::Failed(_) => "failed",
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            Self::Crashed(message) | Self::Failed(message) => message,
+            Self::Ok | Self::Timeout => "",
+        }
+    }
+}
+
+/// What running one ROM of a batch did
+#[derive(Clone, Debug)]
+pub struct RomReport {
+    /// Path of the ROM relative to the batch directory
+    pub rom: String,
+    pub status: Status,
+    pub frames: u32,
+    pub instructions: u64,
+    /// Instructions executed that decode to no known opcode
+    pub invalid_opcodes: u64,
+    /// SHA-1 of the framebuffer when the run ended
+    pub screen_hash: String,
+}
+
+impl RomReport {
+    pub fn failed(rom: String, message: String) -> Self {
+        Self { rom, status: Status::Failed(message), frames: 0, instructions: 0, invalid_opcodes: 0, screen_hash: String::new() }
+    }
+}
+
+/// Lists the ROMs in `dir` and its subdirectories, sorted by path.
+pub fn find_roms(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut roms = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            roms.extend(find_roms(&path)?);
+        } else if path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| ROM_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())) {
+            roms.push(path);
+        }
+    }
+
+    roms.sort();
+    Ok(roms)
+}
+
+/// Runs `state` for `frames` frames of `ipf` instructions, giving up once `timeout` has passed.
+/// Panics are reported as crashes, so the caller should silence the panic hook.
+pub fn run_rom(rom: String, mut state: Chip8State, frames: u32, ipf: u32, timeout: Duration) -> RomReport {
+    let started = Instant::now();
+    let (mut frames_run, mut instructions, mut invalid_opcodes) = (0, 0, 0);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        for _ in 0..frames {
+            if started.elapsed() > timeout {
+                return Status::Timeout;
+            }
+            for i in 0..ipf {
+                // without a VIP to run it, machine code is skipped like an unknown instruction
+                let invalid = match state.decode_opcode() {
+                    Opcode::NOP => true,
+                    Opcode::SYS(_) => state.vip.is_none(),
+                    _ => false,
+                };
+                if invalid {
+                    invalid_opcodes += 1;
+                }
+                state.tick(u32::from(i == 0));
+                instructions += 1;
+                if let Some(err) = state.stack_error {
+                    return Status::Crashed(format!("{:03X}: {err}", state.pc));
+                }
+            }
+            frames_run += 1;
+        }
+        Status::Ok
+    }));
+    let status = result.unwrap_or_else(|panic| {
+        Status::Crashed(format!("{:03X}: {}", state.pc, panic_message(&*panic)))
+    });
+
+    RomReport { rom, status, frames: frames_run, instructions, invalid_opcodes, screen_hash: romdb::sha1_hex(&state.framebuffer) }
+}
+
+/// Calls `run` on every ROM from `jobs` threads, returning the reports in the order of `roms`.
+pub fn run_all<F>(roms: &[PathBuf], jobs: usize, run: F) -> Vec<RomReport>
+where
+    F: Fn(&Path) -> RomReport + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let reports = Mutex::new(vec![None; roms.len()]);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, roms.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(rom) = roms.get(i) else { break };
+                    let report = run(rom);
+                    reports.lock().unwrap_or_else(|err| err.into_inner())[i] = Some(report);
+                }
+            });
+        }
+    });
+
+    reports.into_inner().unwrap_or_else(|err| err.into_inner()).into_iter().flatten().collect()
+}
+
+/// One line per ROM, with a header line.
+pub fn to_csv(reports: &[RomReport]) -> String {
+    let mut csv = String::from("rom,status,error,frames,instructions,invalid_opcodes,screen_hash\n");
+    for report in reports {
+        let _ = writeln!(csv, "{},{},{},{},{},{},{}", csv_field(&report.rom), report.status.name(), csv_field(report.status.message()),
+            report.frames, report.instructions, report.invalid_opcodes, report.screen_hash);
+    }
+
+    csv
+}
+
+/// A JSON report entry, with the same fields as the CSV columns
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    rom: &'a str,
+    status: &'static str,
+    error: &'a str,
+    frames: u32,
+    instructions: u64,
+    invalid_opcodes: u64,
+    screen_hash: &'a str,
+}
+
+/// An array holding one object per ROM.
+pub fn to_json(reports: &[RomReport]) -> String {
+    let reports: Vec<_> = reports.iter().map(|report| JsonReport {
+        rom: &report.rom,
+        status: report.status.name(),
+        error: report.status.message(),
+        frames: report.frames,
+        instructions: report.instructions,
+        invalid_opcodes: report.invalid_opcodes,
+        screen_hash: &report.screen_hash,
+    }).collect();
+
+    serde_json::to_string_pretty(&reports).unwrap_or_default() + "\n"
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        String::from(text)
+    }
+}
@@ -0,0 +1,264 @@
+mod batch;
+mod report;
+
+use std::{fs, panic::{self, AssertUnwindSafe}, path::{Path, PathBuf}, process::ExitCode, thread, time::Duration};
+
+use chip8exe::{Chip8State, analysis::ControlFlowGraph, frontend::panic_message, movie::{Movie, MoviePlayer, MovieRecorder}, quirks::Quirks, romdb::{RomDb, RomMatch}, stack};
+use clap::{Parser, ValueEnum};
+use rand::{SeedableRng, rngs::StdRng};
+
+/// Instructions per frame for ROMs the ROM database does not know
+const DEFAULT_IPF: u32 = 10;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+}
+
+/// Runs a CHIP-8 ROM without a display, for a fixed number of 60 Hz frames.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// ROM file to run, or a directory whose ROMs (.ch8, .c8, .sc8, .xo8, also in subdirectories) all run as a batch
+    rom: PathBuf,
+    /// Frames to run; 60 make one second of emulated time
+    #[arg(short, long, default_value_t = 600)]
+    frames: u32,
+    /// Instructions per frame [default: from the ROM database, or 10]
+    #[arg(long)]
+    ipf: Option<u32>,
+    /// Quirk preset: default, vip, schip or xochip [default: from the ROM database]
+    #[arg(short, long)]
+    quirks: Option<String>,
+    /// Nested calls allowed before a CALL fails: 12 on the COSMAC VIP, 16 on SUPER-CHIP [default: 32]
+    #[arg(long, value_name = "DEPTH", value_parser = clap::value_parser!(u8).range(1..=i64::from(stack::MAX_DEPTH)))]
+    stack_depth: Option<u8>,
+    /// Keep return addresses in emulated memory below ECF, like the COSMAC VIP; holds at most 24 calls
+    #[arg(long)]
+    stack_in_memory: bool,
+    /// Directory holding platforms.json and programs.json of the CHIP-8 database, used instead of the built-in copy
+    #[arg(long, value_name = "DIR")]
+    rom_db: Option<PathBuf>,
+    /// Print where execution time went: hot spots, an opcode histogram and hot loops
+    #[arg(short, long)]
+    profile: bool,
+    /// Rows listed per profile section
+    #[arg(long, default_value_t = 10)]
+    top: usize,
+    /// Write which bytes ran as code and which were accessed as data to this file
+    #[arg(long, value_name = "FILE")]
+    coverage: Option<PathBuf>,
+    /// Analyze the ROM statically before running it: list suspicious code and write its control-flow graph to this Graphviz DOT file
+    #[arg(long, value_name = "FILE")]
+    cfg: Option<PathBuf>,
+    /// Seed for the random number generator, making runs reproducible [default: random, or 0 in a batch]
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Record the run's input and timing to this movie file, which --replay reproduces exactly
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Replay a movie recorded here or in the debugger, for its whole length instead of --frames, checking for desyncs
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+    /// Batch: ROMs run at the same time [default: one per CPU]
+    #[arg(short, long)]
+    jobs: Option<usize>,
+    /// Batch: seconds each ROM may run before it is stopped
+    #[arg(long, default_value_t = 10.0)]
+    timeout: f64,
+    /// Batch: file to write the report to, instead of standard output
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+    /// Batch: report format [default: from the report file extension, or csv]
+    #[arg(long)]
+    format: Option<Format>,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = if cli.rom.is_dir() { run_batch(&cli) } else { run(&cli) };
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("chip8run: {err}");
+            ExitCode::from(2)
+        },
+    }
+}
+
+fn load_db(cli: &Cli) -> Result<RomDb, String> {
+    match &cli.rom_db {
+        Some(dir) => RomDb::load_dir(dir).map_err(|err| format!("could not load the ROM database from {}: {err}", dir.display())),
+        None => Ok(RomDb::embedded()),
+    }
+}
+
+/// Loads a ROM with the options shared by single and batch runs, returning the instructions per frame to run it at.
+fn prepare<'db>(cli: &Cli, path: &Path, rom: &[u8], db: &'db RomDb) -> Result<(Chip8State, u32, Option<RomMatch<'db>>), String> {
+    let mut state = Chip8State::default();
+    let found = state.load_known_program(rom, db).map_err(|err| format!("could not load {}: {err}", path.display()))?;
+    if let Some(name) = &cli.quirks {
+        state.quirks = Quirks::preset(name)
+            .ok_or_else(|| format!("unknown quirk preset {name}, expected one of {}", Quirks::PRESET_NAMES.join(", ")))?;
+    }
+    if let Some(depth) = cli.stack_depth {
+        state.stack_config.max_depth = depth;
+    }
+    state.stack_config.in_memory = cli.stack_in_memory;
+    if let Some(seed) = cli.seed {
+        state.rng = StdRng::seed_from_u64(seed);
+    }
+    let ipf = cli.ipf.or(found.as_ref().and_then(|found| found.tickrate)).unwrap_or(DEFAULT_IPF);
+
+    Ok((state, ipf, found))
+}
+
+fn run(cli: &Cli) -> Result<ExitCode, String> {
+    let rom = fs::read(&cli.rom).map_err(|err| format!("could not read {}: {err}", cli.rom.display()))?;
+    let db = load_db(cli)?;
+
+    let (mut state, ipf, found) = prepare(cli, &cli.rom, &rom, &db)?;
+    if let Some(found) = &found {
+        println!("{}", found.describe());
+    }
+    if let Some(path) = &cli.cfg {
+        let graph = ControlFlowGraph::from_state(&state, rom.len());
+        print!("{}", report::analysis(&graph));
+        fs::write(path, graph.to_dot()).map_err(|err| format!("could not write {}: {err}", path.display()))?;
+    }
+    let mut player = match &cli.replay {
+        Some(path) => {
+            let movie = fs::read_to_string(path).map_err(|err| err.to_string())
+                .and_then(|text| text.parse::<Movie>().map_err(|err| err.to_string()))
+                .map_err(|err| format!("could not read movie {}: {err}", path.display()))?;
+            Some(MoviePlayer::start(movie, &mut state, &rom).map_err(|err| format!("could not replay {}: {err}", path.display()))?)
+        },
+        None => None,
+    };
+    let mut recorder = cli.record.as_ref().map(|_| MovieRecorder::start(&mut state, &rom, cli.seed.unwrap_or_else(rand::random)));
+    if cli.profile {
+        state.profiler = Some(Box::default());
+    }
+    if cli.coverage.is_some() {
+        state.coverage = Some(Box::default());
+    }
+    state.smc = Some(Box::default());
+
+    let old_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {
+        // reported below
+    }));
+    let mut frames_run = 0;
+    let mut desync = None;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        if let Some(player) = &mut player {
+            loop {
+                match player.tick(&mut state) {
+                    Ok(true) if state.stack_error.is_none() => {},
+                    Ok(_) => break,
+                    Err(err) => {
+                        desync = Some(err);
+                        break;
+                    },
+                }
+            }
+            return;
+        }
+        for _ in 0..cli.frames {
+            match &mut recorder {
+                Some(recorder) => recorder.run_frame(&mut state, ipf),
+                None => state.run_frame(ipf),
+            }
+            frames_run += 1;
+            if state.stack_error.is_some() {
+                break;
+            }
+        }
+    }));
+    panic::set_hook(old_hook);
+
+    match &player {
+        Some(player) => println!("Replayed {} of {} instructions", player.instructions(), player.movie().instructions()),
+        None => println!("Ran {frames_run} frames at {ipf} instructions per frame"),
+    }
+    if let (Some(path), Some(recorder)) = (&cli.record, recorder) {
+        fs::write(path, recorder.finish(&state).to_string()).map_err(|err| format!("could not write {}: {err}", path.display()))?;
+    }
+    let code = match result {
+        Ok(()) if desync.is_some() => {
+            println!("Movie {}", desync.map(|err| err.to_string()).unwrap_or_default());
+            ExitCode::FAILURE
+        },
+        Ok(()) => match state.stack_error {
+            Some(err) => {
+                println!("Crashed at {:03X}: {err}", state.pc);
+                ExitCode::FAILURE
+            },
+            None => ExitCode::SUCCESS,
+        },
+        Err(panic) => {
+            println!("Crashed at {:03X}: {}", state.pc, panic_message(&*panic));
+            ExitCode::FAILURE
+        },
+    };
+
+    if let Some(smc) = &state.smc {
+        print!("{}", report::self_modifying_code(smc, cli.top));
+    }
+    if let Some(profiler) = &state.profiler {
+        print!("{}", report::profile(profiler, &state, cli.top));
+    }
+    if let (Some(path), Some(coverage)) = (&cli.coverage, &state.coverage) {
+        fs::write(path, coverage.export()).map_err(|err| format!("could not write {}: {err}", path.display()))?;
+    }
+
+    Ok(code)
+}
+
+/// Runs every ROM in the directory for the same number of frames, in parallel, and writes one report line per ROM.
+fn run_batch(cli: &Cli) -> Result<ExitCode, String> {
+    if cli.record.is_some() || cli.replay.is_some() {
+        return Err(String::from("movies can only be recorded and replayed for a single ROM"));
+    }
+    let roms = batch::find_roms(&cli.rom).map_err(|err| format!("could not list {}: {err}", cli.rom.display()))?;
+    let db = load_db(cli)?;
+    let timeout = Duration::try_from_secs_f64(cli.timeout).map_err(|_| format!("{} is not a valid timeout", cli.timeout))?;
+    let jobs = cli.jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, usize::from));
+    let seed = cli.seed.unwrap_or(0);
+
+    let old_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {
+        // reported as crashes
+    }));
+    let reports = batch::run_all(&roms, jobs, |path| {
+        let name = path.strip_prefix(&cli.rom).unwrap_or(path).display().to_string();
+        let prepared = fs::read(path).map_err(|err| format!("could not read {}: {err}", path.display()))
+            .and_then(|rom| prepare(cli, path, &rom, &db).map(|(state, ipf, _)| (state, ipf)));
+        match prepared {
+            Ok((mut state, ipf)) => {
+                state.rng = StdRng::seed_from_u64(seed);
+                batch::run_rom(name, state, cli.frames, ipf, timeout)
+            },
+            Err(err) => batch::RomReport::failed(name, err),
+        }
+    });
+    panic::set_hook(old_hook);
+
+    let format = cli.format.unwrap_or(match cli.report.as_ref().and_then(|path| path.extension()) {
+        Some(ext) if ext.eq_ignore_ascii_case("json") => Format::Json,
+        _ => Format::Csv,
+    });
+    let text = match format {
+        Format::Csv => batch::to_csv(&reports),
+        Format::Json => batch::to_json(&reports),
+    };
+    match &cli.report {
+        Some(path) => fs::write(path, text).map_err(|err| format!("could not write {}: {err}", path.display()))?,
+        None => print!("{text}"),
+    }
+
+    let passed = reports.iter().filter(|report| report.status == batch::Status::Ok).count();
+    eprintln!("Ran {} ROMs: {passed} finished, {} did not", reports.len(), reports.len() - passed);
+    Ok(if passed == reports.len() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
@@ -0,0 +1,76 @@
+use std::fmt::Write;
+
+use chip8exe::{Chip8State, analysis::{ControlFlowGraph, Issue}, opcode::Opcode, profiler::Profiler, smc::SmcDetector};
+
+/// Formats the profile as plain text tables, listing at most `top` rows per table.
+pub fn profile(profiler: &Profiler, state: &Chip8State, top: usize) -> String {
+    let mut report = String::new();
+    let total = profiler.total();
+
+    let _ = writeln!(report, "\nInstructions executed: {total}");
+
+    let _ = writeln!(report, "\nHot spots\n  ADDR       HITS  SHARE  INSTRUCTION");
+    for (addr, hits) in profiler.hot_spots().into_iter().take(top) {
+        let _ = writeln!(report, "  {addr:03X}  {hits:>10}  {:>4.1}%  {:X?}", share(hits, total), decode(state, addr));
+    }
+
+    let _ = writeln!(report, "\nOpcodes\n  NAME       HITS  SHARE");
+    for (name, hits) in profiler.opcode_histogram().into_iter().take(top) {
+        let _ = writeln!(report, "  {name:<5}{hits:>10}  {:>4.1}%", share(hits, total));
+    }
+
+    let _ = writeln!(report, "\nHot loops\n  RANGE    ITERATIONS  INSTRUCTIONS  SHARE");
+    for hot_loop in profiler.hot_loops().into_iter().take(top) {
+        let _ = writeln!(report, "  {:03X}-{:03X} {:>10}  {:>12}  {:>4.1}%",
+            hot_loop.start, hot_loop.end, hot_loop.iterations, hot_loop.instructions, share(hot_loop.instructions, total));
+    }
+
+    report
+}
+
+/// Summarizes a control-flow graph and lists its issues, one per line.
+pub fn analysis(graph: &ControlFlowGraph) -> String {
+    let mut report = format!("Basic blocks: {}\n", graph.blocks.len());
+
+    for issue in &graph.issues {
+        let _ = match issue {
+            Issue::ComputedJump(addr) => writeln!(report, "  {addr:03X}  computed jump; its targets were not analyzed"),
+            Issue::Misaligned(addr) => writeln!(report, "  {addr:03X}  misaligned instruction"),
+            Issue::LeavesProgram { from, to } => writeln!(report, "  {from:03X}  control leaves the program for {to:03X}"),
+            Issue::MachineCode(addr) => writeln!(report, "  {addr:03X}  machine code call; not analyzed"),
+            Issue::UnknownInstruction(addr) => writeln!(report, "  {addr:03X}  unknown instruction"),
+            Issue::Unreachable { start, end } => writeln!(report, "  {start:03X}  unreachable through {:03X}", end - 1),
+        };
+    }
+
+    report
+}
+
+/// Lists the last `top` writes to code, if there were any.
+pub fn self_modifying_code(smc: &SmcDetector, top: usize) -> String {
+    let events = smc.events();
+    if events.is_empty() {
+        return String::new();
+    }
+
+    let mut report = format!("\nSelf-modifying code: {} writes\n  PC   ADDR  OLD  NEW\n", smc.count());
+    for event in events.iter().skip(events.len().saturating_sub(top)) {
+        let _ = writeln!(report, "  {:03X}  {:03X}   {:02X}   {:02X}", event.pc, event.address, event.old_value, event.new_value);
+    }
+
+    report
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn share(hits: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        hits as f64 * 100.0 / total as f64
+    }
+}
+
+fn decode(state: &Chip8State, addr: u16) -> Opcode {
+    let addr = usize::from(addr);
+    Opcode::decode(u16::from_be_bytes([state.memory[addr], state.memory[(addr + 1) % state.memory.len()]]), state.variant)
+}
@@ -0,0 +1,42 @@
+use std::{env, fs, process::Command};
+
+// 200: ADDVB(V0, 1)  202: JP(200)
+const LOOP: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+// 200: CALL(200)
+const RECURSE: [u8; 2] = [0x22, 0x00];
+// 200: RND(V0, 0F)  202: LDF(V0)  204: DRW(V1, V1, 5)  206: JP(200)
+const RANDOM: [u8; 8] = [0xC0, 0x0F, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x00];
+
+fn run_batch(dir: &std::path::Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8run"))
+        .arg(dir)
+        .args(["--frames", "4", "--ipf", "8", "--stack-depth", "12", "--jobs", "2", "--format", "csv"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_batch_report() {
+    let dir = env::temp_dir().join(format!("chip8run_batch_{}", std::process::id()));
+    fs::create_dir_all(dir.join("more")).unwrap();
+    fs::write(dir.join("loop.ch8"), LOOP).unwrap();
+    fs::write(dir.join("more/random.ch8"), RANDOM).unwrap();
+    fs::write(dir.join("recurse.ch8"), RECURSE).unwrap();
+    fs::write(dir.join("notes.txt"), "not a ROM").unwrap();
+
+    let report = run_batch(&dir);
+    let again = run_batch(&dir);
+    fs::remove_dir_all(dir).unwrap();
+
+    let lines: Vec<_> = report.lines().collect();
+    assert_eq!(lines.len(), 4, "{report}");
+    assert_eq!(lines[0], "rom,status,error,frames,instructions,invalid_opcodes,screen_hash");
+    assert!(lines[1].starts_with("loop.ch8,ok,,4,32,0,"), "{report}");
+    assert!(lines[2].starts_with("more/random.ch8,ok,,4,32,0,"), "{report}");
+    assert!(lines[3].starts_with("recurse.ch8,crashed,200: stack overflow: more than 12 nested calls,1,13,0,"), "{report}");
+    // a fixed seed makes random programs draw the same screen every time
+    assert_eq!(report, again)
+}
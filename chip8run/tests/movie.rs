@@ -0,0 +1,38 @@
+use std::{env, fs, path::Path, process::{Command, Output}};
+
+// 200: RND(V0, 0F)  202: LDF(V0)  204: DRW(V1, V1, 5)  206: JP(200)
+const RANDOM: [u8; 8] = [0xC0, 0x0F, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x00];
+
+fn chip8run(rom: &Path, args: &[&str], movie: &Path) -> (Output, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8run")).arg(rom).args(args).arg(movie).output().unwrap();
+    let stdout = String::from_utf8(output.stdout.clone()).unwrap();
+    (output, stdout)
+}
+
+#[test]
+fn test_record_replay() {
+    let dir = env::temp_dir().join(format!("chip8run_movie_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (rom, movie) = (dir.join("random.ch8"), dir.join("random.c8m"));
+    fs::write(&rom, RANDOM).unwrap();
+
+    let (output, _) = chip8run(&rom, &["--frames", "90", "--ipf", "8", "--seed", "5", "--record"], &movie);
+    assert!(output.status.success());
+    let text = fs::read_to_string(&movie).unwrap();
+    assert!(text.starts_with("CHIP-8 movie 2\n"), "{text}");
+    // one after 60 frames, and one at the end
+    assert_eq!(text.lines().filter(|line| line.starts_with("hash ")).count(), 2, "{text}");
+
+    let (output, report) = chip8run(&rom, &["--replay"], &movie);
+    assert!(output.status.success(), "{report}");
+    assert!(report.contains("Replayed 720 of 720 instructions"), "{report}");
+
+    // other random numbers draw other digits
+    fs::write(&movie, text.replacen("seed 5", "seed 6", 1)).unwrap();
+    let (output, report) = chip8run(&rom, &["--replay"], &movie);
+    fs::remove_dir_all(dir).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(report.contains("Replayed 473 of 720 instructions"), "{report}");
+    assert!(report.contains("Movie desync after 473 instructions"), "{report}")
+}
@@ -0,0 +1,24 @@
+use std::{env, fs, process::Command};
+
+// 200: ADDVB(V0, 1)  202: JP(200)
+const ROM: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+#[test]
+fn test_profile_report() {
+    let rom_path = env::temp_dir().join(format!("chip8run_profile_{}.ch8", std::process::id()));
+    fs::write(&rom_path, ROM).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8run"))
+        .arg(&rom_path)
+        .args(["--frames", "3", "--ipf", "10", "--profile"])
+        .output()
+        .unwrap();
+    fs::remove_file(rom_path).unwrap();
+
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.contains("Ran 3 frames at 10 instructions per frame"), "{report}");
+    assert!(report.contains("Instructions executed: 30"), "{report}");
+    assert!(report.contains("  200          15  50.0%  ADDVB(V0, 1)"), "{report}");
+    assert!(report.contains("  200-202         15            30  100.0%"), "{report}")
+}
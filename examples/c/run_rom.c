@@ -0,0 +1,53 @@
+/* Runs a CHIP-8 ROM through the C API for a number of frames, then prints the screen. */
+
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "chip8.h"
+
+#define INSTRUCTIONS_PER_FRAME 10
+
+int main(int argc, char **argv) {
+    if (argc < 2) {
+        fprintf(stderr, "usage: %s ROM [FRAMES]\n", argv[0]);
+        return 2;
+    }
+    int frames = argc > 2 ? atoi(argv[2]) : 60;
+
+    FILE *file = fopen(argv[1], "rb");
+    if (file == NULL) {
+        perror(argv[1]);
+        return 2;
+    }
+    uint8_t rom[4096];
+    size_t len = fread(rom, 1, sizeof rom, file);
+    fclose(file);
+
+    Chip8State *chip8 = chip8_new();
+    if (chip8_load_rom(chip8, rom, len) != CHIP8_OK) {
+        fprintf(stderr, "%s does not fit in memory\n", argv[1]);
+        chip8_free(chip8);
+        return 2;
+    }
+
+    int status = 0;
+    chip8_set_keys(chip8, 0);
+    for (int frame = 0; frame < frames; frame++) {
+        if (chip8_run_frame(chip8, INSTRUCTIONS_PER_FRAME) != CHIP8_OK) {
+            fprintf(stderr, "crashed in frame %d\n", frame);
+            status = 1;
+            break;
+        }
+    }
+
+    const uint8_t *framebuffer = chip8_framebuffer(chip8);
+    for (int y = 0; y < CHIP8_HEIGHT; y++) {
+        for (int x = 0; x < CHIP8_WIDTH; x++) {
+            putchar(framebuffer[(y * CHIP8_WIDTH + x) / 8] & (0x80 >> (x % 8)) ? '#' : '.');
+        }
+        putchar('\n');
+    }
+
+    chip8_free(chip8);
+    return status;
+}
@@ -0,0 +1,133 @@
+/* The chip8exe C API. Generated from src/capi.rs by cbindgen; see tests/header.rs. */
+
+#ifndef CHIP8_H
+#define CHIP8_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/** An emulator, created by chip8_new and released by chip8_free */
+typedef struct Chip8State Chip8State;
+
+/**
+ * Returned by functions that succeeded
+ */
+#define CHIP8_OK 0
+
+/**
+ * Returned by functions that failed
+ */
+#define CHIP8_ERROR -1
+
+/**
+ * Display width in pixels
+ */
+#define CHIP8_WIDTH 64
+
+/**
+ * Display height in pixels
+ */
+#define CHIP8_HEIGHT 32
+
+/**
+ * Size of the buffer `chip8_framebuffer` points to
+ */
+#define CHIP8_FRAMEBUFFER_SIZE 256
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an emulator, ready to load a ROM. Release it with `chip8_free`.
+ */
+Chip8State *chip8_new(void);
+
+/**
+ * Releases an emulator created by `chip8_new`. Does nothing if `state` is null.
+ *
+ * # Safety
+ *
+ * `state` must be null or come from `chip8_new`, and must not be used afterwards.
+ */
+void chip8_free(Chip8State *state);
+
+/**
+ * Resets the emulator and loads `len` bytes of ROM at 0x200. Returns `CHIP8_ERROR` if the ROM does not fit, or a pointer is null.
+ *
+ * # Safety
+ *
+ * `state` must come from `chip8_new`, and `rom` must point to `len` readable bytes.
+ */
+int32_t chip8_load_rom(Chip8State *state,
+                       const uint8_t *rom,
+                       size_t len);
+
+/**
+ * Sets which keys are held: bit N stands for key N. Does nothing if `state` is null.
+ *
+ * # Safety
+ *
+ * `state` must be null or come from `chip8_new`.
+ */
+void chip8_set_keys(Chip8State *state, uint16_t keys);
+
+/**
+ * The display, `CHIP8_HEIGHT` rows of `CHIP8_WIDTH` bits, leftmost pixel in the most significant bit.
+ * Valid until the emulator is freed. Null if `state` is null.
+ *
+ * # Safety
+ *
+ * `state` must be null or come from `chip8_new`.
+ */
+const uint8_t *chip8_framebuffer(const Chip8State *state);
+
+/**
+ * Whether the sound timer is running, meaning the host should play a tone. False if `state` is null.
+ *
+ * # Safety
+ *
+ * `state` must be null or come from `chip8_new`.
+ */
+bool chip8_sound_active(const Chip8State *state);
+
+/**
+ * Runs one 60 Hz frame of `instructions` instructions.
+ * Returns `CHIP8_ERROR` if the program crashed, after which it fails on the same instruction until reset or another ROM is
+ * loaded, or if `state` is null.
+ *
+ * # Safety
+ *
+ * `state` must be null or come from `chip8_new`.
+ */
+int32_t chip8_run_frame(Chip8State *state,
+                        uint32_t instructions);
+
+/**
+ * Counts the timers down `time_passed` times, then executes one instruction. A crash aborts the process.
+ * Does nothing if `state` is null.
+ *
+ * # Safety
+ *
+ * `state` must be null or come from `chip8_new`.
+ */
+void chip8_tick(Chip8State *state,
+                uint32_t time_passed);
+
+/**
+ * Clears the registers, timers, display and memory, unloading the ROM. Does nothing if `state` is null.
+ *
+ * # Safety
+ *
+ * `state` must be null or come from `chip8_new`.
+ */
+void chip8_reset(Chip8State *state);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* CHIP8_H */
@@ -0,0 +1,374 @@
+use std::{collections::{BTreeMap, BTreeSet}, fmt::Write};
+
+use crate::{Chip8State, opcode::Opcode, variant::Variant};
+
+/// How control passes from one basic block to another
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeKind {
+    /// The next instruction, after a block ends at a jump target or a skip does not skip
+    Fallthrough,
+    /// `JP`, or CHIP-8E's relative `JPB` and `JPF`
+    Jump,
+    /// `CALL`, to the subroutine
+    Call,
+    /// After a `CALL`, once the subroutine returns
+    CallReturn,
+    /// `RET`, back to the instruction after a `CALL` of the subroutine
+    Return,
+    /// A skip instruction (`SEVB`, `SKP`, CHIP-8E's `SKIP` and the like) skipping the next instruction
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Edge {
+    pub target: u16,
+    pub kind: EdgeKind,
+}
+
+/// Straight-line code entered only at `start`, left only after its last instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicBlock {
+    pub start: u16,
+    /// Address after the last instruction
+    pub end: u16,
+    /// Raw instruction words, with their addresses
+    pub instructions: Vec<(u16, u16)>,
+    pub edges: Vec<Edge>,
+}
+
+/// Something the analysis found worth a closer look
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Issue {
+    /// `JPV0`, or CHIP-8E's `SKB`, at this address jumps to a target only known at run time, so the code it reaches is not analyzed
+    ComputedJump(u16),
+    /// An instruction reached at an odd offset from the program start, so it overlaps the aligned instruction stream
+    Misaligned(u16),
+    /// Control reaches an address outside the program
+    LeavesProgram { from: u16, to: u16 },
+    /// `0NNN` at this address calls 1802 machine code, which is not analyzed
+    MachineCode(u16),
+    /// An instruction word that decodes to no known opcode
+    UnknownInstruction(u16),
+    /// Program bytes in `start..end` that no analyzed path reaches; data, or code behind a computed jump
+    Unreachable { start: u16, end: u16 },
+}
+
+/// Control-flow graph of a program, found by following every statically known path from its entry point.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ControlFlowGraph {
+    pub blocks: BTreeMap<u16, BasicBlock>,
+    pub issues: Vec<Issue>,
+    /// Instruction set the program was decoded as
+    pub variant: Variant,
+}
+
+impl ControlFlowGraph {
+    /// Analyzes a program of `len` bytes just loaded into `state`, at its layout's program start, entered at its program counter.
+    #[must_use]
+    pub fn from_state(state: &Chip8State, len: usize) -> Self {
+        let start = state.layout.program_start.min(0x1000);
+        #[allow(clippy::cast_possible_truncation)]
+        let end = start + len.min(0x1000 - usize::from(start)) as u16;
+        Self::build(&state.memory, start, end, state.pc, state.variant)
+    }
+
+    /// Analyzes the program occupying `memory[start..end]`, entered at `entry`, decoding it as `variant` does.
+    #[must_use]
+    pub fn build(memory: &[u8], start: u16, end: u16, entry: u16, variant: Variant) -> Self {
+        let fetch = |addr: u16| u16::from_be_bytes([memory[usize::from(addr)], memory[usize::from(addr + 1) % memory.len()]]);
+        let in_program = |addr: u16| addr >= start && addr.saturating_add(1) < end;
+
+        let mut graph = Self { variant, ..Self::default() };
+        let mut leaders = BTreeSet::from([entry]);
+        let mut reached = BTreeSet::new();
+        // subroutine entry to the return addresses of its calls
+        let mut callers: BTreeMap<u16, BTreeSet<u16>> = BTreeMap::new();
+        let mut pending = vec![entry];
+
+        // first pass: every reachable instruction, and where blocks must begin
+        while let Some(addr) = pending.pop() {
+            if !reached.insert(addr) {
+                continue;
+            }
+            if addr.checked_sub(start).is_some_and(|offset| offset % 2 == 1) {
+                graph.issues.push(Issue::Misaligned(addr));
+            }
+
+            let next = addr + 2;
+            let mut successors = vec![];
+            match resolve_jump(addr, Opcode::decode(fetch(addr), variant)) {
+                Opcode::JP(target) => {
+                    leaders.insert(target);
+                    successors.push(target);
+                },
+                Opcode::CALL(target) => {
+                    leaders.extend([target, next]);
+                    callers.entry(target).or_default().insert(next);
+                    successors.extend([target, next]);
+                },
+                Opcode::RET | Opcode::STOP => {},
+                Opcode::JPV0(_) | Opcode::SKB(_) => graph.issues.push(Issue::ComputedJump(addr)),
+                Opcode::SKIP => {
+                    leaders.insert(next + 2);
+                    successors.push(next + 2);
+                },
+                opcode if is_skip(&opcode) => {
+                    leaders.extend([next, next + 2]);
+                    successors.extend([next, next + 2]);
+                },
+                Opcode::SYS(_) => {
+                    graph.issues.push(Issue::MachineCode(addr));
+                    successors.push(next);
+                },
+                Opcode::NOP => {
+                    graph.issues.push(Issue::UnknownInstruction(addr));
+                    successors.push(next);
+                },
+                _ => successors.push(next),
+            }
+
+            for successor in successors {
+                if in_program(successor) {
+                    pending.push(successor);
+                } else {
+                    graph.issues.push(Issue::LeavesProgram { from: addr, to: successor });
+                }
+            }
+        }
+
+        // second pass: split the reached instructions into blocks at the leaders
+        for &leader in leaders.iter().filter(|leader| reached.contains(leader)) {
+            let mut block = BasicBlock { start: leader, end: leader, instructions: vec![], edges: vec![] };
+            let mut addr = leader;
+            loop {
+                let word = fetch(addr);
+                block.instructions.push((addr, word));
+                let next = addr + 2;
+                block.end = next;
+
+                match resolve_jump(addr, Opcode::decode(word, variant)) {
+                    Opcode::JP(target) => block.edges.push(Edge { target, kind: EdgeKind::Jump }),
+                    Opcode::CALL(target) => block.edges.extend([Edge { target, kind: EdgeKind::Call }, Edge { target: next, kind: EdgeKind::CallReturn }]),
+                    Opcode::RET | Opcode::STOP | Opcode::JPV0(_) | Opcode::SKB(_) => {},
+                    Opcode::SKIP => block.edges.push(Edge { target: next + 2, kind: EdgeKind::Skip }),
+                    opcode if is_skip(&opcode) => block.edges.extend([Edge { target: next, kind: EdgeKind::Fallthrough }, Edge { target: next + 2, kind: EdgeKind::Skip }]),
+                    _ if leaders.contains(&next) || !reached.contains(&next) => {
+                        if reached.contains(&next) {
+                            block.edges.push(Edge { target: next, kind: EdgeKind::Fallthrough });
+                        }
+                    },
+                    _ => {
+                        addr = next;
+                        continue;
+                    },
+                }
+                break;
+            }
+            graph.blocks.insert(leader, block);
+        }
+
+        // returns lead back to every call site of the subroutine the returning block belongs to
+        for (&subroutine, return_addresses) in &callers {
+            for block in graph.subroutine_blocks(subroutine) {
+                if graph.blocks[&block].instructions.last().is_some_and(|(_, word)| Opcode::decode(*word, variant) == Opcode::RET) {
+                    let block = graph.blocks.get_mut(&block).expect("block was just listed");
+                    block.edges.extend(return_addresses.iter().map(|target| Edge { target: *target, kind: EdgeKind::Return }));
+                }
+            }
+        }
+
+        graph.find_unreachable(&reached, start, end);
+        graph.issues.sort_by_key(issue_address);
+        graph.issues.dedup();
+
+        graph
+    }
+
+    /// Blocks of the subroutine entered at `entry`, not following calls out of it.
+    fn subroutine_blocks(&self, entry: u16) -> BTreeSet<u16> {
+        let mut found = BTreeSet::new();
+        let mut pending = vec![entry];
+        while let Some(start) = pending.pop() {
+            if let Some(block) = self.blocks.get(&start) {
+                if found.insert(start) {
+                    pending.extend(block.edges.iter().filter(|edge| !matches!(edge.kind, EdgeKind::Call | EdgeKind::Return)).map(|edge| edge.target));
+                }
+            }
+        }
+        found
+    }
+
+    fn find_unreachable(&mut self, reached: &BTreeSet<u16>, start: u16, end: u16) {
+        let mut covered = vec![false; usize::from(end - start)];
+        for addr in reached {
+            for byte in [*addr, addr + 1] {
+                if let Some(covered) = byte.checked_sub(start).and_then(|offset| covered.get_mut(usize::from(offset))) {
+                    *covered = true;
+                }
+            }
+        }
+
+        let mut offset = 0;
+        while offset < covered.len() {
+            let is_covered = covered[offset];
+            let run = covered[offset..].iter().take_while(|byte| **byte == is_covered).count();
+            if !is_covered {
+                #[allow(clippy::cast_possible_truncation)]
+                self.issues.push(Issue::Unreachable { start: start + offset as u16, end: start + (offset + run) as u16 });
+            }
+            offset += run;
+        }
+    }
+
+    /// The block containing `address`, if it was reached.
+    #[must_use]
+    pub fn block_at(&self, address: u16) -> Option<&BasicBlock> {
+        self.blocks.range(..=address).next_back().map(|(_, block)| block).filter(|block| address < block.end)
+    }
+
+    /// Renders the graph in Graphviz DOT, one node per block labeled with its disassembly.
+    #[must_use]
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph chip8 {\n    node [shape=box fontname=\"monospace\"];\n");
+
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for (addr, word) in &block.instructions {
+                let _ = write!(label, "{addr:03X}: {:X?}\\l", Opcode::decode(*word, self.variant));
+            }
+            let flagged = self.issues.iter().any(|issue| matches!(issue, Issue::ComputedJump(addr) | Issue::Misaligned(addr) | Issue::MachineCode(addr) | Issue::UnknownInstruction(addr) if block.start <= *addr && *addr < block.end));
+            let style = if flagged { " color=red" } else { "" };
+            let _ = writeln!(dot, "    b{:03X} [label=\"{label}\"{style}];", block.start);
+        }
+
+        for block in self.blocks.values() {
+            for edge in &block.edges {
+                let attributes = match edge.kind {
+                    EdgeKind::Fallthrough => "",
+                    EdgeKind::Jump => " [label=\"jp\"]",
+                    EdgeKind::Call => " [label=\"call\" style=bold]",
+                    EdgeKind::CallReturn => " [style=dotted]",
+                    EdgeKind::Return => " [label=\"ret\" style=dashed]",
+                    EdgeKind::Skip => " [label=\"skip\"]",
+                };
+                let _ = writeln!(dot, "    b{:03X} -> b{:03X}{attributes};", block.start, edge.target);
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Turns CHIP-8E's relative jumps at `addr` into the `JP` to the same target
+fn resolve_jump(addr: u16, opcode: Opcode) -> Opcode {
+    match opcode {
+        Opcode::JPB(bytes) => Opcode::JP(addr.wrapping_sub(u16::from(bytes)) & 0x0FFF),
+        Opcode::JPF(bytes) => Opcode::JP(addr.wrapping_add(u16::from(bytes)) & 0x0FFF),
+        opcode => opcode,
+    }
+}
+
+/// Conditional skips, which may or may not skip the next instruction
+fn is_skip(opcode: &Opcode) -> bool {
+    matches!(opcode, Opcode::SEVB(..) | Opcode::SNEVB(..) | Opcode::SEVV(..) | Opcode::SNEVV(..) | Opcode::SGTVV(..) | Opcode::SKP(_) | Opcode::SKNP(_))
+}
+
+fn issue_address(issue: &Issue) -> u16 {
+    match issue {
+        Issue::ComputedJump(addr) | Issue::Misaligned(addr) | Issue::MachineCode(addr) | Issue::UnknownInstruction(addr) => *addr,
+        Issue::LeavesProgram { from, .. } => *from,
+        Issue::Unreachable { start, .. } => *start,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::MemoryLayout;
+
+    fn analyze(rom: &[u8]) -> ControlFlowGraph {
+        let mut state = Chip8State::default();
+        state.load_program(rom).unwrap();
+        ControlFlowGraph::from_state(&state, rom.len())
+    }
+
+    #[test]
+    fn test_blocks_and_edges() {
+        // 200: LDVB(VA, 5)  202: CALL(20A)  204: SEVB(VA, 5)  206: JP(204)  208: JP(208)  20A: ADDVB(VA, 1)  20C: RET
+        let graph = analyze(&[0x6A, 0x05, 0x22, 0x0A, 0x3A, 0x05, 0x12, 0x04, 0x12, 0x08, 0x7A, 0x01, 0x00, 0xEE]);
+
+        assert_eq!(graph.blocks.keys().copied().collect::<Vec<_>>(), vec![0x200, 0x204, 0x206, 0x208, 0x20A]);
+        assert_eq!(graph.blocks[&0x200].edges, vec![Edge { target: 0x20A, kind: EdgeKind::Call }, Edge { target: 0x204, kind: EdgeKind::CallReturn }]);
+        assert_eq!(graph.blocks[&0x204].edges, vec![Edge { target: 0x206, kind: EdgeKind::Fallthrough }, Edge { target: 0x208, kind: EdgeKind::Skip }]);
+        assert_eq!(graph.blocks[&0x206].edges, vec![Edge { target: 0x204, kind: EdgeKind::Jump }]);
+        assert_eq!(graph.blocks[&0x20A].edges, vec![Edge { target: 0x204, kind: EdgeKind::Return }]);
+        assert_eq!(graph.blocks[&0x20A].instructions.len(), 2);
+        assert_eq!(graph.block_at(0x20C).map(|block| block.start), Some(0x20A));
+        assert!(graph.issues.is_empty())
+    }
+
+    #[test]
+    fn test_issues() {
+        // 200: JP(205)  202: JPV0(300)  204: sprite data  205: misaligned JP(202)  207: data  208: 0000
+        let graph = analyze(&[0x12, 0x05, 0xB3, 0x00, 0xF0, 0x12, 0x02, 0xAA, 0x00, 0x00]);
+
+        assert_eq!(graph.issues, vec![
+            Issue::ComputedJump(0x202),
+            Issue::Unreachable { start: 0x204, end: 0x205 },
+            Issue::Misaligned(0x205),
+            Issue::Unreachable { start: 0x207, end: 0x20A },
+        ]);
+
+        let graph = analyze(&[0x00, 0x00, 0x12, 0x00]);
+        assert_eq!(graph.issues, vec![Issue::MachineCode(0x200)]);
+        let graph = analyze(&[0x80, 0x08, 0x12, 0x00]);
+        assert_eq!(graph.issues, vec![Issue::UnknownInstruction(0x200)]);
+        let graph = analyze(&[0x13, 0x00]);
+        assert_eq!(graph.issues, vec![Issue::LeavesProgram { from: 0x200, to: 0x300 }])
+    }
+
+    #[test]
+    fn test_dot() {
+        let dot = analyze(&[0x12, 0x00]).to_dot();
+
+        assert_eq!(dot, "digraph chip8 {\n    node [shape=box fontname=\"monospace\"];\n    b200 [label=\"200: JP(200)\\l\"];\n    b200 -> b200 [label=\"jp\"];\n}\n")
+    }
+
+    #[test]
+    fn test_chip8e() {
+        // 200: SGTVV(V1, V2)  202: JPF(6)  204: SKIP  206: 0000  208: JPB(4)  20A: STOP
+        let mut state = Chip8State { variant: Variant::Chip8E, ..Chip8State::default() };
+        let rom = [0x51, 0x21, 0xBF, 0x06, 0x01, 0x88, 0x00, 0x00, 0xBB, 0x04, 0x00, 0xED];
+        state.load_program(&rom).unwrap();
+        let graph = ControlFlowGraph::from_state(&state, rom.len());
+
+        assert_eq!(graph.blocks.keys().copied().collect::<Vec<_>>(), vec![0x200, 0x202, 0x204, 0x208]);
+        assert_eq!(graph.blocks[&0x200].edges, vec![Edge { target: 0x202, kind: EdgeKind::Fallthrough }, Edge { target: 0x204, kind: EdgeKind::Skip }]);
+        assert_eq!(graph.blocks[&0x202].edges, vec![Edge { target: 0x208, kind: EdgeKind::Jump }]);
+        assert_eq!(graph.blocks[&0x204].edges, vec![Edge { target: 0x208, kind: EdgeKind::Skip }]);
+        assert_eq!(graph.blocks[&0x208].edges, vec![Edge { target: 0x204, kind: EdgeKind::Jump }]);
+        assert_eq!(graph.issues, vec![Issue::Unreachable { start: 0x206, end: 0x208 }, Issue::Unreachable { start: 0x20A, end: 0x20C }]);
+
+        // plain CHIP-8 reads the same bytes as a skip, two jumps and machine code
+        state.variant = Variant::Chip8;
+        assert!(ControlFlowGraph::from_state(&state, rom.len()).issues.contains(&Issue::ComputedJump(0x202)))
+    }
+
+    #[test]
+    fn test_layout() {
+        // 300: JP(304)  302: JP(302)  304: JP(1FE)
+        let mut state = Chip8State { layout: MemoryLayout { font_address: 0, program_start: 0x300, initial_pc: 0x302 }, ..Chip8State::default() };
+        state.load_program(&[0x13, 0x04, 0x13, 0x02, 0x11, 0xFE]).unwrap();
+        let graph = ControlFlowGraph::from_state(&state, 6);
+        assert_eq!(graph.blocks.keys().copied().collect::<Vec<_>>(), vec![0x302]);
+        assert_eq!(graph.issues, vec![Issue::Unreachable { start: 0x300, end: 0x302 }, Issue::Unreachable { start: 0x304, end: 0x306 }]);
+
+        // entered below the program
+        state.pc = 0x1FE;
+        state.memory[0x1FE..0x200].copy_from_slice(&[0x13, 0x00]);
+        let graph = ControlFlowGraph::from_state(&state, 6);
+        assert_eq!(graph.blocks.keys().copied().collect::<Vec<_>>(), vec![0x1FE, 0x300, 0x304]);
+        assert_eq!(graph.issues, vec![Issue::Unreachable { start: 0x302, end: 0x304 }, Issue::LeavesProgram { from: 0x304, to: 0x1FE }])
+    }
+}
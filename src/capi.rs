@@ -0,0 +1,181 @@
+//! The C API. Hosts hold a `Chip8State` only through the pointer `chip8_new` returns.
+//! `include/chip8.h` declares it for C; `tests/header.rs` checks that it matches this file.
+
+use std::{panic::{self, AssertUnwindSafe}, ptr, slice};
+
+use crate::Chip8State;
+
+/// Returned by functions that succeeded
+pub const CHIP8_OK: i32 = 0;
+/// Returned by functions that failed
+pub const CHIP8_ERROR: i32 = -1;
+/// Display width in pixels
+pub const CHIP8_WIDTH: u32 = 64;
+/// Display height in pixels
+pub const CHIP8_HEIGHT: u32 = 32;
+/// Size of the buffer `chip8_framebuffer` points to
+pub const CHIP8_FRAMEBUFFER_SIZE: u32 = 256;
+
+/// Creates an emulator, ready to load a ROM. Release it with `chip8_free`.
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Chip8State {
+    Box::into_raw(Box::default())
+}
+
+/// Releases an emulator created by `chip8_new`. Does nothing if `state` is null.
+///
+/// # Safety
+///
+/// `state` must be null or come from `chip8_new`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(state: *mut Chip8State) {
+    if !state.is_null() {
+        drop(Box::from_raw(state));
+    }
+}
+
+/// Resets the emulator and loads `len` bytes of ROM at 0x200. Returns `CHIP8_ERROR` if the ROM does not fit, or a pointer is null.
+///
+/// # Safety
+///
+/// `state` must come from `chip8_new`, and `rom` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(state: *mut Chip8State, rom: *const u8, len: usize) -> i32 {
+    let (Some(state), false) = (state.as_mut(), rom.is_null()) else {
+        return CHIP8_ERROR;
+    };
+
+    state.reset();
+    match state.load_program(slice::from_raw_parts(rom, len)) {
+        Ok(()) => CHIP8_OK,
+        Err(_) => CHIP8_ERROR,
+    }
+}
+
+/// Sets which keys are held: bit N stands for key N. Does nothing if `state` is null.
+///
+/// # Safety
+///
+/// `state` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_keys(state: *mut Chip8State, keys: u16) {
+    if let Some(state) = state.as_mut() {
+        state.input = keys;
+    }
+}
+
+/// The display, `CHIP8_HEIGHT` rows of `CHIP8_WIDTH` bits, leftmost pixel in the most significant bit.
+/// Valid until the emulator is freed. Null if `state` is null.
+///
+/// # Safety
+///
+/// `state` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(state: *const Chip8State) -> *const u8 {
+    state.as_ref().map_or(ptr::null(), |state| state.framebuffer.as_ptr())
+}
+
+/// Whether the sound timer is running, meaning the host should play a tone. False if `state` is null.
+///
+/// # Safety
+///
+/// `state` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound_active(state: *const Chip8State) -> bool {
+    state.as_ref().is_some_and(|state| state.st > 0)
+}
+
+/// Runs one 60 Hz frame of `instructions` instructions.
+/// Returns `CHIP8_ERROR` if the program crashed, after which it fails on the same instruction until reset or another ROM is
+/// loaded, or if `state` is null.
+///
+/// # Safety
+///
+/// `state` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(state: *mut Chip8State, instructions: u32) -> i32 {
+    let Some(state) = state.as_mut() else {
+        return CHIP8_ERROR;
+    };
+
+    // the program counter only moves past an instruction that completed, so a crashing one is retried, and crashes again
+    let result = panic::catch_unwind(AssertUnwindSafe(|| state.run_frame(instructions)));
+
+    if result.is_err() || state.stack_error.is_some() { CHIP8_ERROR } else { CHIP8_OK }
+}
+
+/// Counts the timers down `time_passed` times, then executes one instruction. A crash aborts the process.
+/// Does nothing if `state` is null.
+///
+/// # Safety
+///
+/// `state` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_tick(state: *mut Chip8State, time_passed: u32) {
+    if let Some(state) = state.as_mut() {
+        state.tick(time_passed);
+    }
+}
+
+/// Clears the registers, timers, display and memory, unloading the ROM. Does nothing if `state` is null.
+///
+/// # Safety
+///
+/// `state` must be null or come from `chip8_new`.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_reset(state: *mut Chip8State) {
+    if let Some(state) = state.as_mut() {
+        state.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_lifecycle() {
+        // 200: LDF(V0)  202: DRW(V0, V0, 5)  204: JP(204)
+        let rom = [0xF0, 0x29, 0xD0, 0x05, 0x12, 0x04];
+        let state = chip8_new();
+
+        unsafe {
+            assert_eq!(chip8_load_rom(state, rom.as_ptr(), rom.len()), CHIP8_OK);
+            assert_eq!(chip8_load_rom(state, ptr::null(), 0), CHIP8_ERROR);
+            assert_eq!(chip8_run_frame(state, 3), CHIP8_OK);
+
+            let framebuffer = slice::from_raw_parts(chip8_framebuffer(state), CHIP8_FRAMEBUFFER_SIZE as usize);
+            assert_eq!(framebuffer[0], 0xF0);
+            assert_eq!(framebuffer[8], 0x90);
+
+            chip8_free(state);
+            chip8_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_run_frame_crash() {
+        // 200: LDI(FFF)  202: LDVI(V1), reading past the end of memory
+        let rom = [0xAF, 0xFF, 0xF1, 0x65];
+        let mut state = Chip8State::default();
+        state.load_program(&rom).unwrap();
+
+        unsafe {
+            assert_eq!(chip8_run_frame(&mut state, 2), CHIP8_ERROR);
+            assert_eq!(chip8_run_frame(&mut state, 2), CHIP8_ERROR);
+        }
+        assert_eq!(state.pc, 0x202)
+    }
+
+    #[test]
+    fn test_null_handle() {
+        unsafe {
+            chip8_set_keys(ptr::null_mut(), 0xFFFF);
+            chip8_tick(ptr::null_mut(), 1);
+            chip8_reset(ptr::null_mut());
+            assert!(chip8_framebuffer(ptr::null()).is_null());
+            assert!(!chip8_sound_active(ptr::null()));
+            assert_eq!(chip8_run_frame(ptr::null_mut(), 1), CHIP8_ERROR);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+/// What the CPU is wired to: memory, the seven I/O ports selected by `OUT` and `INP`, and the four external flags.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, value: u8);
+    /// `OUT N` puts `value` on the bus for port N, from 1 to 7
+    fn output(&mut self, _port: u8, _value: u8) {}
+    /// `INP N` reads port N, from 0 to 7; nothing drives an unconnected port
+    fn input(&mut self, _port: u8) -> u8 {
+        0
+    }
+    /// External flag EF1 to EF4, tested by the `B1`..`B4` and `BN1`..`BN4` branches
+    fn flag(&self, _flag: u8) -> bool {
+        false
+    }
+}
+
+/// The RCA CDP1802 microprocessor of the COSMAC VIP: sixteen 16-bit registers, any of which can be the program counter
+/// (selected by P) or the data pointer (selected by X), the 8-bit accumulator D and the DF carry flag.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cdp1802 {
+    pub r: [u16; 16],
+    pub d: u8,
+    pub df: bool,
+    /// Register used as the program counter
+    pub p: u8,
+    /// Register used as the data pointer
+    pub x: u8,
+    /// X and P saved by an interrupt or `MARK`
+    pub t: u8,
+    /// Interrupts enabled
+    pub ie: bool,
+    /// The Q output, which drives the VIP's speaker
+    pub q: bool,
+    /// Set by `IDL` until a DMA transfer or an interrupt
+    pub idle: bool,
+}
+
+impl Cdp1802 {
+    /// A processor as the reset line leaves it: X, P and Q cleared, R0 the program counter at 0 and interrupts enabled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self { ie: true, ..Self::default() }
+    }
+
+    /// Resets the processor like `new`. Like the hardware, only X, P, Q, R0 and IE are set.
+    pub fn reset(&mut self) {
+        self.x = 0;
+        self.p = 0;
+        self.q = false;
+        self.r[0] = 0;
+        self.ie = true;
+        self.idle = false;
+    }
+
+    /// Executes one instruction and returns the machine cycles it took: 2, or 3 for the long branches and skips.
+    /// An idle processor does nothing for a cycle.
+    pub fn step(&mut self, bus: &mut impl Bus) -> u32 {
+        if self.idle {
+            return 1;
+        }
+        let opcode = self.fetch(bus);
+        let n = opcode & 0x0F;
+        let rn = usize::from(n);
+        match opcode >> 4 {
+            0x0 if n == 0 => self.idle = true,
+            0x0 => self.d = bus.read(self.r[rn]),
+            0x1 => self.r[rn] = self.r[rn].wrapping_add(1),
+            0x2 => self.r[rn] = self.r[rn].wrapping_sub(1),
+            0x3 => self.short_branch(bus, n),
+            0x4 => {
+                self.d = bus.read(self.r[rn]);
+                self.r[rn] = self.r[rn].wrapping_add(1);
+            },
+            0x5 => bus.write(self.r[rn], self.d),
+            0x6 => self.input_output(bus, n),
+            0x7 => self.control(bus, n),
+            0x8 => self.d = self.r[rn].to_le_bytes()[0],
+            0x9 => self.d = self.r[rn].to_le_bytes()[1],
+            0xA => self.r[rn] = (self.r[rn] & 0xFF00) | u16::from(self.d),
+            0xB => self.r[rn] = (self.r[rn] & 0x00FF) | (u16::from(self.d) << 8),
+            0xC => {
+                self.long_branch(bus, n);
+                return 3;
+            },
+            0xD => self.p = n,
+            0xE => self.x = n,
+            _ => self.alu(bus, n),
+        }
+        2
+    }
+
+    /// Takes an interrupt if they are enabled: saves X and P in T, and continues with R1 as the program counter and R2 as
+    /// the data pointer, interrupts disabled. Returns whether it was taken; taking it is one machine cycle.
+    pub fn interrupt(&mut self) -> bool {
+        if !self.ie {
+            return false;
+        }
+        self.t = (self.x << 4) | self.p;
+        self.p = 1;
+        self.x = 2;
+        self.ie = false;
+        self.idle = false;
+        true
+    }
+
+    /// A DMA output cycle: the byte at R0 goes out to the device, and R0 moves on.
+    pub fn dma_out(&mut self, bus: &mut impl Bus) -> u8 {
+        let byte = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        byte
+    }
+
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let pc = usize::from(self.p);
+        let byte = bus.read(self.r[pc]);
+        self.r[pc] = self.r[pc].wrapping_add(1);
+        byte
+    }
+
+    fn rx(&self) -> u16 {
+        self.r[usize::from(self.x)]
+    }
+
+    fn inc_rx(&mut self) {
+        let x = usize::from(self.x);
+        self.r[x] = self.r[x].wrapping_add(1);
+    }
+
+    /// `3N`: conditions 0 to 7 branch when met, 8 to F when not; `38` never branches, and skips the address byte
+    fn short_branch(&mut self, bus: &mut impl Bus, n: u8) {
+        let condition = match n & 0x07 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            flag => bus.flag(flag - 3),
+        };
+        let pc = usize::from(self.p);
+        if condition != (n >= 8) {
+            let target = bus.read(self.r[pc]);
+            self.r[pc] = (self.r[pc] & 0xFF00) | u16::from(target);
+        } else {
+            self.r[pc] = self.r[pc].wrapping_add(1);
+        }
+    }
+
+    /// `CN`: long branches to the address in the next two bytes, and long skips over them
+    fn long_branch(&mut self, bus: &mut impl Bus, n: u8) {
+        let pc = usize::from(self.p);
+        let (branch, condition) = match n {
+            0x0 => (true, true),
+            0x1 => (true, self.q),
+            0x2 => (true, self.d == 0),
+            0x3 => (true, self.df),
+            0x4 => return,
+            0x5 => (false, !self.q),
+            0x6 => (false, self.d != 0),
+            0x7 => (false, !self.df),
+            0x8 => (false, true),
+            0x9 => (true, !self.q),
+            0xA => (true, self.d != 0),
+            0xB => (true, !self.df),
+            0xC => (false, self.ie),
+            0xD => (false, self.q),
+            0xE => (false, self.d == 0),
+            _ => (false, self.df),
+        };
+        if branch && condition {
+            let hi = bus.read(self.r[pc]);
+            let lo = bus.read(self.r[pc].wrapping_add(1));
+            self.r[pc] = u16::from_be_bytes([hi, lo]);
+        } else if branch || condition {
+            self.r[pc] = self.r[pc].wrapping_add(2);
+        }
+    }
+
+    /// `6N`: `IRX`, `OUT 1`..`OUT 7`, and the inputs. `68`, which the 1802 does not define, reads port 0 like the others.
+    fn input_output(&mut self, bus: &mut impl Bus, n: u8) {
+        match n {
+            0 => self.inc_rx(),
+            1..=7 => {
+                let value = bus.read(self.rx());
+                bus.output(n, value);
+                self.inc_rx();
+            },
+            _ => {
+                self.d = bus.input(n - 8);
+                bus.write(self.rx(), self.d);
+            },
+        }
+    }
+
+    /// `7N`: returns, stack operations, Q, and arithmetic with the carry
+    fn control(&mut self, bus: &mut impl Bus, n: u8) {
+        match n {
+            0x0 | 0x1 => {
+                let xp = bus.read(self.rx());
+                self.inc_rx();
+                self.x = xp >> 4;
+                self.p = xp & 0x0F;
+                self.ie = n == 0;
+            },
+            0x2 => {
+                self.d = bus.read(self.rx());
+                self.inc_rx();
+            },
+            0x3 => {
+                bus.write(self.rx(), self.d);
+                let x = usize::from(self.x);
+                self.r[x] = self.r[x].wrapping_sub(1);
+            },
+            0x4 => {
+                let value = bus.read(self.rx());
+                self.add(value, self.df);
+            },
+            0x5 => {
+                let value = bus.read(self.rx());
+                self.subtract(value, self.d, self.df);
+            },
+            0x6 => {
+                let carry = self.d & 1 != 0;
+                self.d = (self.d >> 1) | (u8::from(self.df) << 7);
+                self.df = carry;
+            },
+            0x7 => {
+                let value = bus.read(self.rx());
+                self.subtract(self.d, value, self.df);
+            },
+            0x8 => bus.write(self.rx(), self.t),
+            0x9 => {
+                self.t = (self.x << 4) | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            },
+            0xA => self.q = false,
+            0xB => self.q = true,
+            0xC => {
+                let value = self.fetch(bus);
+                self.add(value, self.df);
+            },
+            0xD => {
+                let value = self.fetch(bus);
+                self.subtract(value, self.d, self.df);
+            },
+            0xE => {
+                let carry = self.d & 0x80 != 0;
+                self.d = (self.d << 1) | u8::from(self.df);
+                self.df = carry;
+            },
+            _ => {
+                let value = self.fetch(bus);
+                self.subtract(self.d, value, self.df);
+            },
+        }
+    }
+
+    /// `FN`: logic and arithmetic with the byte at R(X), or with the next byte for `F8` to `FF`, and the shifts
+    fn alu(&mut self, bus: &mut impl Bus, n: u8) {
+        match n {
+            0x6 => {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+                return;
+            },
+            0xE => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+                return;
+            },
+            _ => {},
+        }
+        let value = if n < 8 { bus.read(self.rx()) } else { self.fetch(bus) };
+        match n & 0x07 {
+            0 => self.d = value,
+            1 => self.d |= value,
+            2 => self.d &= value,
+            3 => self.d ^= value,
+            4 => self.add(value, false),
+            5 => self.subtract(value, self.d, true),
+            _ => self.subtract(self.d, value, true),
+        }
+    }
+
+    fn add(&mut self, value: u8, carry: bool) {
+        let sum = u16::from(self.d) + u16::from(value) + u16::from(carry);
+        self.d = sum.to_le_bytes()[0];
+        self.df = sum > 0xFF;
+    }
+
+    /// D = `minuend` - `subtrahend`, less one unless `no_borrow`; DF is set when there was no borrow.
+    fn subtract(&mut self, minuend: u8, subtrahend: u8, no_borrow: bool) {
+        let difference = i16::from(minuend) - i16::from(subtrahend) - i16::from(!no_borrow);
+        self.d = difference.to_le_bytes()[0];
+        self.df = difference >= 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Memory([u8; 256]);
+
+    impl Bus for Memory {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.0[usize::from(addr & 0xFF)]
+        }
+
+        fn write(&mut self, addr: u16, value: u8) {
+            self.0[usize::from(addr & 0xFF)] = value;
+        }
+
+        fn flag(&self, flag: u8) -> bool {
+            flag == 3
+        }
+    }
+
+    fn run(program: &[u8], steps: usize) -> (Cdp1802, Memory) {
+        let mut memory = Memory([0; 256]);
+        memory.0[..program.len()].copy_from_slice(program);
+        let mut cpu = Cdp1802::new();
+        for _ in 0..steps {
+            cpu.step(&mut memory);
+        }
+        (cpu, memory)
+    }
+
+    #[test]
+    fn test_registers() {
+        // LDI 12  PHI 5  LDI 34  PLO 5  INC 5  GLO 5  DEC 6  GHI 6
+        let (cpu, _) = run(&[0xF8, 0x12, 0xB5, 0xF8, 0x34, 0xA5, 0x15, 0x85, 0x26, 0x96], 8);
+        assert_eq!(cpu.r[5], 0x1235);
+        assert_eq!(cpu.r[6], 0xFFFF);
+        assert_eq!((cpu.d, cpu.r[0]), (0xFF, 10))
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        // LDI F0  ADI 20  (D 10, DF 1)  ADCI 01  (D 12, DF 0)  SMI 13  (D FF, DF 0)  SMBI 00  (D FE, DF 1)
+        let (cpu, _) = run(&[0xF8, 0xF0, 0xFC, 0x20, 0x7C, 0x01, 0xFF, 0x13, 0x7F, 0x00], 5);
+        assert_eq!((cpu.d, cpu.df), (0xFE, true));
+
+        // LDI 05  SDI 03  (D FE, DF 0)  SHRC  (D 7F, DF 0)  SHL  (D FE, DF 0)  SHLC  (D FC, DF 1)
+        let (cpu, _) = run(&[0xF8, 0x05, 0xFD, 0x03, 0x76, 0xFE, 0x7E], 5);
+        assert_eq!((cpu.d, cpu.df), (0xFC, true))
+    }
+
+    #[test]
+    fn test_memory() {
+        // SEX 1  LDI 80  PLO 1  LDI 55  STXD  LDI AA  STR 1  LDXA  LDX
+        let (cpu, memory) = run(&[0xE1, 0xF8, 0x80, 0xA1, 0xF8, 0x55, 0x73, 0xF8, 0xAA, 0x51, 0x72, 0xF0], 9);
+        assert_eq!((memory.0[0x80], memory.0[0x7F]), (0x55, 0xAA));
+        assert_eq!((cpu.r[1], cpu.d), (0x80, 0x55))
+    }
+
+    #[test]
+    fn test_branches() {
+        // 00: LDI 00  BZ 06  LDI 01  06: B3 0A  IDL  IDL  0A: LBNZ 0000  LSZ  IDL  IDL  10: BN3 00  SEQ  LBQ 0020
+        let (cpu, _) = run(&[0xF8, 0x00, 0x32, 0x06, 0xF8, 0x01, 0x36, 0x0A, 0x00, 0x00, 0xCA, 0x00, 0x00, 0xCE, 0x00, 0x00,
+            0x3E, 0x00, 0x7B, 0xC1, 0x00, 0x20], 8);
+        assert_eq!((cpu.r[0], cpu.d, cpu.idle), (0x0020, 0x00, false))
+    }
+
+    #[test]
+    fn test_subroutines_and_interrupts() {
+        // 00: LDI 50  PLO 2  MARK  SEP 4 (calls 20)  20: SEX 2  INC 2  RET
+        let mut memory = Memory([0; 256]);
+        memory.0[..5].copy_from_slice(&[0xF8, 0x50, 0xA2, 0x79, 0xD4]);
+        memory.0[0x20..0x23].copy_from_slice(&[0xE2, 0x12, 0x70]);
+        let mut cpu = Cdp1802 { x: 3, ..Cdp1802::new() };
+        cpu.r[4] = 0x20;
+        for _ in 0..7 {
+            cpu.step(&mut memory);
+        }
+        // MARK saved X 3 and P 0 at 50, where RET found them
+        assert_eq!(memory.0[0x50], 0x30);
+        assert_eq!((cpu.x, cpu.p, cpu.r[0], cpu.r[2]), (3, 0, 5, 0x51));
+
+        assert!(cpu.interrupt());
+        assert_eq!((cpu.p, cpu.x, cpu.t, cpu.ie), (1, 2, 0x30, false));
+        assert!(!cpu.interrupt())
+    }
+}
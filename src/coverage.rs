@@ -0,0 +1,153 @@
+use std::{error::Error, fmt};
+
+use crate::opcode::Opcode;
+
+/// Byte was fetched as part of an executed instruction
+pub const CODE: u8 = 0b01;
+/// Byte was accessed as data: read by `DRW`, `LDVI` or `LDVVI`, or written by `LDB`, `LDIV` or `LDIVV`
+pub const DATA: u8 = 0b10;
+
+/// Classifies each memory address by how the running program used it.
+/// Enabled by setting `Chip8State::coverage`.
+///
+/// The export format lists one range per line, as `start-end kind` with inclusive hex addresses,
+/// and kind being `code`, `data` or `code+data`. Untouched ranges are left out, and lines starting with `#` are comments.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Coverage {
+    flags: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoverageError {
+    /// 1-based line number of the offending line
+    pub line: usize,
+}
+
+impl fmt::Display for CoverageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: expected `start-end code|data|code+data`", self.line)
+    }
+}
+
+impl Error for CoverageError {}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self { flags: vec![0; 4096] }
+    }
+}
+
+impl Coverage {
+    /// Classifies the bytes touched by executing `opcode` at `address`, with the index register at `index`.
+    pub fn record(&mut self, address: u16, opcode: &Opcode, index: u16) {
+        self.mark(address, 2, CODE);
+
+        let data_len = match opcode {
+            Opcode::DRW(_, _, rows) => u16::from(*rows),
+            Opcode::LDVI(reg) | Opcode::LDIV(reg) => *reg as u16 + 1,
+            Opcode::LDVVI(first, last) | Opcode::LDIVV(first, last) => (*last as u16 + 1).saturating_sub(*first as u16),
+            Opcode::LDB(_) => 3,
+            _ => 0,
+        };
+        self.mark(index, data_len, DATA);
+    }
+
+    fn mark(&mut self, start: u16, len: u16, kind: u8) {
+        for offset in 0..len {
+            self.flags[usize::from(start.wrapping_add(offset) & 0x0FFF)] |= kind;
+        }
+    }
+
+    /// `CODE` and `DATA` flags of `address`; 0 if untouched.
+    #[must_use]
+    pub fn get(&self, address: u16) -> u8 {
+        self.flags[usize::from(address & 0x0FFF)]
+    }
+
+    pub fn clear(&mut self) {
+        self.flags.fill(0);
+    }
+
+    #[must_use]
+    pub fn export(&self) -> String {
+        let mut text = String::from("# CHIP-8 coverage: start-end code|data|code+data\n");
+
+        let mut start = 0;
+        while start < self.flags.len() {
+            let kind = self.flags[start];
+            let end = start + self.flags[start..].iter().take_while(|flags| **flags == kind).count();
+            if kind != 0 {
+                text.push_str(&format!("{start:03X}-{:03X} {}\n", end - 1, kind_name(kind)));
+            }
+            start = end;
+        }
+
+        text
+    }
+
+    /// Reads an exported coverage map back.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first line that is neither a range nor a comment.
+    pub fn parse(text: &str) -> Result<Self, CoverageError> {
+        let mut coverage = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = CoverageError { line: i + 1 };
+            let (range, kind) = line.split_once(' ').ok_or(error.clone())?;
+            let (start, end) = range.split_once('-').ok_or(error.clone())?;
+            let start = u16::from_str_radix(start, 16).ok().filter(|addr| *addr < 0x1000).ok_or(error.clone())?;
+            let end = u16::from_str_radix(end, 16).ok().filter(|addr| *addr < 0x1000 && *addr >= start).ok_or(error.clone())?;
+            let kind = match kind.trim() {
+                "code" => CODE,
+                "data" => DATA,
+                "code+data" => CODE | DATA,
+                _ => return Err(error),
+            };
+            coverage.mark(start, end - start + 1, kind);
+        }
+
+        Ok(coverage)
+    }
+}
+
+fn kind_name(kind: u8) -> &'static str {
+    match kind {
+        CODE => "code",
+        DATA => "data",
+        _ => "code+data",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reg;
+
+    #[test]
+    fn test_record_export() {
+        let mut coverage = Coverage::default();
+        coverage.record(0x200, &Opcode::LDI(0x20A), 0);
+        coverage.record(0x202, &Opcode::DRW(Reg::V0, Reg::V1, 5), 0x20A);
+        coverage.record(0x204, &Opcode::LDVI(Reg::V1), 0x20E);
+
+        assert_eq!(coverage.get(0x203), CODE);
+        assert_eq!(coverage.get(0x20A), DATA);
+        assert_eq!(coverage.get(0x206), 0);
+        assert_eq!(coverage.export(), "# CHIP-8 coverage: start-end code|data|code+data\n200-205 code\n20A-20F data\n");
+        assert_eq!(Coverage::parse(&coverage.export()), Ok(coverage))
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Coverage::parse("200-201 code+data").map(|coverage| coverage.get(0x201)), Ok(CODE | DATA));
+        assert_eq!(Coverage::parse("# header\n200-1FF code"), Err(CoverageError { line: 2 }));
+        assert_eq!(Coverage::parse("200-201 sprite"), Err(CoverageError { line: 1 }))
+    }
+}
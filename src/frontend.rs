@@ -0,0 +1,41 @@
+use std::{any::Any, time::Duration};
+
+/// Keyboard keys for CHIP-8 keys 0 to F, laid out like the COSMAC VIP keypad on the 1234/QWER/ASDF/ZXCV block
+pub const DEFAULT_KEYMAP: [char; 16] = ['x', '1', '2', '3', 'q', 'w', 'e', 'a', 's', 'd', 'z', 'c', '4', 'r', 'f', 'v'];
+/// How long a key stays held after its last press or repeat, on terminals that do not report releases.
+/// Terminal key repeat typically starts after about half a second, so shorter timeouts make held keys flicker.
+pub const DEFAULT_KEY_RELEASE_TIMEOUT: Duration = Duration::from_millis(550);
+
+/// The message of a panic caught with `catch_unwind`, such as a crash in `Chip8State::tick`.
+/// Pass the payload itself, `&*panic`, rather than the box holding it.
+#[must_use]
+pub fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(msg) = panic.downcast_ref::<&'static str>() {
+        String::from(*msg)
+    } else if let Some(msg) = panic.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        String::from("Unknown Error")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use super::*;
+
+    #[test]
+    fn test_panic_message() {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        let caught = [
+            panic::catch_unwind(|| panic!("static")).unwrap_err(),
+            panic::catch_unwind(|| panic!("formatted {:03X}", 0x200)).unwrap_err(),
+            panic::catch_unwind(|| panic::panic_any(5)).unwrap_err(),
+        ];
+        panic::set_hook(hook);
+
+        assert_eq!(caught.iter().map(|panic| panic_message(&**panic)).collect::<Vec<_>>(), ["static", "formatted 200", "Unknown Error"])
+    }
+}
@@ -0,0 +1,103 @@
+use std::{error::Error, fmt};
+
+use crate::PROGRAM_START;
+
+/// Bytes taken by the font: 16 digits of 5 rows
+pub const FONT_SIZE: u16 = 80;
+/// Rows in each font digit
+pub const FONT_HEIGHT: u16 = 5;
+
+/// Where the font and the program are placed in memory, and where execution starts.
+/// The default puts the font at 0 and loads and starts programs at `PROGRAM_START`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryLayout {
+    /// Address of the font's digit 0; `LDF` points I at digit N, `FONT_HEIGHT` * N bytes further on
+    pub font_address: u16,
+    /// Address programs are loaded at
+    pub program_start: u16,
+    /// Address execution starts at after a reset or a program load
+    pub initial_pc: u16,
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        Self { font_address: 0, program_start: PROGRAM_START, initial_pc: PROGRAM_START }
+    }
+}
+
+impl MemoryLayout {
+    /// The font at 0x050, where many modern interpreters put it
+    pub const FONT_050: Self = Self { font_address: 0x050, program_start: PROGRAM_START, initial_pc: PROGRAM_START };
+    /// Programs for the ETI-660, which start at 0x600
+    pub const ETI660: Self = Self { font_address: 0, program_start: 0x600, initial_pc: 0x600 };
+
+    /// A layout loading and running programs from `address`, with the font where it is in `self`.
+    #[must_use]
+    pub fn starting_at(self, address: u16) -> Self {
+        Self { program_start: address, initial_pc: address, ..self }
+    }
+
+    /// The address of a font digit's sprite, as `LDF` sets I to it. Like on the original interpreters, values above F
+    /// point past the font.
+    #[must_use]
+    pub fn font_sprite(&self, digit: u8) -> u16 {
+        self.font_address.wrapping_add(u16::from(digit) * FONT_HEIGHT)
+    }
+
+    /// Checks that the font fits in memory and the addresses are in it.
+    ///
+    /// # Errors
+    ///
+    /// Names the first setting that does not fit.
+    pub fn validate(&self) -> Result<(), LayoutError> {
+        if self.font_address > 0x1000 - FONT_SIZE {
+            Err(LayoutError::Font(self.font_address))
+        } else if self.program_start > 0xFFF {
+            Err(LayoutError::ProgramStart(self.program_start))
+        } else if self.initial_pc > 0xFFF {
+            Err(LayoutError::InitialPc(self.initial_pc))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A memory layout setting outside memory
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LayoutError {
+    /// The font would run past the end of memory from this address
+    Font(u16),
+    ProgramStart(u16),
+    InitialPc(u16),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Font(addr) => write!(f, "font address {addr:03X} leaves no room for the {FONT_SIZE}-byte font, expected at most {:03X}", 0x1000 - FONT_SIZE),
+            Self::ProgramStart(addr) => write!(f, "program start {addr:03X} is outside memory"),
+            Self::InitialPc(addr) => write!(f, "initial PC {addr:03X} is outside memory"),
+        }
+    }
+}
+
+impl Error for LayoutError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_font_sprite() {
+        assert_eq!(MemoryLayout::default().font_sprite(0xA), 50);
+        assert_eq!(MemoryLayout::FONT_050.font_sprite(1), 0x055);
+        assert_eq!(MemoryLayout::ETI660.starting_at(0x200), MemoryLayout::default())
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(MemoryLayout::ETI660.validate(), Ok(()));
+        assert_eq!(MemoryLayout { font_address: 0xFC0, ..MemoryLayout::default() }.validate(), Err(LayoutError::Font(0xFC0)));
+        assert_eq!(MemoryLayout::default().starting_at(0x1000).validate(), Err(LayoutError::ProgramStart(0x1000)))
+    }
+}
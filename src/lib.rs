@@ -1,10 +1,46 @@
-use opcode::{Opcode, WaitStatus};
+use std::{error::Error, fmt};
+
+use rand::{SeedableRng, rngs::StdRng};
 
+use coverage::Coverage;
+use layout::MemoryLayout;
+use opcode::{Opcode, WaitStatus};
+use phosphor::Phosphor;
+use profiler::Profiler;
+use quirks::Quirks;
+use romdb::{RomDb, RomMatch};
+use smc::SmcDetector;
+use stack::{StackConfig, StackError};
+use variant::{IoPort, Variant};
+use vip::Vip;
+
+pub mod analysis;
+pub mod capi;
+pub mod cdp1802;
+pub mod coverage;
+pub mod frontend;
+pub mod layout;
 pub mod opcode;
+pub mod movie;
+pub mod netplay;
+pub mod phosphor;
+pub mod profiler;
+pub mod quirks;
+pub mod romdb;
+pub mod smc;
+pub mod snapshot;
+pub mod stack;
+pub mod symbols;
+pub mod variant;
+pub mod vip;
 mod util;
 mod sprite;
 
+pub use capi::{chip8_reset, chip8_run_frame, chip8_tick};
+
 pub const INSTR_SIZE: u8 = 2;
+/// Where programs are loaded and start running, unless the memory layout says otherwise
+pub const PROGRAM_START: u16 = 0x200;
 
 /// For conveniently accessing registers in a 16-byte buffer
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -24,13 +60,39 @@ impl From<u8> for Reg {
     }
 }
 
-#[repr(C)]
+/// One decoded entry of the call stack
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StackFrame {
+    /// Address of the `CALL` instruction that pushed this entry
+    pub call_site: u16,
+    /// Address execution resumes at after the matching `RET`
+    pub return_address: u16,
+    /// Destination of the `CALL`, if the instruction at `call_site` still decodes as one
+    pub target: Option<u16>,
+}
+
+/// A program does not fit in memory above the program start address
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProgramTooLarge {
+    pub size: usize,
+    pub capacity: usize,
+}
+
+impl fmt::Display for ProgramTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "program is {} bytes, but only {} fit in memory", self.size, self.capacity)
+    }
+}
+
+impl Error for ProgramTooLarge {}
+
+#[derive(Clone)]
 pub struct Chip8State {
     /// General-Purpose Registers
     pub registers: [u8; 16],
     /// Index Register
     pub index: u16,
-    /// 64-Byte Stack
+    /// 64-Byte Stack; unused while `stack_config.in_memory` is set
     pub stack: [u8; 64],
     /// Stack pointer
     pub sp: u8,
@@ -42,18 +104,51 @@ pub struct Chip8State {
     pub st: u8,
     /// 64x32-Bit Frame Buffer (Monochrome)
     pub framebuffer: [u8; 256],
+    /// CHIP-8X foreground color of each framebuffer byte, so of each 8x1 pixel area, as a `variant::color`
+    pub colors: [u8; 256],
+    /// CHIP-8X background color, as a `variant::color`
+    pub background: u8,
 
-    /// 4K Memory; Programs start at 0x200
+    /// 4K Memory; Programs start at `layout.program_start`
     pub memory: [u8; 4096],
     /// 16-key emulated keyboard
     pub input: u16,
+    /// Interpreter-specific behaviours; kept across resets
+    pub quirks: Quirks,
+    /// Instruction set; kept across resets
+    pub variant: Variant,
+    /// CHIP-8X and CHIP-8E I/O port
+    pub io: IoPort,
+    /// Set while a CHIP-8E `FX4F` waits for the delay timer it started
+    pub delay_wait: bool,
+    /// Execution counts, collected while set; kept across resets
+    pub profiler: Option<Box<Profiler>>,
+    /// Code/data classification of memory, collected while set; kept across resets
+    pub coverage: Option<Box<Coverage>>,
+    /// Self-modifying code detection, while set
+    pub smc: Option<Box<SmcDetector>>,
+    /// Display persistence, which frontends can show instead of the raw framebuffer to reduce flicker, while set
+    pub phosphor: Option<Box<Phosphor>>,
+    /// COSMAC VIP whose 1802 runs `0NNN` machine code, and with an interpreter image the whole program, while set;
+    /// kept across resets
+    pub vip: Option<Box<Vip>>,
+    /// Call depth limit and stack location; kept across resets
+    pub stack_config: StackConfig,
+    /// Where the font and programs go, and where execution starts; kept across resets
+    pub layout: MemoryLayout,
+    /// Why execution halted, if a `CALL` or `RET` failed
+    pub stack_error: Option<StackError>,
+    /// Source of `RND` values, seeded randomly; reseed it with `SeedableRng::seed_from_u64` for reproducible runs. Kept across resets
+    pub rng: StdRng,
 }
 
 impl Default for Chip8State {
     fn default() -> Self {
         let mut state = Self { registers: Default::default(), index: Default::default(), stack: [0; 64],
             sp: Default::default(), pc: Default::default(), dt: Default::default(),
-            st: Default::default(), framebuffer: [0; 256], memory: [0; 4096], input: 0 };
+            st: Default::default(), framebuffer: [0; 256], colors: [0; 256], background: 0, memory: [0; 4096], input: 0,
+            quirks: Quirks::default(), variant: Variant::default(), io: IoPort::default(), delay_wait: false, profiler: None, coverage: None, smc: None, phosphor: None,
+            vip: None, stack_config: StackConfig::default(), layout: MemoryLayout::default(), stack_error: None, rng: StdRng::from_entropy() };
 
         state.init();
         state
@@ -63,20 +158,80 @@ impl Default for Chip8State {
 impl Chip8State {
     fn init(&mut self) {
         sprite::store_default_sprites(self);
+        self.colors = [variant::color::DEFAULT_FOREGROUND; 256];
+        self.background = variant::color::BACKGROUNDS[0];
 
-        self.pc = 0x0200;
+        self.pc = self.layout.initial_pc & 0x0FFF;
     }
 
-    fn reset(&mut self) {
+    /// Clears the registers, timers, display and memory, unloading the ROM. The settings documented as kept across resets
+    /// stay.
+    pub fn reset(&mut self) {
         self.registers = [0; 16];
         self.index = 0;
         self.stack = [0; 64];
         self.sp = 0;
+        self.stack_error = None;
         self.dt = 0;
         self.st = 0;
         self.framebuffer = [0; 256];
         self.memory = [0; 4096];
+        self.io = IoPort::default();
+        self.delay_wait = false;
+        if let Some(smc) = &mut self.smc {
+            smc.clear();
+        }
+        if let Some(phosphor) = &mut self.phosphor {
+            phosphor.clear();
+        }
         self.init();
+        if let Some(vip) = &mut self.vip {
+            vip.reset(&mut self.memory);
+        }
+    }
+
+    /// Copies a program into memory at the layout's program start and points the program counter at its initial PC.
+    ///
+    /// # Errors
+    ///
+    /// Fails without changing memory if the program does not fit.
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), ProgramTooLarge> {
+        let start = usize::from(self.layout.program_start).min(self.memory.len());
+        let capacity = self.memory.len() - start;
+        if program.len() > capacity {
+            return Err(ProgramTooLarge { size: program.len(), capacity });
+        }
+
+        self.memory[start..start + program.len()].copy_from_slice(program);
+        self.pc = self.layout.initial_pc & 0x0FFF;
+        Ok(())
+    }
+
+    /// Loads a program like `load_program`, and if the database knows it, switches to the quirks and variant it lists.
+    /// Programs for a variant loaded elsewhere, like CHIP-8X at 0x300, are loaded there, unless the layout already starts
+    /// programs somewhere other than where the current variant would.
+    ///
+    /// # Errors
+    ///
+    /// Fails without changing the state if the program does not fit.
+    pub fn load_known_program<'db>(&mut self, program: &[u8], db: &'db RomDb) -> Result<Option<RomMatch<'db>>, ProgramTooLarge> {
+        let found = db.lookup(program);
+        let previous = (self.layout, self.variant);
+        if let Some(found) = &found {
+            if self.layout == self.layout.starting_at(self.variant.program_start()) {
+                self.layout = self.layout.starting_at(found.variant.program_start());
+            }
+            self.variant = found.variant;
+        }
+        if let Err(err) = self.load_program(program) {
+            (self.layout, self.variant) = previous;
+            return Err(err);
+        }
+
+        if let Some(found) = &found {
+            self.quirks = found.quirks;
+        }
+        Ok(found)
     }
 
     fn fetch_instruction(&self, addr: u16) -> u16 {
@@ -88,7 +243,18 @@ impl Chip8State {
 
     #[must_use]
     pub fn decode_opcode(&self) -> Opcode {
-        Opcode::from(self.fetch_instruction(self.pc))
+        Opcode::decode(self.fetch_instruction(self.pc), self.variant)
+    }
+
+    /// Stores a shift's result in `reg` and the bit shifted out in VF, in the order `quirks.shift_vf_first` gives.
+    fn shift_result(&mut self, reg: Reg, result: u8, flag: u8) {
+        if self.quirks.shift_vf_first {
+            self.registers[Reg::VF as usize] = flag;
+            self.registers[reg as usize] = result;
+        } else {
+            self.registers[reg as usize] = result;
+            self.registers[Reg::VF as usize] = flag;
+        }
     }
 
     fn jump_to_address(&mut self, address: u16) {
@@ -106,17 +272,71 @@ impl Chip8State {
         }
     }
 
-    fn push_stack(&mut self, value: u16) {
-        self.stack[self.sp as usize] = ((value & 0xFF00) >> 8) as u8;
-        self.stack[(self.sp + 1) as usize] = (value & 0x00FF) as u8;
+    /// Where stack entry `index`, counted from the outermost call, is kept in memory: the VIP pushed return addresses
+    /// downward from `VIP_STACK_TOP`.
+    fn memory_stack_entry(index: usize) -> usize {
+        usize::from(stack::VIP_STACK_TOP) - 1 - 2 * index
+    }
+
+    /// Stores the `CALL` site of stack entry `index`. In memory, it is stored as the return address after it.
+    fn write_stack_entry(&mut self, index: usize, call_site: u16) {
+        if self.stack_config.in_memory {
+            let addr = Self::memory_stack_entry(index);
+            let return_address = call_site.wrapping_add(u16::from(INSTR_SIZE)) & 0x0FFF;
+            self.memory[addr..addr + 2].copy_from_slice(&return_address.to_be_bytes());
+        } else {
+            self.stack[2 * index..2 * index + 2].copy_from_slice(&call_site.to_be_bytes());
+        }
+    }
+
+    /// The `CALL` site of stack entry `index`, derived from the return address when the stack is in memory.
+    fn read_stack_entry(&self, index: usize) -> u16 {
+        if self.stack_config.in_memory {
+            let addr = Self::memory_stack_entry(index);
+            u16::from_be_bytes([self.memory[addr], self.memory[addr + 1]]).wrapping_sub(u16::from(INSTR_SIZE)) & 0x0FFF
+        } else {
+            u16::from_be_bytes([self.stack[2 * index], self.stack[2 * index + 1]])
+        }
+    }
+
+    fn push_stack(&mut self, value: u16) -> Result<(), StackError> {
+        let depth = self.stack_config.depth();
+        if self.sp >= 2 * depth {
+            return Err(StackError::Overflow { depth });
+        }
+
+        self.write_stack_entry(usize::from(self.sp / 2), value);
 
         self.sp += 2;
+        Ok(())
     }
 
-    fn pop_stack(&mut self) -> u16 {
+    fn pop_stack(&mut self) -> Result<u16, StackError> {
+        if self.sp < 2 {
+            return Err(StackError::Underflow);
+        }
         self.sp -= 2;
 
-        (u16::from(self.stack[self.sp as usize]) << 8) | u16::from(self.stack[(self.sp + 1) as usize])
+        Ok(self.read_stack_entry(usize::from(self.sp / 2)))
+    }
+
+    /// Decodes each 16-bit entry pushed onto the stack, outermost call first.
+    #[must_use]
+    pub fn call_stack(&self) -> Vec<StackFrame> {
+        let capacity = if self.stack_config.in_memory { stack::MAX_MEMORY_DEPTH } else { stack::MAX_DEPTH };
+        let depth = usize::from((self.sp / 2).min(capacity));
+
+        (0..depth).map(|index| {
+            // entries can be anything once a program overwrites them, so the instruction is read wrapping around memory
+            let call_site = self.read_stack_entry(index) & 0x0FFF;
+            let instruction = u16::from_be_bytes([self.memory[usize::from(call_site)], self.memory[usize::from((call_site + 1) & 0x0FFF)]]);
+            let target = match Opcode::decode(instruction, self.variant) {
+                Opcode::CALL(addr) => Some(addr),
+                _ => None,
+            };
+
+            StackFrame { call_site, return_address: call_site.wrapping_add(u16::from(INSTR_SIZE)) & 0x0FFF, target }
+        }).collect()
     }
 
     fn read_input(&self, key: u16) -> bool {
@@ -133,6 +353,9 @@ impl Chip8State {
     fn store_to_memory(&mut self, value: u8, address: u16) {
         let address = address & 0x0FFF;
 
+        if let Some(smc) = &mut self.smc {
+            smc.check_write(self.pc, address, self.memory[address as usize], value);
+        }
         self.memory[address as usize] = value;
     }
 
@@ -184,29 +407,76 @@ impl Chip8State {
         self.store_to_memory(bcd.tens, address + 1);
         self.store_to_memory(bcd.ones, address + 2);
     }
-}
 
-#[no_mangle]
-pub extern "C" fn chip8_tick(state: &mut Chip8State, time_passed: u32) {
-    for _ in 0..time_passed {
-        state.decrement_timers();
-    }
+    /// Counts the timers down `time_passed` times, then executes one instruction. With a VIP interpreter image, the 1802
+    /// runs the instruction through it instead, and the timers are left to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the program accesses memory past 0xFFF. Unlike `chip8_tick`, the panic can be caught.
+    pub fn tick(&mut self, time_passed: u32) {
+        for _ in 0..time_passed {
+            // timer ticks mark the 60 Hz frames
+            if let Some(phosphor) = &mut self.phosphor {
+                phosphor.end_frame(&self.framebuffer);
+            }
+            // an interpreter image counts the timers down itself, in the 1861 interrupt
+            if !self.runs_interpreter() {
+                self.decrement_timers();
+            }
+        }
+        if self.stack_error.is_some() {
+            return;
+        }
+        if self.runs_interpreter() {
+            self.run_interpreter();
+            return;
+        }
 
-    let current_opcode = state.decode_opcode();
+        let pc = self.pc;
+        let current_opcode = self.decode_opcode();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(pc, &current_opcode);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(pc, &current_opcode, self.index);
+        }
+        if let Some(smc) = &mut self.smc {
+            smc.record_execution(pc);
+        }
 
-    let wait_status = current_opcode.execute(state);
+        let wait_status = current_opcode.execute(self);
+
+        if wait_status == WaitStatus::Running {
+            self.pc += u16::from(INSTR_SIZE);
+        }
 
-    if wait_status == WaitStatus::Running {
-        state.pc += u16::from(INSTR_SIZE);
+        if let (Some(phosphor), Opcode::DRW(..)) = (&mut self.phosphor, &current_opcode) {
+            phosphor.record(&self.framebuffer);
+        }
+        if let (Some(profiler), Opcode::JP(_) | Opcode::JPV0(_) | Opcode::JPB(_) | Opcode::JPF(_)) = (&mut self.profiler, current_opcode) {
+            profiler.record_jump(pc, self.pc);
+        }
     }
-}
 
-#[no_mangle]
-pub extern "C" fn chip8_reset(state: &mut Chip8State) {
-    state.reset();
+    fn runs_interpreter(&self) -> bool {
+        self.vip.as_ref().is_some_and(|vip| vip.runs_interpreter())
+    }
+
+    /// Runs one 60 Hz frame: the timers count down once, then `instructions` instructions execute.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `tick`.
+    pub fn run_frame(&mut self, instructions: u32) {
+        for i in 0..instructions {
+            self.tick(u32::from(i == 0));
+        }
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
     use super::*;
 
@@ -233,7 +503,7 @@ mod tests {
 
         state.jump_to_address(0x0352);
         
-        assert_eq!(state.decode_opcode(), Opcode::SHR(Reg::VA))
+        assert_eq!(state.decode_opcode(), Opcode::SHR(Reg::VA, Reg::VB))
     }
 
     #[test]
@@ -268,8 +538,8 @@ mod tests {
     fn test_push_stack() {
         let mut state = Chip8State::default();
 
-        state.push_stack(0xABCD);
-        state.push_stack(0x1234);
+        state.push_stack(0xABCD).unwrap();
+        state.push_stack(0x1234).unwrap();
 
         assert_eq!(state.stack[0..4], [0xAB, 0xCD, 0x12, 0x34]);
         assert_eq!(state.sp, 4)
@@ -279,14 +549,161 @@ mod tests {
     fn test_pop_stack() {
         let mut state = Chip8State::default();
 
-        state.push_stack(0x1234);
-        state.push_stack(0xABCD);
+        state.push_stack(0x1234).unwrap();
+        state.push_stack(0xABCD).unwrap();
 
-        assert_eq!(state.pop_stack(), 0xABCD);
-        assert_eq!(state.pop_stack(), 0x1234);
+        assert_eq!(state.pop_stack(), Ok(0xABCD));
+        assert_eq!(state.pop_stack(), Ok(0x1234));
         assert_eq!(state.sp, 0)
     }
 
+    #[test]
+    fn test_stack_limits() {
+        let mut state = Chip8State::default();
+        state.stack_config = StackConfig { max_depth: 2, in_memory: false };
+
+        assert_eq!(state.pop_stack(), Err(StackError::Underflow));
+        state.push_stack(0x0200).unwrap();
+        state.push_stack(0x0202).unwrap();
+        assert_eq!(state.push_stack(0x0204), Err(StackError::Overflow { depth: 2 }));
+        assert_eq!(state.sp, 4)
+    }
+
+    #[test]
+    fn test_stack_in_memory() {
+        let mut state = Chip8State::default();
+        state.stack_config = StackConfig::VIP;
+        // 200: CALL(204)  202: JP(202)  204: CALL(204)
+        state.load_program(&[0x22, 0x04, 0x12, 0x02, 0x22, 0x04]).unwrap();
+
+        state.run_frame(20);
+
+        // return addresses, growing down from 0xECF
+        assert_eq!(state.memory[0xECC..0xED0], [0x02, 0x06, 0x02, 0x02]);
+        assert_eq!(state.call_stack()[1].call_site, 0x204);
+        assert_eq!(state.stack, [0; 64]);
+        assert_eq!(state.call_stack().len(), 12);
+        assert_eq!(state.stack_error, Some(StackError::Overflow { depth: 12 }));
+        assert_eq!(state.pc, 0x204)
+    }
+
+    #[test]
+    fn test_load_program() {
+        let mut state = Chip8State::default();
+        state.pc = 0x300;
+
+        assert_eq!(state.load_program(&[0x12, 0x34]), Ok(()));
+        assert_eq!(state.memory[0x200..0x202], [0x12, 0x34]);
+        assert_eq!(state.pc, 0x200);
+        assert_eq!(state.load_program(&[0; 3585]), Err(ProgramTooLarge { size: 3585, capacity: 3584 }));
+        assert_eq!(state.load_known_program(&[0x12, 0x00], &RomDb::embedded()).map(|found| found.is_none()), Ok(true))
+    }
+
+    #[test]
+    fn test_memory_layout() {
+        let mut state = Chip8State::default();
+        state.layout = MemoryLayout { font_address: 0x050, program_start: 0x600, initial_pc: 0x602 };
+        state.reset();
+        assert_eq!(state.memory[0x050..0x055], [0xF0, 0x90, 0x90, 0x90, 0xF0]);
+        assert_eq!(state.memory[0..5], [0; 5]);
+
+        // 600: NOP  602: LDVB(V0, 8)  604: LDF(V0)
+        state.load_program(&[0x00, 0x00, 0x60, 0x08, 0xF0, 0x29]).unwrap();
+        assert_eq!(state.pc, 0x602);
+        assert_eq!(state.load_program(&[0; 2561]), Err(ProgramTooLarge { size: 2561, capacity: 2560 }));
+        state.run_frame(2);
+        assert_eq!(state.index, 0x078)
+    }
+
+    #[test]
+    fn test_profile_tick() {
+        let mut state = Chip8State::default();
+        // 200: ADDVB(V0, 1)  202: JP(200)
+        state.load_program(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        state.profiler = Some(Box::default());
+
+        state.run_frame(6);
+
+        let profiler = state.profiler.as_ref().unwrap();
+        assert_eq!(profiler.total(), 6);
+        assert_eq!(profiler.hits(0x202), 3);
+        assert_eq!(profiler.hot_loops()[0].iterations, 3)
+    }
+
+    #[test]
+    fn test_detect_smc() {
+        let mut state = Chip8State::default();
+        // 200: LDI(206)  202: LDVB(V0, 13)  204: LDIV(V0)  206: JP(206), patched to JP(306)
+        state.load_program(&[0xA2, 0x06, 0x60, 0x13, 0xF0, 0x55, 0x12, 0x06]).unwrap();
+        state.smc = Some(Box::default());
+
+        state.run_frame(4);
+
+        let smc = state.smc.as_ref().unwrap();
+        assert_eq!(smc.events(), &[smc::SmcEvent { pc: 0x204, address: 0x206, old_value: 0x12, new_value: 0x13 }]);
+        assert_eq!(state.pc, 0x306)
+    }
+
+    #[test]
+    fn test_phosphor_hides_flicker() {
+        let mut state = Chip8State::default();
+        // 200: LDF(V0)  202: DRW(V1, V1, 5)  204: DRW(V1, V1, 5)  206: JP(202)
+        state.load_program(&[0xF0, 0x29, 0xD1, 0x15, 0xD1, 0x15, 0x12, 0x02]).unwrap();
+        state.phosphor = Some(Box::new(Phosphor::new(phosphor::PhosphorMode::MaxOfFrames(2))));
+
+        let mut shown = vec![];
+        for _ in 0..6 {
+            state.run_frame(5);
+            shown.push((state.framebuffer[0] != 0, state.pixel_intensity(0, 0)));
+        }
+
+        // the raw display blinks, but the pixel shows lit throughout
+        assert!(shown.iter().any(|(lit, _)| *lit) && shown.iter().any(|(lit, _)| !lit));
+        assert!(shown.iter().all(|(_, intensity)| *intensity == phosphor::FULL))
+    }
+
+    #[test]
+    fn test_clone_across_threads() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let mut state = Chip8State::default();
+        state.rng = StdRng::seed_from_u64(1);
+        // 200: RND(V0, FF)  202: JP(200)
+        state.load_program(&[0xC0, 0xFF, 0x12, 0x00]).unwrap();
+        let copy = state.clone();
+        assert_send(&copy);
+
+        let handle = std::thread::spawn(move || {
+            let mut copy = copy;
+            copy.run_frame(5);
+            copy.registers
+        });
+        state.run_frame(5);
+
+        assert_eq!(handle.join().unwrap(), state.registers)
+    }
+
+    #[test]
+    fn test_call_stack() {
+        let mut state = Chip8State::default();
+
+        state.memory[0x0200] = 0x23;
+        state.memory[0x0201] = 0x00;
+        Opcode::CALL(0x0300).execute(&mut state);
+        state.push_stack(0x0ABC).unwrap();
+
+        assert_eq!(state.call_stack(), vec![
+            StackFrame { call_site: 0x0200, return_address: 0x0202, target: Some(0x0300) },
+            StackFrame { call_site: 0x0ABC, return_address: 0x0ABE, target: None },
+        ]);
+
+        // a corrupted entry is read within memory, wrapping around its end
+        state.push_stack(0xFFFF).unwrap();
+        state.memory[0x0FFF] = 0x23;
+        state.memory[0x0000] = 0x45;
+        assert_eq!(state.call_stack()[2], StackFrame { call_site: 0x0FFF, return_address: 0x0001, target: Some(0x0345) })
+    }
+
     #[test]
     fn test_read_input() {
         let mut state = Chip8State::default();
@@ -0,0 +1,521 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::{Chip8State, ProgramTooLarge, layout::{FONT_SIZE, MemoryLayout}, quirks::Quirks, romdb::sha1_hex, stack::StackConfig, variant::Variant};
+
+const MAGIC: &str = "CHIP-8 movie";
+/// Format version written by `Movie::to_string`; older or newer movies are refused
+pub const MOVIE_VERSION: u32 = 2;
+/// Timer ticks, which count 60 Hz frames, between the state hashes a movie records
+pub const CHECKPOINT_INTERVAL: u32 = 60;
+
+/// One recorded instruction: how many times the timers counted down before it, and the keys held while it ran
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub timer_ticks: u32,
+    pub input: u16,
+}
+
+/// A hash of the state after a number of instructions, which a replay has to match
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub instructions: u64,
+    pub hash: String,
+}
+
+/// A recorded session: how the program was started, and the input and timing of every instruction after.
+/// Replaying it runs the same instructions in the same order, so it reproduces the session exactly.
+///
+/// Movies are text. A header lists the ROM's SHA-1, the random seed, the load address, font address and initial PC, the
+/// quirks turned on, the stack configuration and the instruction set; each following line is a step, as timer ticks and held
+/// keys in hex with an `xN` repeat count, or a state hash.
+///
+/// ```text
+/// CHIP-8 movie 2
+/// rom 0b5b31cb1b7ad1a5e8a3d73f3e5db4d1fd9e4c41
+/// seed 42
+/// load 200
+/// font 000
+/// pc 200
+/// quirks increment_index
+/// stack 32
+/// variant chip8
+/// 1 0000
+/// 0 0000 x9
+/// 1 0020 x10
+/// hash 5d41402abc4b2a76
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Movie {
+    /// SHA-1 of the ROM, in lowercase hex
+    pub rom_sha1: String,
+    pub seed: u64,
+    /// Where the ROM was loaded and started, and where the font was
+    pub layout: MemoryLayout,
+    pub quirks: Quirks,
+    pub stack_config: StackConfig,
+    pub variant: Variant,
+    /// The steps, with runs of identical steps merged
+    runs: Vec<(Step, u32)>,
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+impl Movie {
+    /// Instructions the movie runs
+    #[must_use]
+    pub fn instructions(&self) -> u64 {
+        self.runs.iter().map(|(_, count)| u64::from(*count)).sum()
+    }
+
+    /// Every step in order.
+    pub fn steps(&self) -> impl Iterator<Item = Step> + '_ {
+        self.runs.iter().flat_map(|(step, count)| (0..*count).map(|_| *step))
+    }
+
+    fn push(&mut self, step: Step, count: u32) {
+        match self.runs.last_mut() {
+            Some((last, run)) if *last == step && run.checked_add(count).is_some() => *run += count,
+            _ => self.runs.push((step, count)),
+        }
+    }
+}
+
+/// A movie could not be read, or replaying it went wrong
+#[derive(Clone, Debug, PartialEq)]
+pub enum MovieError {
+    /// The text does not start like a movie
+    NotAMovie,
+    /// The movie was written in another format version
+    Version(u32),
+    /// The line with this number, counting from 1, is missing or cannot be read
+    Corrupt(usize),
+    /// The movie was recorded with another ROM, whose SHA-1 it holds
+    WrongRom(String),
+    /// The ROM does not fit in memory at the movie's load address
+    RomTooLarge(ProgramTooLarge),
+    /// The state stopped matching the recording: its hash after this many instructions differs
+    Desync { instructions: u64, expected: String, actual: String },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAMovie => write!(f, "not a CHIP-8 movie"),
+            Self::Version(version) => write!(f, "movie format version {version} is not supported, expected {MOVIE_VERSION}"),
+            Self::Corrupt(line) => write!(f, "movie is corrupt at line {line}"),
+            Self::WrongRom(sha1) => write!(f, "movie was recorded with another ROM, with SHA-1 {sha1}"),
+            Self::RomTooLarge(err) => write!(f, "cannot load the ROM where the movie starts it: {err}"),
+            Self::Desync { instructions, expected, actual } =>
+                write!(f, "desync after {instructions} instructions: state hash is {actual}, recorded {expected}"),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let quirks: Vec<&str> = Quirks::FLAG_NAMES.iter().zip(self.quirks.flags()).filter(|(_, on)| *on).map(|(name, _)| *name).collect();
+        writeln!(f, "{MAGIC} {MOVIE_VERSION}")?;
+        writeln!(f, "rom {}", self.rom_sha1)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "load {:03X}", self.layout.program_start)?;
+        writeln!(f, "font {:03X}", self.layout.font_address)?;
+        writeln!(f, "pc {:03X}", self.layout.initial_pc)?;
+        writeln!(f, "quirks {}", quirks.join(" "))?;
+        writeln!(f, "stack {}{}", self.stack_config.max_depth, if self.stack_config.in_memory { " memory" } else { "" })?;
+        writeln!(f, "variant {}", self.variant.name())?;
+
+        // runs are split where a checkpoint falls inside them
+        let mut checkpoints = self.checkpoints.iter().peekable();
+        let mut instructions = 0;
+        for (step, count) in &self.runs {
+            let mut left = u64::from(*count);
+            while left > 0 {
+                let run = checkpoints.peek()
+                    .map(|checkpoint| checkpoint.instructions.saturating_sub(instructions))
+                    .filter(|until| (1..left).contains(until))
+                    .unwrap_or(left);
+                write!(f, "{} {:04X}", step.timer_ticks, step.input)?;
+                if run > 1 {
+                    write!(f, " x{run}")?;
+                }
+                writeln!(f)?;
+                instructions += run;
+                left -= run;
+                while let Some(checkpoint) = checkpoints.next_if(|checkpoint| checkpoint.instructions == instructions) {
+                    writeln!(f, "hash {}", checkpoint.hash)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Movie {
+    type Err = MovieError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+        let version = lines.next()
+            .and_then(|(_, line)| line.strip_prefix(MAGIC))
+            .ok_or(MovieError::NotAMovie)?;
+        match version.trim().parse() {
+            Ok(MOVIE_VERSION) => {},
+            Ok(version) => return Err(MovieError::Version(version)),
+            Err(_) => return Err(MovieError::Corrupt(1)),
+        }
+
+        // the rom, seed, load, font, pc, quirks, stack and variant lines, which all have to be there
+        let mut seen = [false; 8];
+        let mut movie = Self {
+            rom_sha1: String::new(),
+            seed: 0,
+            layout: MemoryLayout::default(),
+            quirks: Quirks::default(),
+            stack_config: StackConfig::default(),
+            variant: Variant::default(),
+            runs: vec![],
+            checkpoints: vec![],
+        };
+        let mut instructions = 0;
+        let mut last = 1;
+        for (number, line) in lines.filter(|(_, line)| !line.is_empty()) {
+            let corrupt = || MovieError::Corrupt(number);
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "rom" if value.len() == 40 && value.chars().all(|c| c.is_ascii_hexdigit()) => {
+                    movie.rom_sha1 = value.to_ascii_lowercase();
+                    seen[0] = true;
+                },
+                "seed" => {
+                    movie.seed = value.parse().map_err(|_| corrupt())?;
+                    seen[1] = true;
+                },
+                "load" => {
+                    movie.layout.program_start = parse_address(value).ok_or_else(corrupt)?;
+                    seen[2] = true;
+                },
+                "font" => {
+                    movie.layout.font_address = parse_address(value).filter(|addr| *addr <= 0x1000 - FONT_SIZE).ok_or_else(corrupt)?;
+                    seen[3] = true;
+                },
+                "pc" => {
+                    movie.layout.initial_pc = parse_address(value).ok_or_else(corrupt)?;
+                    seen[4] = true;
+                },
+                "quirks" => {
+                    let mut flags = [false; Quirks::FLAG_NAMES.len()];
+                    for name in value.split_whitespace() {
+                        flags[Quirks::FLAG_NAMES.iter().position(|known| *known == name).ok_or_else(corrupt)?] = true;
+                    }
+                    movie.quirks = Quirks::from_flags(flags);
+                    seen[5] = true;
+                },
+                "stack" => {
+                    let (depth, in_memory) = match value.split_once(' ') {
+                        Some((depth, "memory")) => (depth, true),
+                        Some(_) => return Err(corrupt()),
+                        None => (value, false),
+                    };
+                    movie.stack_config = StackConfig { max_depth: depth.parse().map_err(|_| corrupt())?, in_memory };
+                    seen[6] = true;
+                },
+                "variant" => {
+                    movie.variant = Variant::from_name(value).ok_or_else(corrupt)?;
+                    seen[7] = true;
+                },
+                "hash" if !value.is_empty() => movie.checkpoints.push(Checkpoint { instructions, hash: String::from(value) }),
+                _ => {
+                    let mut fields = line.split_whitespace();
+                    let timer_ticks = fields.next().and_then(|ticks| ticks.parse().ok()).ok_or_else(corrupt)?;
+                    let input = fields.next().and_then(|input| u16::from_str_radix(input, 16).ok()).ok_or_else(corrupt)?;
+                    let count = match fields.next() {
+                        Some(count) => count.strip_prefix('x').and_then(|count| count.parse::<u32>().ok()).filter(|count| *count > 0).ok_or_else(corrupt)?,
+                        None => 1,
+                    };
+                    if fields.next().is_some() {
+                        return Err(corrupt());
+                    }
+                    movie.push(Step { timer_ticks, input }, count);
+                    instructions += u64::from(count);
+                },
+            }
+            last = number;
+        }
+
+        if seen.contains(&false) {
+            return Err(MovieError::Corrupt(last + 1));
+        }
+        Ok(movie)
+    }
+}
+
+/// Records a session as it runs, for a program just loaded.
+pub struct MovieRecorder {
+    movie: Movie,
+    instructions: u64,
+    /// Timer ticks since the last checkpoint
+    timer_ticks: u32,
+}
+
+impl MovieRecorder {
+    /// Starts recording the program just loaded into `state`, from `rom`, reseeding its random number generator with `seed`.
+    #[must_use]
+    pub fn start(state: &mut Chip8State, rom: &[u8], seed: u64) -> Self {
+        state.rng = StdRng::seed_from_u64(seed);
+        let movie = Movie {
+            rom_sha1: sha1_hex(rom),
+            seed,
+            layout: MemoryLayout { initial_pc: state.pc, ..state.layout },
+            quirks: state.quirks,
+            stack_config: state.stack_config,
+            variant: state.variant,
+            runs: vec![],
+            checkpoints: vec![],
+        };
+        Self { movie, instructions: 0, timer_ticks: 0 }
+    }
+
+    /// Runs one instruction like `Chip8State::tick`, recording the timer ticks and the keys held.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `Chip8State::tick`; the instruction stays recorded, so replaying the movie panics the same way.
+    pub fn tick(&mut self, state: &mut Chip8State, time_passed: u32) {
+        self.movie.push(Step { timer_ticks: time_passed, input: state.input }, 1);
+        self.instructions += 1;
+        state.tick(time_passed);
+
+        self.timer_ticks = self.timer_ticks.saturating_add(time_passed);
+        if self.timer_ticks >= CHECKPOINT_INTERVAL {
+            self.checkpoint(state);
+        }
+    }
+
+    /// Runs one 60 Hz frame like `Chip8State::run_frame`, recording it.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `tick`.
+    pub fn run_frame(&mut self, state: &mut Chip8State, instructions: u32) {
+        for i in 0..instructions {
+            self.tick(state, u32::from(i == 0));
+        }
+    }
+
+    /// Instructions recorded so far
+    #[must_use]
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Stops recording, hashing the state it ended in.
+    #[must_use]
+    pub fn finish(mut self, state: &Chip8State) -> Movie {
+        if self.movie.checkpoints.last().map_or(0, |checkpoint| checkpoint.instructions) < self.instructions {
+            self.checkpoint(state);
+        }
+        self.movie
+    }
+
+    fn checkpoint(&mut self, state: &Chip8State) {
+        self.movie.checkpoints.push(Checkpoint { instructions: self.instructions, hash: state_hash(state) });
+        self.timer_ticks = 0;
+    }
+}
+
+/// Replays a movie, checking that the state matches the recording at every checkpoint.
+pub struct MoviePlayer {
+    movie: Movie,
+    /// The run being played, and how much of it has been
+    run: usize,
+    played_in_run: u32,
+    instructions: u64,
+    next_checkpoint: usize,
+}
+
+impl MoviePlayer {
+    /// Starts replaying: checks that `rom` is the recorded ROM, then resets `state` and starts it as the recording did.
+    ///
+    /// # Errors
+    ///
+    /// Fails without changing the state if the movie was recorded with another ROM, or the ROM does not fit at its load address.
+    pub fn start(movie: Movie, state: &mut Chip8State, rom: &[u8]) -> Result<Self, MovieError> {
+        if sha1_hex(rom) != movie.rom_sha1 {
+            return Err(MovieError::WrongRom(movie.rom_sha1));
+        }
+
+        let mut started = state.clone();
+        started.layout = movie.layout;
+        started.variant = movie.variant;
+        started.reset();
+        started.load_program(rom).map_err(MovieError::RomTooLarge)?;
+        started.input = 0;
+        started.quirks = movie.quirks;
+        started.stack_config = movie.stack_config;
+        started.rng = StdRng::seed_from_u64(movie.seed);
+        *state = started;
+        Ok(Self { movie, run: 0, played_in_run: 0, instructions: 0, next_checkpoint: 0 })
+    }
+
+    /// Runs the next recorded instruction with the keys held then. Returns false, without running anything, once the movie is over.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `MovieError::Desync` when the state no longer matches the recording.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `Chip8State::tick`.
+    pub fn tick(&mut self, state: &mut Chip8State) -> Result<bool, MovieError> {
+        let Some((step, count)) = self.movie.runs.get(self.run).copied() else {
+            return Ok(false)
+        };
+        self.played_in_run += 1;
+        if self.played_in_run == count {
+            self.run += 1;
+            self.played_in_run = 0;
+        }
+        self.instructions += 1;
+        state.input = step.input;
+        state.tick(step.timer_ticks);
+
+        while let Some(checkpoint) = self.movie.checkpoints.get(self.next_checkpoint).filter(|checkpoint| checkpoint.instructions <= self.instructions) {
+            self.next_checkpoint += 1;
+            let actual = state_hash(state);
+            if checkpoint.instructions == self.instructions && actual != checkpoint.hash {
+                return Err(MovieError::Desync { instructions: self.instructions, expected: checkpoint.hash.clone(), actual });
+            }
+        }
+        Ok(true)
+    }
+
+    /// Instructions replayed so far
+    #[must_use]
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    #[must_use]
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.run >= self.movie.runs.len()
+    }
+}
+
+/// Hashes everything a snapshot holds, which is all a replay can drift in besides the random number generator
+fn state_hash(state: &Chip8State) -> String {
+    format!("{:016x}", state.state_hash())
+}
+
+/// A hex address in memory, as in the `load`, `font` and `pc` lines
+fn parse_address(text: &str) -> Option<u16> {
+    u16::from_str_radix(text, 16).ok().filter(|addr| *addr < 0x1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 200: RND(V0, FF)  202: SKP(V1)  204: JP(200)  206: LDF(V0)  208: DRW(V2, V2, 5)  20A: JP(200)
+    const ROM: [u8; 12] = [0xC0, 0xFF, 0xE1, 0x9E, 0x12, 0x00, 0xF0, 0x29, 0xD2, 0x25, 0x12, 0x00];
+
+    fn record() -> (Movie, Chip8State) {
+        let mut state = Chip8State::default();
+        state.load_program(&ROM).unwrap();
+        state.quirks = Quirks::VIP;
+        let mut recorder = MovieRecorder::start(&mut state, &ROM, 7);
+        for frame in 0..150 {
+            state.input = if frame % 40 < 5 { 0x0001 } else { 0 };
+            recorder.run_frame(&mut state, 10);
+        }
+        (recorder.finish(&state), state)
+    }
+
+    #[test]
+    fn test_replay() {
+        let (movie, recorded) = record();
+        assert_eq!(movie.instructions(), 1500);
+        // two full seconds, then the end
+        assert_eq!(movie.checkpoints.len(), 3);
+
+        let movie: Movie = movie.to_string().parse().unwrap();
+        let mut state = Chip8State::default();
+        let mut player = MoviePlayer::start(movie, &mut state, &ROM).unwrap();
+        while player.tick(&mut state).unwrap() {}
+
+        assert_eq!(player.instructions(), 1500);
+        assert_eq!(state.quirks, Quirks::VIP);
+        assert_eq!(state.framebuffer, recorded.framebuffer);
+        assert_eq!(state.registers, recorded.registers)
+    }
+
+    #[test]
+    fn test_desync() {
+        let (mut movie, _) = record();
+        // other random numbers draw other digits
+        movie.seed = 8;
+
+        let mut state = Chip8State::default();
+        let mut player = MoviePlayer::start(movie, &mut state, &ROM).unwrap();
+        let result = loop {
+            match player.tick(&mut state) {
+                Ok(true) => {},
+                result => break result,
+            }
+        };
+        assert!(matches!(result, Err(MovieError::Desync { instructions: 591, .. })), "{result:?}");
+
+        let (movie, _) = record();
+        assert_eq!(MoviePlayer::start(movie, &mut state, &[0x12, 0x00]).err().map(|err| err.to_string()),
+            Some(format!("movie was recorded with another ROM, with SHA-1 {}", sha1_hex(&ROM))));
+
+        let (mut movie, _) = record();
+        movie.layout = MemoryLayout { font_address: 0, program_start: 0xFFC, initial_pc: 0xFFC };
+        let state = Chip8State::default();
+        let mut started = state.clone();
+        assert_eq!(MoviePlayer::start(movie, &mut started, &ROM).err(), Some(MovieError::RomTooLarge(ProgramTooLarge { size: 12, capacity: 4 })));
+        assert_eq!(started.state_hash(), state.state_hash())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let (movie, _) = record();
+        let text = movie.to_string();
+
+        assert_eq!("PNG".parse::<Movie>(), Err(MovieError::NotAMovie));
+        assert_eq!(text.replacen("movie 2", "movie 1", 1).parse::<Movie>(), Err(MovieError::Version(1)));
+        assert_eq!(text.replacen("seed 7", "seed seven", 1).parse::<Movie>(), Err(MovieError::Corrupt(3)));
+        assert_eq!(text.replacen("quirks ", "quirks fast ", 1).parse::<Movie>(), Err(MovieError::Corrupt(7)));
+        assert_eq!(text.replacen("font 000", "font FC0", 1).parse::<Movie>(), Err(MovieError::Corrupt(5)));
+        assert_eq!(text.replacen("variant chip8", "variant schip", 1).parse::<Movie>(), Err(MovieError::Corrupt(9)));
+        for line in ["font 000\n", "pc 200\n", "variant chip8\n"] {
+            assert!(matches!(text.replacen(line, "", 1).parse::<Movie>(), Err(MovieError::Corrupt(_))), "{line}");
+        }
+        assert_eq!("CHIP-8 movie 2\n1 0000\n".parse::<Movie>(), Err(MovieError::Corrupt(3)))
+    }
+
+    #[test]
+    fn test_layout() {
+        let (movie, _) = record();
+        let text = movie.to_string().replacen("load 200\nfont 000\npc 200\n", "load 600\nfont 000\npc 600\n", 1);
+        assert_eq!(text.parse::<Movie>().map(|movie| movie.layout), Ok(MemoryLayout::ETI660));
+
+        let mut state = Chip8State { layout: MemoryLayout::FONT_050, ..Chip8State::default() };
+        state.load_program(&ROM).unwrap();
+        let recorder = MovieRecorder::start(&mut state, &ROM, 7);
+        let text = recorder.finish(&state).to_string();
+        assert!(text.contains("load 200\nfont 050\npc 200\n"), "{text}");
+
+        let mut state = Chip8State::default();
+        MoviePlayer::start(text.parse().unwrap(), &mut state, &ROM).unwrap();
+        assert_eq!((state.layout, state.pc), (MemoryLayout::FONT_050, 0x200))
+    }
+}
@@ -0,0 +1,446 @@
+use std::{collections::VecDeque, error::Error, fmt, io::{self, Read, Write}, net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs}, time::{Duration, Instant}};
+
+use rand::{SeedableRng, rngs::StdRng};
+
+use crate::{Chip8State, layout::MemoryLayout, quirks::Quirks, romdb::sha1_hex, stack::StackConfig, variant::Variant};
+
+const MAGIC: &[u8; 4] = b"C8NP";
+/// Protocol version both players have to speak
+pub const NETPLAY_VERSION: u8 = 4;
+/// Frames between the state hashes the players compare
+pub const SYNC_INTERVAL: u32 = 60;
+/// How long to wait for the other player before giving up
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+/// How long closing the connection waits for the other player to close theirs, so quitting never hangs on a silent peer
+const CLOSE_TIMEOUT: Duration = Duration::from_millis(500);
+/// Every CHIP-8 key
+pub const ALL_KEYS: u16 = 0xFFFF;
+
+const JOIN: u8 = b'J';
+const HELLO: u8 = b'H';
+const REFUSE: u8 = b'R';
+const INPUT: u8 = b'I';
+const SYNC: u8 = b'S';
+
+/// How the host runs the game, which the joining player adopts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NetplayConfig {
+    /// Instructions per frame
+    pub ipf: u32,
+    /// Seed for both players' random number generators
+    pub seed: u64,
+    /// The CHIP-8 keys each player controls, as `input` bitfields: player 1 hosts, player 2 joins
+    pub keys: [u16; 2],
+    /// Frames a key press takes to reach the game, giving it time to reach the other player too
+    pub delay: u8,
+}
+
+impl Default for NetplayConfig {
+    fn default() -> Self {
+        Self { ipf: 10, seed: 0, keys: [ALL_KEYS; 2], delay: 2 }
+    }
+}
+
+/// Netplay could not start, or stopped
+#[derive(Debug)]
+pub enum NetplayError {
+    Io(io::Error),
+    /// The other player closed the connection
+    Disconnected,
+    /// The other player speaks another protocol version
+    Version(u8),
+    /// The players loaded different ROMs
+    WrongRom,
+    /// The other player sent something that does not fit the protocol
+    Protocol,
+    /// The players' states differed after this frame
+    Desync { frame: u32 },
+}
+
+impl fmt::Display for NetplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => write!(f, "the other player stopped responding"),
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Disconnected => write!(f, "the other player disconnected"),
+            Self::Version(version) => write!(f, "the other player uses netplay version {version}, expected {NETPLAY_VERSION}"),
+            Self::WrongRom => write!(f, "the other player loaded another ROM"),
+            Self::Protocol => write!(f, "the other player sent an invalid message"),
+            Self::Desync { frame } => write!(f, "desync after frame {frame}: the players' states differ"),
+        }
+    }
+}
+
+impl Error for NetplayError {}
+
+impl From<io::Error> for NetplayError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe => Self::Disconnected,
+            _ => Self::Io(err),
+        }
+    }
+}
+
+/// Runs a game in lockstep with a second player over TCP.
+/// Every frame both players send the keys they hold and wait for each other's, so both run the same frames with the same
+/// input. Each player's keys are limited to those `NetplayConfig::keys` gives them. Every `SYNC_INTERVAL` frames they also
+/// exchange state hashes, and a difference stops the game.
+pub struct Netplay {
+    stream: TcpStream,
+    config: NetplayConfig,
+    /// 0 for the host, 1 for the player who joined
+    player: usize,
+    frame: u32,
+    /// Keys for the frames from `frame` on, already sent or received
+    local_inputs: VecDeque<u16>,
+    remote_inputs: VecDeque<u16>,
+    /// Frame the next input received is for
+    next_remote_frame: u32,
+    /// State hashes by frame, not yet compared with the other player's
+    local_hashes: VecDeque<(u32, u64)>,
+    remote_hashes: VecDeque<(u32, u64)>,
+}
+
+impl Netplay {
+    /// Waits on `listener` for a second player and starts the game for both as player 1, with the program just loaded into
+    /// `state` from `rom`. The other player takes `config` and the quirks, stack configuration, memory layout and instruction set of `state`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the connection fails, or the other player loaded another ROM or speaks another protocol version.
+    pub fn host(listener: &TcpListener, state: &mut Chip8State, rom: &[u8], config: NetplayConfig) -> Result<Self, NetplayError> {
+        let (stream, _) = listener.accept()?;
+        let mut netplay = Self::new(stream, config, 0)?;
+
+        let mut join = [0; 46];
+        netplay.stream.read_exact(&mut join)?;
+        if join[..5] != [MAGIC[0], MAGIC[1], MAGIC[2], MAGIC[3], JOIN] {
+            return Err(NetplayError::Protocol);
+        }
+        if join[5] != NETPLAY_VERSION {
+            netplay.send(&[REFUSE, 1, NETPLAY_VERSION])?;
+            return Err(NetplayError::Version(join[5]));
+        }
+        if join[6..] != *sha1_hex(rom).as_bytes() {
+            netplay.send(&[REFUSE, 2])?;
+            return Err(NetplayError::WrongRom);
+        }
+
+        let mut hello = vec![HELLO];
+        hello.extend_from_slice(&config.seed.to_be_bytes());
+        hello.extend_from_slice(&config.ipf.to_be_bytes());
+        hello.extend(state.quirks.flags().map(u8::from));
+        hello.extend_from_slice(&[state.stack_config.max_depth, u8::from(state.stack_config.in_memory)]);
+        for addr in [state.layout.font_address, state.layout.program_start, state.layout.initial_pc] {
+            hello.extend_from_slice(&addr.to_be_bytes());
+        }
+        hello.push(state.variant as u8);
+        hello.extend_from_slice(&config.keys[0].to_be_bytes());
+        hello.extend_from_slice(&config.keys[1].to_be_bytes());
+        hello.push(config.delay);
+        netplay.send(&hello)?;
+
+        netplay.start(state);
+        Ok(netplay)
+    }
+
+    /// Joins the game hosted at `address` as player 2, with the program just loaded into `state` from `rom`.
+    /// The host's instructions per frame, seed, key assignment, quirks, stack configuration, memory layout and instruction set
+    /// replace this player's, and the program is loaded again where the host's layout puts it.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the connection fails, or the host loaded another ROM or speaks another protocol version.
+    pub fn join(address: impl ToSocketAddrs, state: &mut Chip8State, rom: &[u8]) -> Result<Self, NetplayError> {
+        let stream = TcpStream::connect(address)?;
+        let mut netplay = Self::new(stream, NetplayConfig::default(), 1)?;
+
+        let mut join = MAGIC.to_vec();
+        join.extend_from_slice(&[JOIN, NETPLAY_VERSION]);
+        join.extend_from_slice(sha1_hex(rom).as_bytes());
+        netplay.send(&join)?;
+
+        let [kind] = netplay.read()?;
+        match kind {
+            HELLO => {},
+            REFUSE => return Err(match netplay.read()? {
+                [1] => NetplayError::Version(netplay.read::<1>()?[0]),
+                [2] => NetplayError::WrongRom,
+                _ => NetplayError::Protocol,
+            }),
+            _ => return Err(NetplayError::Protocol),
+        }
+        let seed = u64::from_be_bytes(netplay.read()?);
+        let ipf = u32::from_be_bytes(netplay.read()?);
+        let quirks = Quirks::from_flags(netplay.read()?.map(|flag: u8| flag != 0));
+        let [max_depth, in_memory] = netplay.read()?;
+        let layout = MemoryLayout {
+            font_address: u16::from_be_bytes(netplay.read()?),
+            program_start: u16::from_be_bytes(netplay.read()?),
+            initial_pc: u16::from_be_bytes(netplay.read()?),
+        };
+        let [variant] = netplay.read()?;
+        let variant = *Variant::ALL.get(usize::from(variant)).ok_or(NetplayError::Protocol)?;
+        let keys = [u16::from_be_bytes(netplay.read()?), u16::from_be_bytes(netplay.read()?)];
+        let [delay] = netplay.read()?;
+
+        netplay.config = NetplayConfig { ipf, seed, keys, delay };
+        state.quirks = quirks;
+        state.stack_config = StackConfig { max_depth, in_memory: in_memory != 0 };
+        state.variant = variant;
+        if layout != state.layout {
+            layout.validate().map_err(|_| NetplayError::Protocol)?;
+            state.layout = layout;
+            state.reset();
+            state.load_program(rom).map_err(|_| NetplayError::Protocol)?;
+        }
+        netplay.start(state);
+        Ok(netplay)
+    }
+
+    fn new(stream: TcpStream, config: NetplayConfig, player: usize) -> io::Result<Self> {
+        // inputs are tiny and needed right away
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        Ok(Self {
+            stream,
+            config,
+            player,
+            frame: 0,
+            local_inputs: VecDeque::new(),
+            remote_inputs: VecDeque::new(),
+            next_remote_frame: 0,
+            local_hashes: VecDeque::new(),
+            remote_hashes: VecDeque::new(),
+        })
+    }
+
+    fn start(&mut self, state: &mut Chip8State) {
+        state.rng = StdRng::seed_from_u64(self.config.seed);
+        state.input = 0;
+        // nobody presses anything in the frames before the first input arrives
+        let delay = usize::from(self.config.delay);
+        self.local_inputs = VecDeque::from(vec![0; delay]);
+        self.remote_inputs = VecDeque::from(vec![0; delay]);
+        self.next_remote_frame = u32::from(self.config.delay);
+    }
+
+    /// Runs one frame with `keys`, the keys this player holds, and the other player's, waiting for them if they have not arrived.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the other player disconnects or stops responding, or the players' states differ.
+    ///
+    /// # Panics
+    ///
+    /// Panics like `Chip8State::run_frame`.
+    pub fn run_frame(&mut self, state: &mut Chip8State, keys: u16) -> Result<(), NetplayError> {
+        let keys = keys & self.config.keys[self.player];
+        let mut input = vec![INPUT];
+        input.extend_from_slice(&(self.frame + u32::from(self.config.delay)).to_be_bytes());
+        input.extend_from_slice(&keys.to_be_bytes());
+        self.send(&input)?;
+        self.local_inputs.push_back(keys);
+
+        while self.remote_inputs.is_empty() {
+            self.receive()?;
+        }
+        let local = self.local_inputs.pop_front().unwrap_or_default();
+        let remote = self.remote_inputs.pop_front().unwrap_or_default();
+        state.input = local | remote;
+        state.run_frame(self.config.ipf);
+        self.frame += 1;
+
+        if self.frame.is_multiple_of(SYNC_INTERVAL) {
+            let hash = state.state_hash();
+            let mut sync = vec![SYNC];
+            sync.extend_from_slice(&self.frame.to_be_bytes());
+            sync.extend_from_slice(&hash.to_be_bytes());
+            self.send(&sync)?;
+            self.local_hashes.push_back((self.frame, hash));
+            self.compare_hashes()?;
+        }
+        Ok(())
+    }
+
+    /// Frames run so far
+    #[must_use]
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// 1 for the host, 2 for the player who joined
+    #[must_use]
+    pub fn player(&self) -> usize {
+        self.player + 1
+    }
+
+    #[must_use]
+    pub fn config(&self) -> &NetplayConfig {
+        &self.config
+    }
+
+    /// Reads one message from the other player.
+    fn receive(&mut self) -> Result<(), NetplayError> {
+        let [kind] = self.read()?;
+        let frame = u32::from_be_bytes(self.read()?);
+        match kind {
+            INPUT => {
+                let keys = u16::from_be_bytes(self.read()?);
+                if frame != self.next_remote_frame {
+                    return Err(NetplayError::Protocol);
+                }
+                self.next_remote_frame += 1;
+                self.remote_inputs.push_back(keys & self.config.keys[1 - self.player]);
+            },
+            SYNC => {
+                let hash = u64::from_be_bytes(self.read()?);
+                self.remote_hashes.push_back((frame, hash));
+                self.compare_hashes()?;
+            },
+            _ => return Err(NetplayError::Protocol),
+        }
+        Ok(())
+    }
+
+    fn compare_hashes(&mut self) -> Result<(), NetplayError> {
+        while let (Some(local), Some(remote)) = (self.local_hashes.front(), self.remote_hashes.front()) {
+            if local != remote {
+                return Err(NetplayError::Desync { frame: local.0.min(remote.0) });
+            }
+            self.local_hashes.pop_front();
+            self.remote_hashes.pop_front();
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        self.stream.write_all(message)
+    }
+
+    fn read<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.stream.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl Drop for Netplay {
+    fn drop(&mut self) {
+        // closing with unread messages would reset the connection and could lose the last inputs on their way to the
+        // other player, so this side stops sending and reads until the other side has closed too, or `CLOSE_TIMEOUT` passes
+        let _ = self.stream.shutdown(Shutdown::Write);
+        let deadline = Instant::now() + CLOSE_TIMEOUT;
+        let mut buffer = [0; 256];
+        while let Some(left) = deadline.checked_duration_since(Instant::now()).filter(|left| !left.is_zero()) {
+            if self.stream.set_read_timeout(Some(left)).is_err() || matches!(self.stream.read(&mut buffer), Ok(0) | Err(_)) {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    // 200: RND(V0, 0F)  202: ADD(V1, V2)  204: SKP(V3)  206: JP(200)  208: LDF(V0)  20A: DRW(V1, V4, 5)  20C: JP(200)
+    const ROM: [u8; 14] = [0xC0, 0x0F, 0x81, 0x24, 0xE3, 0x9E, 0x12, 0x00, 0xF0, 0x29, 0xD1, 0x45, 0x12, 0x00];
+
+    /// Plays `frames` frames as both players, player 1 holding key 0 and player 2 key 1 on every third frame.
+    /// `meddle` may change player 2's state after each frame.
+    fn play(frames: u32, meddle: fn(u32, &mut Chip8State)) -> (Result<Chip8State, NetplayError>, Result<Chip8State, NetplayError>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let host = thread::spawn(move || {
+            let mut state = Chip8State { layout: MemoryLayout::FONT_050, variant: Variant::Chip8E, ..Chip8State::default() };
+            state.reset();
+            state.load_program(&ROM).unwrap();
+            state.quirks = Quirks::VIP;
+            let config = NetplayConfig { ipf: 8, seed: 3, keys: [0x0001, 0x0002], delay: 2 };
+            let mut netplay = Netplay::host(&listener, &mut state, &ROM, config)?;
+            for frame in 0..frames {
+                // player 1 does not own key 1, so holding it does nothing
+                netplay.run_frame(&mut state, if frame % 3 == 0 { 0x0003 } else { 0 })?;
+            }
+            Ok(state)
+        });
+
+        let mut state = Chip8State::default();
+        state.load_program(&ROM).unwrap();
+        let guest = Netplay::join(address, &mut state, &ROM).and_then(|mut netplay| {
+            assert_eq!((netplay.player(), netplay.config().ipf, state.quirks, state.layout), (2, 8, Quirks::VIP, MemoryLayout::FONT_050));
+            assert_eq!(state.variant, Variant::Chip8E);
+            for frame in 0..frames {
+                netplay.run_frame(&mut state, if frame % 3 == 0 { 0x0002 } else { 0 })?;
+                meddle(frame, &mut state);
+            }
+            Ok(state)
+        });
+        (host.join().unwrap(), guest)
+    }
+
+    #[test]
+    fn test_lockstep() {
+        let (host, guest) = match play(130, |_, _| {}) {
+            (Ok(host), Ok(guest)) => (host, guest),
+            (host, guest) => panic!("netplay failed: {:?} {:?}", host.err(), guest.err()),
+        };
+
+        assert_eq!(host.registers, guest.registers);
+        assert_eq!(host.framebuffer, guest.framebuffer);
+        assert_ne!(host.framebuffer, [0; 256])
+    }
+
+    #[test]
+    fn test_desync() {
+        let (host, guest) = play(200, |frame, state| if frame == 100 {
+            state.registers[5] = 1;
+        });
+
+        let (host, guest) = (host.err(), guest.err());
+        assert!(matches!(guest, Some(NetplayError::Desync { frame: 120 })), "{guest:?}");
+        assert!(matches!(host, Some(NetplayError::Desync { frame: 120 } | NetplayError::Disconnected)), "{host:?}")
+    }
+
+    #[test]
+    fn test_wrong_rom() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let host = thread::spawn(move || {
+            let mut state = Chip8State::default();
+            Netplay::host(&listener, &mut state, &ROM, NetplayConfig::default()).err()
+        });
+
+        let mut state = Chip8State::default();
+        let guest = Netplay::join(address, &mut state, &[0x12, 0x00]).err();
+
+        assert!(matches!(guest, Some(NetplayError::WrongRom)), "{guest:?}");
+        assert!(matches!(host.join().unwrap(), Some(NetplayError::WrongRom)))
+    }
+
+    #[test]
+    fn test_quit_with_silent_peer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (guest_gone, wait_for_guest) = std::sync::mpsc::channel::<()>();
+        let host = thread::spawn(move || {
+            let mut state = Chip8State::default();
+            let netplay = Netplay::host(&listener, &mut state, &ROM, NetplayConfig::default()).unwrap();
+            // stays connected without reading or closing until the guest is gone
+            let _ = wait_for_guest.recv();
+            drop(netplay);
+        });
+
+        let mut state = Chip8State::default();
+        let netplay = Netplay::join(address, &mut state, &ROM).unwrap();
+        let start = Instant::now();
+        drop(netplay);
+        assert!(start.elapsed() < TIMEOUT / 2, "{:?}", start.elapsed());
+        guest_gone.send(()).unwrap();
+        host.join().unwrap()
+    }
+}
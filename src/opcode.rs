@@ -1,6 +1,6 @@
-use rand::random;
+use rand::Rng;
 
-use crate::{Reg, Chip8State, INSTR_SIZE, util::BCD};
+use crate::{Reg, Chip8State, INSTR_SIZE, util::BCD, variant::{Variant, color}};
 
 #[derive(Debug, PartialEq)]
 pub enum WaitStatus {
@@ -11,6 +11,8 @@ pub enum WaitStatus {
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq)]
 pub enum Opcode {
+    /// Call the 1802 machine code subroutine at nnn
+    SYS(u16),
     /// Clear the display
     CLS,
     /// Return from subroutine
@@ -41,12 +43,12 @@ pub enum Opcode {
     ADDVV(Reg, Reg),
     /// Set VF = Vx > Vy; Set Vx = Vx - Vy;
     SUB(Reg, Reg),
-    /// Set VF = lsb Vx; Set Vx = Vx >> 1
-    SHR(Reg),
+    /// Set VF = lsb Vx; Set Vx = Vx >> 1 (with the `shift_vy` quirk: Set Vx = Vy >> 1, VF = lsb Vy)
+    SHR(Reg, Reg),
     /// Set VF = Vy > Vx; Set Vx = Vy - Vx
     SUBN(Reg, Reg),
-    /// Set VF = msb Vx; Set Vx = Vx << 1
-    SHL(Reg),
+    /// Set VF = msb Vx; Set Vx = Vx << 1 (with the `shift_vy` quirk: Set Vx = Vy << 1, VF = msb Vy)
+    SHL(Reg, Reg),
     /// Skip next instruction if Vx != Vy
     SNEVV(Reg, Reg),
     /// Set I = nnn
@@ -79,6 +81,38 @@ pub enum Opcode {
     LDIV(Reg),
     /// Fill V0 to Vx with values from memory starting at address I; Set I = I + x + 1
     LDVI(Reg),
+    /// CHIP-8X: Step the background color on to the next of blue, black, green and red
+    BGC,
+    /// CHIP-8X: Set Vx = Vx + Vy, adding the low and the high nibbles separately, modulo 8
+    ADDN(Reg, Reg),
+    /// CHIP-8X: Set the foreground color of an area given by Vx and Vy (and n rows, if n > 0) to V(x + 1)
+    COL(Reg, Reg, u8),
+    /// CHIP-8X, CHIP-8E: Write Vx to the I/O port
+    OUT(Reg),
+    /// CHIP-8X, CHIP-8E: Wait for a byte on the I/O port, store it in Vx
+    IN(Reg),
+    /// CHIP-8E: Set Vx = the byte on the I/O port, without waiting
+    INP(Reg),
+    /// CHIP-8E: Stop execution
+    STOP,
+    /// CHIP-8E: Wait until the delay timer reaches 0
+    WAITDT,
+    /// CHIP-8E: Skip next instruction
+    SKIP,
+    /// CHIP-8E: Skip next instruction if Vx > Vy
+    SGTVV(Reg, Reg),
+    /// CHIP-8E: Store Vx to Vy in memory starting at address I; Set I = I + y - x + 1
+    LDIVV(Reg, Reg),
+    /// CHIP-8E: Fill Vx to Vy with values from memory starting at address I; Set I = I + y - x + 1
+    LDVVI(Reg, Reg),
+    /// CHIP-8E: Jump back nn bytes from this instruction
+    JPB(u8),
+    /// CHIP-8E: Jump forward nn bytes from this instruction
+    JPF(u8),
+    /// CHIP-8E: Skip the next Vx bytes
+    SKB(Reg),
+    /// CHIP-8E: Set delay timer = Vx, then wait until it reaches 0
+    DLY(Reg),
     /// No operation
     NOP,
 }
@@ -88,6 +122,7 @@ impl From<u16> for Opcode {
         match value {
             0x00E0 => Self::CLS,
             0x00EE => Self::RET,
+            0x0000..=0x0FFF => Self::SYS(value),
             0x1000..=0x1FFF => Self::JP(value & 0x0FFF),
             0x2000..=0x2FFF => Self::CALL(value & 0x0FFF),
             0x3000..=0x3FFF => Self::SEVB(Reg::from(((value & 0x0F00) >> 8) as u8), (value & 0x00FF) as u8),
@@ -103,9 +138,9 @@ impl From<u16> for Opcode {
                     0x3 => Self::XORVV(Reg::from(((value & 0x0F00) >> 8) as u8), Reg::from(((value & 0x00F0) >> 4) as u8)),
                     0x4 => Self::ADDVV(Reg::from(((value & 0x0F00) >> 8) as u8), Reg::from(((value & 0x00F0) >> 4) as u8)),
                     0x5 => Self::SUB(Reg::from(((value & 0x0F00) >> 8) as u8), Reg::from(((value & 0x00F0) >> 4) as u8)),
-                    0x6 => Self::SHR(Reg::from(((value & 0x0F00) >> 8) as u8)),
+                    0x6 => Self::SHR(Reg::from(((value & 0x0F00) >> 8) as u8), Reg::from(((value & 0x00F0) >> 4) as u8)),
                     0x7 => Self::SUBN(Reg::from(((value & 0x0F00) >> 8) as u8), Reg::from(((value & 0x00F0) >> 4) as u8)),
-                    0xE => Self::SHL(Reg::from(((value & 0x0F00) >> 8) as u8)),
+                    0xE => Self::SHL(Reg::from(((value & 0x0F00) >> 8) as u8), Reg::from(((value & 0x00F0) >> 4) as u8)),
                     _ => Self::NOP,
                 }
             },
@@ -135,19 +170,127 @@ impl From<u16> for Opcode {
                     _ => Self::NOP,
                 }
             },
-            _ => Self::NOP,
         }
     }
 }
 
 impl Opcode {
+    /// Decodes an instruction of a variant's instruction set. `From<u16>` decodes plain CHIP-8.
+    ///
+    /// CHIP-8X adds `02A0` (`BGC`), `5XY1` (`ADDN`), `FXF8` (`OUT`) and `FXFB` (`IN`), and replaces `BNNN` with `BXYN`
+    /// (`COL`). `COL` colors whole 8-pixel columns: the low nibble of Vx is the first column, and the high nibble the number
+    /// of columns after it also colored. With n = 0, Vy gives 4-pixel rows the same way; otherwise, it is the first of n pixel
+    /// rows. The second keypad's `EXF2` and `EXF5` are not emulated.
+    ///
+    /// CHIP-8E adds `00ED` (`STOP`), `0151` (`WAITDT`), `0188` (`SKIP`), `5XY1` (`SGTVV`), `5XY2` (`LDIVV`), `5XY3`
+    /// (`LDVVI`), `BBNN` (`JPB`), `BFNN` (`JPF`), `FX03` (`OUT`), `FX1B` (`SKB`), `FX4F` (`DLY`), `FXE3` (`IN`) and `FXE7`
+    /// (`INP`).
+    #[must_use]
+    pub fn decode(value: u16, variant: Variant) -> Self {
+        let x = Reg::from(((value & 0x0F00) >> 8) as u8);
+        let y = Reg::from(((value & 0x00F0) >> 4) as u8);
+        #[allow(clippy::cast_possible_truncation)]
+        let byte = value as u8;
+        match (variant, value & 0xF000, value & 0xF00F, value & 0xF0FF) {
+            (Variant::Chip8X, ..) if value == 0x02A0 => Self::BGC,
+            (Variant::Chip8X, _, 0x5001, _) => Self::ADDN(x, y),
+            (Variant::Chip8X, 0xB000, ..) => Self::COL(x, y, (value & 0x000F) as u8),
+            (Variant::Chip8X, .., 0xF0F8) | (Variant::Chip8E, .., 0xF003) => Self::OUT(x),
+            (Variant::Chip8X, .., 0xF0FB) | (Variant::Chip8E, .., 0xF0E3) => Self::IN(x),
+            (Variant::Chip8E, ..) if value == 0x00ED => Self::STOP,
+            (Variant::Chip8E, ..) if value == 0x0151 => Self::WAITDT,
+            (Variant::Chip8E, ..) if value == 0x0188 => Self::SKIP,
+            (Variant::Chip8E, _, 0x5001, _) => Self::SGTVV(x, y),
+            (Variant::Chip8E, _, 0x5002, _) => Self::LDIVV(x, y),
+            (Variant::Chip8E, _, 0x5003, _) => Self::LDVVI(x, y),
+            (Variant::Chip8E, ..) if value & 0xFF00 == 0xBB00 => Self::JPB(byte),
+            (Variant::Chip8E, ..) if value & 0xFF00 == 0xBF00 => Self::JPF(byte),
+            (Variant::Chip8E, .., 0xF01B) => Self::SKB(x),
+            (Variant::Chip8E, .., 0xF04F) => Self::DLY(x),
+            (Variant::Chip8E, .., 0xF0E7) => Self::INP(x),
+            _ => Self::from(value),
+        }
+    }
+
+    /// The memory address encoded in the instruction, for opcodes that carry one.
+    #[must_use]
+    pub fn target_address(&self) -> Option<u16> {
+        match self {
+            Opcode::SYS(addr) | Opcode::JP(addr) | Opcode::CALL(addr) | Opcode::LDI(addr) | Opcode::JPV0(addr) => Some(*addr),
+            _ => None,
+        }
+    }
+
+    /// The mnemonic naming this variant, without operands.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Opcode::SYS(..) => "SYS",
+            Opcode::CLS => "CLS",
+            Opcode::RET => "RET",
+            Opcode::JP(..) => "JP",
+            Opcode::CALL(..) => "CALL",
+            Opcode::SEVB(..) => "SEVB",
+            Opcode::SNEVB(..) => "SNEVB",
+            Opcode::SEVV(..) => "SEVV",
+            Opcode::LDVB(..) => "LDVB",
+            Opcode::ADDVB(..) => "ADDVB",
+            Opcode::LDVV(..) => "LDVV",
+            Opcode::ORVV(..) => "ORVV",
+            Opcode::ANDVV(..) => "ANDVV",
+            Opcode::XORVV(..) => "XORVV",
+            Opcode::ADDVV(..) => "ADDVV",
+            Opcode::SUB(..) => "SUB",
+            Opcode::SHR(..) => "SHR",
+            Opcode::SUBN(..) => "SUBN",
+            Opcode::SHL(..) => "SHL",
+            Opcode::SNEVV(..) => "SNEVV",
+            Opcode::LDI(..) => "LDI",
+            Opcode::JPV0(..) => "JPV0",
+            Opcode::RND(..) => "RND",
+            Opcode::DRW(..) => "DRW",
+            Opcode::SKP(..) => "SKP",
+            Opcode::SKNP(..) => "SKNP",
+            Opcode::LDVDT(..) => "LDVDT",
+            Opcode::LDVK(..) => "LDVK",
+            Opcode::LDDT(..) => "LDDT",
+            Opcode::LDST(..) => "LDST",
+            Opcode::ADDI(..) => "ADDI",
+            Opcode::LDF(..) => "LDF",
+            Opcode::LDB(..) => "LDB",
+            Opcode::LDIV(..) => "LDIV",
+            Opcode::LDVI(..) => "LDVI",
+            Opcode::BGC => "BGC",
+            Opcode::ADDN(..) => "ADDN",
+            Opcode::COL(..) => "COL",
+            Opcode::OUT(..) => "OUT",
+            Opcode::IN(..) => "IN",
+            Opcode::INP(..) => "INP",
+            Opcode::STOP => "STOP",
+            Opcode::WAITDT => "WAITDT",
+            Opcode::SKIP => "SKIP",
+            Opcode::SGTVV(..) => "SGTVV",
+            Opcode::LDIVV(..) => "LDIVV",
+            Opcode::LDVVI(..) => "LDVVI",
+            Opcode::JPB(..) => "JPB",
+            Opcode::JPF(..) => "JPF",
+            Opcode::SKB(..) => "SKB",
+            Opcode::DLY(..) => "DLY",
+            Opcode::NOP => "NOP",
+        }
+    }
+
     #[allow(clippy::missing_panics_doc)]
     pub fn execute(&self, state: &mut Chip8State) -> WaitStatus {
         match self {
+            Opcode::SYS(addr) => return state.call_machine_code(*addr),
             Opcode::CLS => state.framebuffer.fill(0),
-            Opcode::RET => {
-                let ret_addr = state.pop_stack();
-                state.jump_to_address(ret_addr);
+            Opcode::RET => match state.pop_stack() {
+                Ok(ret_addr) => state.jump_to_address(ret_addr),
+                Err(err) => {
+                    state.stack_error = Some(err);
+                    return WaitStatus::Waiting;
+                },
             },
             Opcode::JP(addr) => {
                 state.jump_to_address(*addr);
@@ -155,7 +298,10 @@ impl Opcode {
             },
             Opcode::CALL(addr) => {
                 let ret_addr = state.pc;
-                state.push_stack(ret_addr);
+                if let Err(err) = state.push_stack(ret_addr) {
+                    state.stack_error = Some(err);
+                    return WaitStatus::Waiting;
+                }
                 state.jump_to_address(*addr);
                 return WaitStatus::Waiting;
             },
@@ -165,9 +311,18 @@ impl Opcode {
             Opcode::LDVB(reg, byte) => state.registers[*reg as usize] = *byte,
             Opcode::ADDVB(reg, byte) => state.registers[*reg as usize] = state.registers[*reg as usize].wrapping_add(*byte),
             Opcode::LDVV(reg1, reg2) => state.registers[*reg1 as usize] = state.registers[*reg2 as usize],
-            Opcode::ORVV(reg1, reg2) => state.registers[*reg1 as usize] |= state.registers[*reg2 as usize],
-            Opcode::ANDVV(reg1, reg2) => state.registers[*reg1 as usize] &= state.registers[*reg2 as usize],
-            Opcode::XORVV(reg1, reg2) => state.registers[*reg1 as usize] ^= state.registers[*reg2 as usize],
+            Opcode::ORVV(reg1, reg2) => {
+                state.registers[*reg1 as usize] |= state.registers[*reg2 as usize];
+                if state.quirks.vf_reset { state.registers[Reg::VF as usize] = 0 }
+            },
+            Opcode::ANDVV(reg1, reg2) => {
+                state.registers[*reg1 as usize] &= state.registers[*reg2 as usize];
+                if state.quirks.vf_reset { state.registers[Reg::VF as usize] = 0 }
+            },
+            Opcode::XORVV(reg1, reg2) => {
+                state.registers[*reg1 as usize] ^= state.registers[*reg2 as usize];
+                if state.quirks.vf_reset { state.registers[Reg::VF as usize] = 0 }
+            },
             Opcode::ADDVV(reg1, reg2) => {
                 let result = state.registers[*reg1 as usize].wrapping_add(state.registers[*reg2 as usize]);
                 state.registers[Reg::VF as usize] = u8::from(result < state.registers[*reg1 as usize]);
@@ -177,29 +332,36 @@ impl Opcode {
                 state.registers[Reg::VF as usize] = u8::from(state.registers[*reg1 as usize] > state.registers[*reg2 as usize]);
                 state.registers[*reg1 as usize] = state.registers[*reg1 as usize].wrapping_sub(state.registers[*reg2 as usize]);
             },
-            Opcode::SHR(reg) => {
-                state.registers[Reg::VF as usize] = state.registers[*reg as usize] & 0x01;
-                state.registers[*reg as usize] >>= 1;
+            Opcode::SHR(reg1, reg2) => {
+                let source = state.registers[if state.quirks.shift_vy { *reg2 } else { *reg1 } as usize];
+                state.shift_result(*reg1, source >> 1, source & 0x01);
             },
             Opcode::SUBN(reg1, reg2) => {
                 state.registers[Reg::VF as usize] = u8::from(state.registers[*reg2 as usize] > state.registers[*reg1 as usize]);
                 state.registers[*reg1 as usize] = state.registers[*reg2 as usize].wrapping_sub(state.registers[*reg1 as usize]);
             },
-            Opcode::SHL(reg) => {
-                state.registers[Reg::VF as usize] = (state.registers[*reg as usize] & 0x80) >> 7;
-                state.registers[*reg as usize] <<= 1;
+            Opcode::SHL(reg1, reg2) => {
+                let source = state.registers[if state.quirks.shift_vy { *reg2 } else { *reg1 } as usize];
+                state.shift_result(*reg1, source << 1, (source & 0x80) >> 7);
             },
             Opcode::SNEVV(reg1, reg2) => if state.registers[*reg1 as usize] != state.registers[*reg2 as usize] { state.pc += u16::from(INSTR_SIZE) },
             Opcode::LDI(val) => state.index = *val,
-            Opcode::JPV0(addr) => state.jump_to_address((addr & 0x0FFF) + u16::from(state.registers[Reg::V0 as usize])),
-            Opcode::RND(reg, mask) => state.registers[*reg as usize] = random::<u8>() & mask,
+            Opcode::JPV0(addr) => {
+                let offset_reg = if state.quirks.jump_vx { Reg::from(((addr & 0x0F00) >> 8) as u8) } else { Reg::V0 };
+                state.jump_to_address((addr & 0x0FFF) + u16::from(state.registers[offset_reg as usize]));
+            },
+            Opcode::RND(reg, mask) => state.registers[*reg as usize] = state.rng.gen::<u8>() & mask,
             Opcode::DRW(x_reg, y_reg, rows) => {
                 let mut overwrite = false;
+                let y = state.registers[*y_reg as usize] % 32;
                 for i in 0..*rows {
+                    if state.quirks.clip_sprites && y + i >= 32 {
+                        break;
+                    }
                     overwrite |= state.write_fb(
                         state.memory[(state.index + u16::from(i)) as usize],
                         state.registers[*x_reg as usize],
-                        state.registers[*y_reg as usize] + i
+                        y + i
                     );
                 }
                 state.registers[Reg::VF as usize] = u8::from(overwrite);
@@ -217,19 +379,82 @@ impl Opcode {
             Opcode::LDDT(reg) => state.dt = state.registers[*reg as usize],
             Opcode::LDST(reg) => state.st = state.registers[*reg as usize],
             Opcode::ADDI(reg) => state.index = state.index.wrapping_add(u16::from(state.registers[*reg as usize])),
-            Opcode::LDF(reg) => state.index = u16::from(state.registers[*reg as usize]) * 5,
+            Opcode::LDF(reg) => state.index = state.layout.font_sprite(state.registers[*reg as usize]),
             Opcode::LDB(reg) => state.store_bcd(BCD::from(state.registers[*reg as usize]), state.index),
             Opcode::LDIV(reg) => {
                 for i in 0..=*reg as u16 {
                     state.store_to_memory(state.registers[i as usize], state.index + i);
                 }
-                state.index += *reg as u16 + 1;
+                if state.quirks.increment_index { state.index += *reg as u16 + 1 }
             },
             Opcode::LDVI(reg) => {
                 for i in 0..=*reg as u16 {
                     state.registers[i as usize] = state.memory[(state.index + i) as usize];
                 }
-                state.index += *reg as u16 + 1;
+                if state.quirks.increment_index { state.index += *reg as u16 + 1 }
+            },
+            Opcode::BGC => {
+                let next = color::BACKGROUNDS.iter().position(|color| *color == state.background).map_or(0, |i| i + 1);
+                state.background = color::BACKGROUNDS[next % color::BACKGROUNDS.len()];
+            },
+            Opcode::ADDN(reg1, reg2) => {
+                let (a, b) = (state.registers[*reg1 as usize], state.registers[*reg2 as usize]);
+                state.registers[*reg1 as usize] = ((a & 0x70) + (b & 0x70)) & 0x70 | ((a & 0x07) + (b & 0x07)) & 0x07;
+            },
+            Opcode::COL(x_reg, y_reg, rows) => {
+                let (x, y) = (usize::from(state.registers[*x_reg as usize]), usize::from(state.registers[*y_reg as usize]));
+                let color = state.registers[(*x_reg as usize + 1) % 16] & 0x07;
+                let columns = (x & 0x0F)..=(x & 0x0F) + (x >> 4);
+                let pixel_rows = if *rows == 0 { 4 * (y & 0x0F)..4 * ((y & 0x0F) + (y >> 4) + 1) } else { y..y + usize::from(*rows) };
+                // areas running off the display are cut off
+                for row in pixel_rows.filter(|row| *row < 32) {
+                    for column in columns.clone().filter(|column| *column < 8) {
+                        state.colors[8 * row + column] = color;
+                    }
+                }
+            },
+            Opcode::OUT(reg) => state.io.output = Some(state.registers[*reg as usize]),
+            Opcode::IN(reg) => if state.io.strobe {
+                state.io.strobe = false;
+                state.registers[*reg as usize] = state.io.input;
+            } else {
+                return WaitStatus::Waiting
+            },
+            Opcode::INP(reg) => state.registers[*reg as usize] = state.io.input,
+            Opcode::STOP => return WaitStatus::Waiting,
+            Opcode::WAITDT => if state.dt > 0 { return WaitStatus::Waiting },
+            Opcode::SKIP => state.pc += u16::from(INSTR_SIZE),
+            Opcode::SGTVV(reg1, reg2) => if state.registers[*reg1 as usize] > state.registers[*reg2 as usize] { state.pc += u16::from(INSTR_SIZE) },
+            Opcode::LDIVV(reg1, reg2) => {
+                for (offset, i) in (*reg1 as u16..=*reg2 as u16).enumerate() {
+                    #[allow(clippy::cast_possible_truncation)]
+                    state.store_to_memory(state.registers[i as usize], state.index + offset as u16);
+                }
+                if state.quirks.increment_index { state.index += (*reg2 as u16 + 1).saturating_sub(*reg1 as u16) }
+            },
+            Opcode::LDVVI(reg1, reg2) => {
+                for (offset, i) in (*reg1 as u16..=*reg2 as u16).enumerate() {
+                    state.registers[i as usize] = state.memory[usize::from(state.index) + offset];
+                }
+                if state.quirks.increment_index { state.index += (*reg2 as u16 + 1).saturating_sub(*reg1 as u16) }
+            },
+            Opcode::JPB(bytes) => {
+                state.jump_to_address(state.pc.wrapping_sub(u16::from(*bytes)));
+                return WaitStatus::Waiting
+            },
+            Opcode::JPF(bytes) => {
+                state.jump_to_address(state.pc.wrapping_add(u16::from(*bytes)));
+                return WaitStatus::Waiting
+            },
+            Opcode::SKB(reg) => state.pc += u16::from(state.registers[*reg as usize]),
+            Opcode::DLY(reg) => if !state.delay_wait {
+                state.dt = state.registers[*reg as usize];
+                state.delay_wait = true;
+                return WaitStatus::Waiting
+            } else if state.dt > 0 {
+                return WaitStatus::Waiting
+            } else {
+                state.delay_wait = false;
             },
             Opcode::NOP => {},
         }
@@ -239,8 +464,9 @@ impl Opcode {
 }
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
-    use crate::sprite::DEFAULT_SPRITES;
+    use crate::{quirks::Quirks, sprite::DEFAULT_SPRITES};
 
     use super::*;
 
@@ -248,6 +474,7 @@ mod tests {
     fn test_opcode_from_u16() {
         assert_eq!(Opcode::from(0x00E0), Opcode::CLS);
         assert_eq!(Opcode::from(0x00EE), Opcode::RET);
+        assert_eq!(Opcode::from(0x0300), Opcode::SYS(0x0300));
         assert_eq!(Opcode::from(0x1FFF), Opcode::JP(0x0FFF));
         assert_eq!(Opcode::from(0x2123), Opcode::CALL(0x0123));
         assert_eq!(Opcode::from(0x35AB), Opcode::SEVB(Reg::V5, 0xAB));
@@ -261,9 +488,9 @@ mod tests {
         assert_eq!(Opcode::from(0x8773), Opcode::XORVV(Reg::V7, Reg::V7));
         assert_eq!(Opcode::from(0x8004), Opcode::ADDVV(Reg::V0, Reg::V0));
         assert_eq!(Opcode::from(0x8FE5), Opcode::SUB(Reg::VF, Reg::VE));
-        assert_eq!(Opcode::from(0x8AB6), Opcode::SHR(Reg::VA));
+        assert_eq!(Opcode::from(0x8AB6), Opcode::SHR(Reg::VA, Reg::VB));
         assert_eq!(Opcode::from(0x8AB7), Opcode::SUBN(Reg::VA, Reg::VB));
-        assert_eq!(Opcode::from(0x844E), Opcode::SHL(Reg::V4));
+        assert_eq!(Opcode::from(0x844E), Opcode::SHL(Reg::V4, Reg::V4));
         assert_eq!(Opcode::from(0x9560), Opcode::SNEVV(Reg::V5, Reg::V6));
         assert_eq!(Opcode::from(0xA380), Opcode::LDI(0x0380));
         assert_eq!(Opcode::from(0xB747), Opcode::JPV0(0x0747));
@@ -282,6 +509,40 @@ mod tests {
         assert_eq!(Opcode::from(0xF765), Opcode::LDVI(Reg::V7))
     }
 
+    #[test]
+    fn test_opcode_decode_variants() {
+        assert_eq!(Opcode::decode(0x02A0, Variant::Chip8), Opcode::SYS(0x02A0));
+        assert_eq!(Opcode::decode(0x02A0, Variant::Chip8X), Opcode::BGC);
+        assert_eq!(Opcode::decode(0x5121, Variant::Chip8X), Opcode::ADDN(Reg::V1, Reg::V2));
+        assert_eq!(Opcode::decode(0xB123, Variant::Chip8X), Opcode::COL(Reg::V1, Reg::V2, 3));
+        assert_eq!(Opcode::decode(0xF3F8, Variant::Chip8X), Opcode::OUT(Reg::V3));
+        assert_eq!(Opcode::decode(0xF3FB, Variant::Chip8X), Opcode::IN(Reg::V3));
+        assert_eq!(Opcode::decode(0xB123, Variant::Chip8E), Opcode::JPV0(0x0123));
+        assert_eq!(Opcode::decode(0x00ED, Variant::Chip8E), Opcode::STOP);
+        assert_eq!(Opcode::decode(0x0151, Variant::Chip8E), Opcode::WAITDT);
+        assert_eq!(Opcode::decode(0x0188, Variant::Chip8E), Opcode::SKIP);
+        assert_eq!(Opcode::decode(0x5121, Variant::Chip8E), Opcode::SGTVV(Reg::V1, Reg::V2));
+        assert_eq!(Opcode::decode(0x5122, Variant::Chip8E), Opcode::LDIVV(Reg::V1, Reg::V2));
+        assert_eq!(Opcode::decode(0x5123, Variant::Chip8E), Opcode::LDVVI(Reg::V1, Reg::V2));
+        assert_eq!(Opcode::decode(0xBB04, Variant::Chip8E), Opcode::JPB(4));
+        assert_eq!(Opcode::decode(0xBF06, Variant::Chip8E), Opcode::JPF(6));
+        assert_eq!(Opcode::decode(0xF403, Variant::Chip8E), Opcode::OUT(Reg::V4));
+        assert_eq!(Opcode::decode(0xF41B, Variant::Chip8E), Opcode::SKB(Reg::V4));
+        assert_eq!(Opcode::decode(0xF44F, Variant::Chip8E), Opcode::DLY(Reg::V4));
+        assert_eq!(Opcode::decode(0xF4E3, Variant::Chip8E), Opcode::IN(Reg::V4));
+        assert_eq!(Opcode::decode(0xF4E7, Variant::Chip8E), Opcode::INP(Reg::V4));
+        assert_eq!(Opcode::decode(0x5120, Variant::Chip8E), Opcode::SEVV(Reg::V1, Reg::V2))
+    }
+
+    #[test]
+    fn test_target_address() {
+        assert_eq!(Opcode::JP(0x0ABC).target_address(), Some(0x0ABC));
+        assert_eq!(Opcode::CALL(0x0123).target_address(), Some(0x0123));
+        assert_eq!(Opcode::LDI(0x0380).target_address(), Some(0x0380));
+        assert_eq!(Opcode::JPV0(0x0747).target_address(), Some(0x0747));
+        assert_eq!(Opcode::LDVB(Reg::V1, 0x23).target_address(), None)
+    }
+
     #[test]
     fn test_op_cls() {
         let mut state = Chip8State::default();
@@ -332,6 +593,16 @@ mod tests {
         assert_eq!(state.pc, 0x0200)
     }
 
+    #[test]
+    fn test_op_ret_underflow() {
+        let mut state = Chip8State::default();
+
+        assert_eq!(Opcode::RET.execute(&mut state), WaitStatus::Waiting);
+
+        assert_eq!(state.stack_error, Some(crate::stack::StackError::Underflow));
+        assert_eq!(state.pc, 0x0200)
+    }
+
     #[test]
     fn test_op_sevb() {
         let mut state = Chip8State::default();
@@ -434,6 +705,18 @@ mod tests {
         assert_eq!(state.registers[Reg::V0 as usize], 0x72)
     }
 
+    #[test]
+    fn test_op_logic_vf_reset() {
+        let mut state = Chip8State::default();
+        state.quirks.vf_reset = true;
+
+        for opcode in [Opcode::ORVV(Reg::V0, Reg::V1), Opcode::ANDVV(Reg::V0, Reg::V1), Opcode::XORVV(Reg::V0, Reg::V1)] {
+            state.registers[Reg::VF as usize] = 0x01;
+            opcode.execute(&mut state);
+            assert_eq!(state.registers[Reg::VF as usize], 0x00);
+        }
+    }
+
     #[test]
     fn test_op_addvv() {
         let mut state = Chip8State::default();
@@ -477,24 +760,62 @@ mod tests {
         let mut state = Chip8State::default();
         
         state.registers[Reg::V0 as usize] = 0x00;
-        Opcode::SHR(Reg::V0).execute(&mut state);
+        Opcode::SHR(Reg::V0, Reg::V1).execute(&mut state);
 
         assert_eq!(state.registers[Reg::V0 as usize], 0x00);
         assert_eq!(state.registers[Reg::VF as usize], 0x00);
 
         state.registers[Reg::VA as usize] = 0x01;
-        Opcode::SHR(Reg::VA).execute(&mut state);
+        Opcode::SHR(Reg::VA, Reg::V1).execute(&mut state);
 
         assert_eq!(state.registers[Reg::VA as usize], 0x00);
         assert_eq!(state.registers[Reg::VF as usize], 0x01);
 
         state.registers[Reg::VE as usize] = 0x40;
-        Opcode::SHR(Reg::VE).execute(&mut state);
+        Opcode::SHR(Reg::VE, Reg::V1).execute(&mut state);
 
         assert_eq!(state.registers[Reg::VE as usize], 0x20);
         assert_eq!(state.registers[Reg::VF as usize], 0x00)
     }
 
+    #[test]
+    fn test_op_shr_shl_shift_vy() {
+        let mut state = Chip8State::default();
+        state.quirks.shift_vy = true;
+
+        state.registers[Reg::V0 as usize] = 0xFF;
+        state.registers[Reg::V1 as usize] = 0x41;
+        Opcode::SHR(Reg::V0, Reg::V1).execute(&mut state);
+
+        assert_eq!(state.registers[Reg::V0 as usize], 0x20);
+        assert_eq!(state.registers[Reg::VF as usize], 0x01);
+
+        state.registers[Reg::V1 as usize] = 0x41;
+        Opcode::SHL(Reg::V0, Reg::V1).execute(&mut state);
+
+        assert_eq!(state.registers[Reg::V0 as usize], 0x82);
+        assert_eq!(state.registers[Reg::VF as usize], 0x00)
+    }
+
+    #[test]
+    fn test_op_shift_vf() {
+        let mut state = Chip8State::default();
+
+        // by default, the flag is overwritten by the result
+        state.registers[Reg::VF as usize] = 0x81;
+        Opcode::SHR(Reg::VF, Reg::VF).execute(&mut state);
+        assert_eq!(state.registers[Reg::VF as usize], 0x40);
+        Opcode::SHL(Reg::VF, Reg::VF).execute(&mut state);
+        assert_eq!(state.registers[Reg::VF as usize], 0x80);
+
+        state.quirks = Quirks::SCHIP;
+        state.registers[Reg::VF as usize] = 0x81;
+        Opcode::SHR(Reg::VF, Reg::VF).execute(&mut state);
+        assert_eq!(state.registers[Reg::VF as usize], 0x01);
+        Opcode::SHL(Reg::VF, Reg::VF).execute(&mut state);
+        assert_eq!(state.registers[Reg::VF as usize], 0x00)
+    }
+
     #[test]
     fn test_op_subn() {
         let mut state = Chip8State::default();
@@ -519,19 +840,19 @@ mod tests {
         let mut state = Chip8State::default();
         
         state.registers[Reg::V0 as usize] = 0x00;
-        Opcode::SHL(Reg::V0).execute(&mut state);
+        Opcode::SHL(Reg::V0, Reg::V1).execute(&mut state);
 
         assert_eq!(state.registers[Reg::V0 as usize], 0x00);
         assert_eq!(state.registers[Reg::VF as usize], 0x00);
 
         state.registers[Reg::VA as usize] = 0x80;
-        Opcode::SHL(Reg::VA).execute(&mut state);
+        Opcode::SHL(Reg::VA, Reg::V1).execute(&mut state);
 
         assert_eq!(state.registers[Reg::VA as usize], 0x00);
         assert_eq!(state.registers[Reg::VF as usize], 0x01);
 
         state.registers[Reg::VE as usize] = 0x7F;
-        Opcode::SHL(Reg::VE).execute(&mut state);
+        Opcode::SHL(Reg::VE, Reg::V1).execute(&mut state);
 
         assert_eq!(state.registers[Reg::VE as usize], 0xFE);
         assert_eq!(state.registers[Reg::VF as usize], 0x00)
@@ -568,6 +889,17 @@ mod tests {
         assert_eq!(state.pc, 0x0B5C)
     }
 
+    #[test]
+    fn test_op_jpv0_jump_vx() {
+        let mut state = Chip8State::default();
+        state.quirks.jump_vx = true;
+
+        state.registers[Reg::V0 as usize] = 0xA0;
+        state.registers[Reg::VA as usize] = 0x04;
+        Opcode::JPV0(0x0ABC).execute(&mut state);
+        assert_eq!(state.pc, 0x0AC0)
+    }
+
     #[test]
     fn test_op_rnd() {
         let mut state = Chip8State::default();
@@ -585,14 +917,14 @@ mod tests {
 
         Opcode::DRW(Reg::V0, Reg::V1, 0x05).execute(&mut state);
         for i in 0..5 {
-            assert_eq!(state.framebuffer[(8 * i) + 0], DEFAULT_SPRITES[0].rows[i]);
+            assert_eq!(state.framebuffer[8 * i], DEFAULT_SPRITES[0].rows[i]);
         }
         assert_eq!(state.registers[Reg::VF as usize], 0x00);
 
         Opcode::LDI(0x005).execute(&mut state);
         Opcode::DRW(Reg::V0, Reg::V1, 0x05).execute(&mut state);
         for i in 0..5 {
-            assert_eq!(state.framebuffer[(8 * i) + 0], DEFAULT_SPRITES[0].rows[i] ^ DEFAULT_SPRITES[1].rows[i]);
+            assert_eq!(state.framebuffer[8 * i], DEFAULT_SPRITES[0].rows[i] ^ DEFAULT_SPRITES[1].rows[i]);
         }
         assert_eq!(state.registers[Reg::VF as usize], 0x01);
 
@@ -606,6 +938,23 @@ mod tests {
         assert_eq!(state.registers[Reg::VF as usize], 0x00);
     }
 
+    #[test]
+    fn test_op_drw_clip_sprites() {
+        let mut state = Chip8State::default();
+
+        state.registers[Reg::V1 as usize] = 30;
+        Opcode::DRW(Reg::V0, Reg::V1, 0x05).execute(&mut state);
+        assert_eq!(state.framebuffer[0], DEFAULT_SPRITES[0].rows[2]);
+
+        let mut state = Chip8State::default();
+        state.quirks.clip_sprites = true;
+
+        state.registers[Reg::V1 as usize] = 30;
+        Opcode::DRW(Reg::V0, Reg::V1, 0x05).execute(&mut state);
+        assert_eq!(state.framebuffer[8 * 31], DEFAULT_SPRITES[0].rows[1]);
+        assert_eq!(state.framebuffer[0], 0x00)
+    }
+
     #[test]
     fn test_op_skp() {
         let mut state = Chip8State::default();
@@ -753,6 +1102,18 @@ mod tests {
         assert_eq!(state.index, 0x0404)
     }
 
+    #[test]
+    fn test_op_ldiv_ldvi_no_increment() {
+        let mut state = Chip8State::default();
+        state.quirks.increment_index = false;
+
+        state.index = 0x0400;
+        Opcode::LDIV(Reg::V3).execute(&mut state);
+        Opcode::LDVI(Reg::V3).execute(&mut state);
+
+        assert_eq!(state.index, 0x0400)
+    }
+
     #[test]
     fn test_op_ldvi() {
         let mut state = Chip8State::default();
@@ -771,4 +1132,141 @@ mod tests {
         assert_eq!(state.registers[Reg::V3 as usize], 0x78);
         assert_eq!(state.index, 0x0404)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_op_bgc() {
+        let mut state = Chip8State::default();
+        assert_eq!(state.background, color::BLUE);
+
+        Opcode::BGC.execute(&mut state);
+        assert_eq!(state.background, color::BLACK);
+        for _ in 0..3 { Opcode::BGC.execute(&mut state); }
+        assert_eq!(state.background, color::BLUE)
+    }
+
+    #[test]
+    fn test_op_addn() {
+        let mut state = Chip8State::default();
+
+        state.registers[Reg::V1 as usize] = 0x65;
+        state.registers[Reg::V2 as usize] = 0x34;
+
+        Opcode::ADDN(Reg::V1, Reg::V2).execute(&mut state);
+
+        assert_eq!(state.registers[Reg::V1 as usize], 0x11)
+    }
+
+    #[test]
+    fn test_op_col() {
+        let mut state = Chip8State::default();
+
+        // columns 6 and 7 (and 8, off the display), zone rows 1 and 2
+        state.registers[Reg::V1 as usize] = 0x26;
+        state.registers[Reg::V3 as usize] = 0x11;
+        state.registers[Reg::V2 as usize] = color::GREEN;
+
+        Opcode::COL(Reg::V1, Reg::V3, 0).execute(&mut state);
+
+        assert_eq!(state.colors[8 * 3 + 6], color::DEFAULT_FOREGROUND);
+        assert_eq!(state.colors[8 * 4 + 6], color::GREEN);
+        assert_eq!(state.colors[8 * 11 + 7], color::GREEN);
+        assert_eq!(state.colors[8 * 12 + 7], color::DEFAULT_FOREGROUND);
+        assert_eq!(state.colors[8 * 4 + 5], color::DEFAULT_FOREGROUND);
+
+        // pixel rows 30 and 31 of column 0, the rest cut off
+        state.registers[Reg::V1 as usize] = 0x00;
+        state.registers[Reg::V3 as usize] = 30;
+        state.registers[Reg::V2 as usize] = color::WHITE;
+
+        Opcode::COL(Reg::V1, Reg::V3, 5).execute(&mut state);
+
+        assert_eq!(state.colors[8 * 29], color::DEFAULT_FOREGROUND);
+        assert_eq!(state.colors[8 * 30], color::WHITE);
+        assert_eq!(state.colors[8 * 31], color::WHITE)
+    }
+
+    #[test]
+    fn test_op_out_in() {
+        let mut state = Chip8State::default();
+
+        state.registers[Reg::V5 as usize] = 0x42;
+        assert_eq!(Opcode::OUT(Reg::V5).execute(&mut state), WaitStatus::Running);
+        assert_eq!(state.io.output, Some(0x42));
+
+        assert_eq!(Opcode::IN(Reg::V6).execute(&mut state), WaitStatus::Waiting);
+        state.io.send(0x99);
+        assert_eq!(Opcode::INP(Reg::V7).execute(&mut state), WaitStatus::Running);
+        assert_eq!(state.registers[Reg::V7 as usize], 0x99);
+        assert_eq!(Opcode::IN(Reg::V6).execute(&mut state), WaitStatus::Running);
+        assert_eq!(state.registers[Reg::V6 as usize], 0x99);
+        assert!(!state.io.strobe)
+    }
+
+    #[test]
+    fn test_op_waitdt_dly() {
+        let mut state = Chip8State::default();
+
+        state.dt = 1;
+        assert_eq!(Opcode::WAITDT.execute(&mut state), WaitStatus::Waiting);
+        state.dt = 0;
+        assert_eq!(Opcode::WAITDT.execute(&mut state), WaitStatus::Running);
+
+        state.registers[Reg::V2 as usize] = 2;
+        assert_eq!(Opcode::DLY(Reg::V2).execute(&mut state), WaitStatus::Waiting);
+        assert_eq!(state.dt, 2);
+        assert_eq!(Opcode::DLY(Reg::V2).execute(&mut state), WaitStatus::Waiting);
+        state.dt = 0;
+        assert_eq!(Opcode::DLY(Reg::V2).execute(&mut state), WaitStatus::Running);
+        assert!(!state.delay_wait)
+    }
+
+    #[test]
+    fn test_op_sgtvv() {
+        let mut state = Chip8State::default();
+
+        state.pc = 0x0200;
+        state.registers[Reg::V1 as usize] = 5;
+        state.registers[Reg::V2 as usize] = 5;
+        Opcode::SGTVV(Reg::V1, Reg::V2).execute(&mut state);
+        assert_eq!(state.pc, 0x0200);
+
+        state.registers[Reg::V1 as usize] = 6;
+        Opcode::SGTVV(Reg::V1, Reg::V2).execute(&mut state);
+        assert_eq!(state.pc, 0x0202)
+    }
+
+    #[test]
+    fn test_op_ldivv_ldvvi() {
+        let mut state = Chip8State::default();
+
+        state.index = 0x0400;
+        state.registers[Reg::V2 as usize] = 0x12;
+        state.registers[Reg::V3 as usize] = 0x34;
+
+        Opcode::LDIVV(Reg::V2, Reg::V3).execute(&mut state);
+
+        assert_eq!(state.memory[0x0400..0x0403], [0x12, 0x34, 0x00]);
+        assert_eq!(state.index, 0x0402);
+
+        state.index = 0x0400;
+        Opcode::LDVVI(Reg::V5, Reg::V6).execute(&mut state);
+
+        assert_eq!(state.registers[Reg::V5 as usize..=Reg::V6 as usize], [0x12, 0x34]);
+        assert_eq!(state.index, 0x0402)
+    }
+
+    #[test]
+    fn test_op_jpb_jpf_skb() {
+        let mut state = Chip8State::default();
+
+        state.pc = 0x0210;
+        assert_eq!(Opcode::JPB(0x10).execute(&mut state), WaitStatus::Waiting);
+        assert_eq!(state.pc, 0x0200);
+        assert_eq!(Opcode::JPF(0x20).execute(&mut state), WaitStatus::Waiting);
+        assert_eq!(state.pc, 0x0220);
+
+        state.registers[Reg::V0 as usize] = 4;
+        Opcode::SKB(Reg::V0).execute(&mut state);
+        assert_eq!(state.pc, 0x0224)
+    }
+}
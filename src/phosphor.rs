@@ -0,0 +1,192 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use crate::Chip8State;
+
+/// Display width in pixels
+pub const WIDTH: usize = 64;
+/// Display height in pixels
+pub const HEIGHT: usize = 32;
+/// Intensity of a fully lit pixel
+pub const FULL: u8 = 255;
+
+/// How pixels linger after they go dark
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhosphorMode {
+    /// A pixel fades once it goes dark, losing this much of its intensity each frame
+    Decay(u8),
+    /// A pixel stays fully lit while it was lit in any of this many last frames
+    MaxOfFrames(u8),
+}
+
+/// A `PhosphorMode` written as `decay:N` or `max:N` could not be read
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsePhosphorModeError(String);
+
+impl fmt::Display for ParsePhosphorModeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not a phosphor mode, expected decay:N (intensity lost per frame, 1 to 255) or max:N (frames, 1 to 255)", self.0)
+    }
+}
+
+impl Error for ParsePhosphorModeError {}
+
+impl FromStr for PhosphorMode {
+    type Err = ParsePhosphorModeError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let error = || ParsePhosphorModeError(String::from(text));
+        let (kind, amount) = text.split_once(':').ok_or_else(error)?;
+        let amount = amount.parse::<u8>().ok().filter(|amount| *amount > 0).ok_or_else(error)?;
+
+        match kind {
+            "decay" => Ok(Self::Decay(amount)),
+            "max" => Ok(Self::MaxOfFrames(amount)),
+            _ => Err(error()),
+        }
+    }
+}
+
+impl fmt::Display for PhosphorMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decay(amount) => write!(f, "decay:{amount}"),
+            Self::MaxOfFrames(frames) => write!(f, "max:{frames}"),
+        }
+    }
+}
+
+/// Smooths out the flicker of sprites erased and redrawn with XOR by keeping a per-pixel intensity, like the glow of a CRT's phosphor.
+/// Every pixel drawn during a frame counts as lit in it, even if it was erased again before the frame ended.
+/// Enabled by setting `Chip8State::phosphor`; cleared on reset.
+#[derive(Clone, Debug)]
+pub struct Phosphor {
+    mode: PhosphorMode,
+    /// Per pixel, the intensity it has faded to, or the frames left until it goes dark
+    levels: Vec<u8>,
+    /// Pixels lit at any point of the current frame, packed like the framebuffer
+    lit_this_frame: [u8; 256],
+}
+
+impl Phosphor {
+    #[must_use]
+    pub fn new(mode: PhosphorMode) -> Self {
+        Self { mode, levels: vec![0; WIDTH * HEIGHT], lit_this_frame: [0; 256] }
+    }
+
+    #[must_use]
+    pub fn mode(&self) -> PhosphorMode {
+        self.mode
+    }
+
+    /// Notes the pixels lit in the framebuffer after a draw.
+    pub fn record(&mut self, framebuffer: &[u8; 256]) {
+        for (lit, pixels) in self.lit_this_frame.iter_mut().zip(framebuffer) {
+            *lit |= pixels;
+        }
+    }
+
+    /// Ages every pixel by a frame, given the framebuffer as the frame ends.
+    pub fn end_frame(&mut self, framebuffer: &[u8; 256]) {
+        self.record(framebuffer);
+        for (i, level) in self.levels.iter_mut().enumerate() {
+            let lit = is_lit(&self.lit_this_frame, i);
+            *level = match (self.mode, lit) {
+                (PhosphorMode::Decay(_), true) => FULL,
+                (PhosphorMode::Decay(amount), false) => level.saturating_sub(amount),
+                (PhosphorMode::MaxOfFrames(frames), true) => frames,
+                (PhosphorMode::MaxOfFrames(_), false) => level.saturating_sub(1),
+            };
+        }
+        self.lit_this_frame = *framebuffer;
+    }
+
+    /// How bright the pixel at `x`, `y` shows, from 0 for dark to `FULL`, given the framebuffer now.
+    #[must_use]
+    pub fn intensity(&self, framebuffer: &[u8; 256], x: usize, y: usize) -> u8 {
+        let i = (y % HEIGHT) * WIDTH + x % WIDTH;
+        if is_lit(framebuffer, i) || is_lit(&self.lit_this_frame, i) {
+            return FULL;
+        }
+
+        match self.mode {
+            PhosphorMode::Decay(_) => self.levels[i],
+            // frames counted down from the last frame it was lit in, which itself counts as one
+            PhosphorMode::MaxOfFrames(_) => if self.levels[i] > 1 { FULL } else { 0 },
+        }
+    }
+
+    /// The intensity of every pixel, row by row.
+    #[must_use]
+    pub fn intensities(&self, framebuffer: &[u8; 256]) -> Vec<u8> {
+        (0..WIDTH * HEIGHT).map(|i| self.intensity(framebuffer, i % WIDTH, i / WIDTH)).collect()
+    }
+
+    /// Forgets every lingering pixel.
+    pub fn clear(&mut self) {
+        self.levels.fill(0);
+        self.lit_this_frame = [0; 256];
+    }
+}
+
+impl Chip8State {
+    /// How bright the pixel at `x`, `y` shows, from 0 to `FULL`: through `phosphor` while it is set, and otherwise just on or off.
+    #[must_use]
+    pub fn pixel_intensity(&self, x: usize, y: usize) -> u8 {
+        match &self.phosphor {
+            Some(phosphor) => phosphor.intensity(&self.framebuffer, x, y),
+            None if is_lit(&self.framebuffer, (y % HEIGHT) * WIDTH + x % WIDTH) => FULL,
+            None => 0,
+        }
+    }
+}
+
+fn is_lit(framebuffer: &[u8; 256], i: usize) -> bool {
+    framebuffer[i / 8] & (0x80 >> (i % 8)) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decay() {
+        let mut phosphor = Phosphor::new(PhosphorMode::Decay(100));
+        let mut framebuffer = [0; 256];
+        framebuffer[0] = 0x80;
+        phosphor.end_frame(&framebuffer);
+        // erased in the next frame, which still started with it lit
+        framebuffer[0] = 0;
+        phosphor.end_frame(&framebuffer);
+
+        assert_eq!(phosphor.intensity(&framebuffer, 0, 0), FULL);
+        phosphor.end_frame(&framebuffer);
+        assert_eq!(phosphor.intensity(&framebuffer, 0, 0), 155);
+        phosphor.end_frame(&framebuffer);
+        phosphor.end_frame(&framebuffer);
+        assert_eq!(phosphor.intensity(&framebuffer, 0, 0), 0);
+        assert_eq!(phosphor.intensity(&framebuffer, 1, 0), 0)
+    }
+
+    #[test]
+    fn test_max_of_frames() {
+        let mut phosphor = Phosphor::new(PhosphorMode::MaxOfFrames(2));
+        let mut framebuffer = [0; 256];
+        // drawn and erased within one frame, which still counts
+        framebuffer[8] = 0x01;
+        phosphor.record(&framebuffer);
+        framebuffer[8] = 0;
+        phosphor.end_frame(&framebuffer);
+
+        assert_eq!(phosphor.intensities(&framebuffer)[64 + 7], FULL);
+        phosphor.end_frame(&framebuffer);
+        assert_eq!(phosphor.intensity(&framebuffer, 7, 1), 0);
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!("decay:32".parse(), Ok(PhosphorMode::Decay(32)));
+        assert_eq!("max:3".parse::<PhosphorMode>().map(|mode| mode.to_string()), Ok(String::from("max:3")));
+        assert!("max:0".parse::<PhosphorMode>().is_err());
+        assert!("glow".parse::<PhosphorMode>().is_err())
+    }
+}
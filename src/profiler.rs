@@ -0,0 +1,120 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::opcode::Opcode;
+
+/// Counts instructions executed per address and per opcode, and how often each backward jump is taken.
+/// Enabled by setting `Chip8State::profiler`.
+#[derive(Clone, Debug)]
+pub struct Profiler {
+    address_hits: Vec<u64>,
+    opcode_hits: BTreeMap<&'static str, u64>,
+    /// Times each backward jump was taken, keyed by (jump address, target address)
+    back_edges: HashMap<(u16, u16), u64>,
+    total: u64,
+}
+
+/// A loop found through a backward jump: the code from `start` through the jump at `end`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HotLoop {
+    pub start: u16,
+    pub end: u16,
+    /// Times the backward jump was taken
+    pub iterations: u64,
+    /// Instructions executed within `start..=end`, in or out of the loop
+    pub instructions: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self { address_hits: vec![0; 4096], opcode_hits: BTreeMap::new(), back_edges: HashMap::new(), total: 0 }
+    }
+}
+
+impl Profiler {
+    /// Counts `opcode` being executed at `address`.
+    pub fn record(&mut self, address: u16, opcode: &Opcode) {
+        self.address_hits[usize::from(address & 0x0FFF)] += 1;
+        *self.opcode_hits.entry(opcode.name()).or_default() += 1;
+        self.total += 1;
+    }
+
+    /// Counts a jump taken from `from` to `to`; only backward jumps are kept, as they form loops.
+    pub fn record_jump(&mut self, from: u16, to: u16) {
+        if to <= from {
+            *self.back_edges.entry((from, to)).or_default() += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Instructions executed in total.
+    #[must_use]
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    #[must_use]
+    pub fn hits(&self, address: u16) -> u64 {
+        self.address_hits[usize::from(address & 0x0FFF)]
+    }
+
+    /// The most executions of any one address.
+    #[must_use]
+    pub fn max_hits(&self) -> u64 {
+        self.address_hits.iter().copied().max().unwrap_or_default()
+    }
+
+    /// Addresses that were executed, most executed first.
+    #[must_use]
+    pub fn hot_spots(&self) -> Vec<(u16, u64)> {
+        let mut spots: Vec<_> = (0..0x1000).map(|addr| (addr, self.hits(addr))).filter(|(_, hits)| *hits > 0).collect();
+        spots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        spots
+    }
+
+    /// Executions per opcode name, most executed first.
+    #[must_use]
+    pub fn opcode_histogram(&self) -> Vec<(&'static str, u64)> {
+        let mut histogram: Vec<_> = self.opcode_hits.iter().map(|(name, hits)| (*name, *hits)).collect();
+        histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        histogram
+    }
+
+    /// Loops closed by backward jumps, those executing the most instructions first.
+    #[must_use]
+    pub fn hot_loops(&self) -> Vec<HotLoop> {
+        let mut loops: Vec<_> = self.back_edges.iter().map(|((from, to), iterations)| HotLoop {
+            start: *to,
+            end: *from,
+            iterations: *iterations,
+            instructions: (*to..=*from).map(|addr| self.hits(addr)).sum(),
+        }).collect();
+        loops.sort_by(|a, b| b.instructions.cmp(&a.instructions).then(a.start.cmp(&b.start)));
+        loops
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiler() {
+        let mut profiler = Profiler::default();
+        for _ in 0..3 {
+            profiler.record(0x200, &Opcode::ADDVB(crate::Reg::V0, 1));
+            profiler.record(0x202, &Opcode::JP(0x200));
+            profiler.record_jump(0x202, 0x200);
+        }
+        profiler.record(0x204, &Opcode::JP(0x300));
+        profiler.record_jump(0x204, 0x300);
+
+        assert_eq!(profiler.total(), 7);
+        assert_eq!(profiler.max_hits(), 3);
+        assert_eq!(profiler.hot_spots(), vec![(0x200, 3), (0x202, 3), (0x204, 1)]);
+        assert_eq!(profiler.opcode_histogram(), vec![("JP", 4), ("ADDVB", 3)]);
+        assert_eq!(profiler.hot_loops(), vec![HotLoop { start: 0x200, end: 0x202, iterations: 3, instructions: 6 }])
+    }
+}
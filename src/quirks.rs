@@ -0,0 +1,83 @@
+/// Behaviours that differ between CHIP-8 interpreters.
+/// The default is no one interpreter's: shifts work on Vx in place and set VF before the result, and `FX55`/`FX65`
+/// advance I.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE` shift Vy into Vx instead of shifting Vx in place
+    pub shift_vy: bool,
+    /// `FX55`/`FX65` leave I pointing past the last register transferred
+    pub increment_index: bool,
+    /// `8XY1`/`8XY2`/`8XY3` reset VF to 0
+    pub vf_reset: bool,
+    /// `BNNN` jumps to NNN + VX (X being the high nibble of NNN) instead of NNN + V0
+    pub jump_vx: bool,
+    /// Sprites drawn past the bottom of the screen are clipped instead of wrapping to the top
+    pub clip_sprites: bool,
+    /// `8XY6`/`8XYE` set VF before the result instead of after it, so `8FF6` and `8FFE` leave the shifted value in VF
+    /// rather than the bit shifted out
+    pub shift_vf_first: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self { shift_vy: false, increment_index: true, vf_reset: false, jump_vx: false, clip_sprites: false, shift_vf_first: true }
+    }
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter
+    pub const VIP: Self = Self { shift_vy: true, increment_index: true, vf_reset: true, jump_vx: false, clip_sprites: true, shift_vf_first: false };
+    /// SUPER-CHIP 1.1 on the HP 48
+    pub const SCHIP: Self = Self { shift_vy: false, increment_index: false, vf_reset: false, jump_vx: true, clip_sprites: true, shift_vf_first: false };
+    /// Octo's XO-CHIP
+    pub const XOCHIP: Self = Self { shift_vy: true, increment_index: true, vf_reset: false, jump_vx: false, clip_sprites: false, shift_vf_first: false };
+
+    pub const PRESET_NAMES: [&'static str; 4] = ["default", "vip", "schip", "xochip"];
+    /// The flags in the order `flags` lists them, as movies name them
+    pub const FLAG_NAMES: [&'static str; 6] = ["shift_vy", "increment_index", "vf_reset", "jump_vx", "clip_sprites", "shift_vf_first"];
+
+    /// Looks up a preset by one of the names in `PRESET_NAMES`.
+    #[must_use]
+    pub fn preset(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "default" => Some(Self::default()),
+            "vip" | "chip8" | "chip-8" => Some(Self::VIP),
+            "schip" | "superchip" => Some(Self::SCHIP),
+            "xochip" | "xo-chip" => Some(Self::XOCHIP),
+            _ => None,
+        }
+    }
+
+    /// Every flag, in the order snapshots, movies and netplay store them.
+    #[must_use]
+    pub fn flags(&self) -> [bool; 6] {
+        [self.shift_vy, self.increment_index, self.vf_reset, self.jump_vx, self.clip_sprites, self.shift_vf_first]
+    }
+
+    #[must_use]
+    pub fn from_flags(flags: [bool; 6]) -> Self {
+        let [shift_vy, increment_index, vf_reset, jump_vx, clip_sprites, shift_vf_first] = flags;
+        Self { shift_vy, increment_index, vf_reset, jump_vx, clip_sprites, shift_vf_first }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset() {
+        assert_eq!(Quirks::preset("default"), Some(Quirks::default()));
+        assert_eq!(Quirks::preset("VIP"), Some(Quirks::VIP));
+        assert_eq!(Quirks::preset("superchip"), Some(Quirks::SCHIP));
+        assert_eq!(Quirks::preset("xo-chip"), Some(Quirks::XOCHIP));
+        assert_eq!(Quirks::preset("megachip"), None)
+    }
+
+    #[test]
+    fn test_flags() {
+        for quirks in [Quirks::default(), Quirks::VIP, Quirks::SCHIP, Quirks::XOCHIP] {
+            assert_eq!(Quirks::from_flags(quirks.flags()), quirks);
+        }
+    }
+}
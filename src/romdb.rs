@@ -0,0 +1,273 @@
+use std::{collections::{BTreeMap, HashMap}, fs, io, path::Path};
+
+use serde::Deserialize;
+
+use crate::{quirks::Quirks, variant::Variant};
+
+const EMBEDDED_PLATFORMS: &str = include_str!("romdb/platforms.json");
+const EMBEDDED_PROGRAMS: &str = include_str!("romdb/programs.json");
+
+/// ROM metadata keyed by the SHA-1 of the ROM bytes, in the layout of the community CHIP-8 database
+/// (<https://github.com/chip-8/chip-8-database>): a `platforms.json` and a `programs.json` file.
+/// Fields this emulator has no use for are ignored, so the upstream files load as they are.
+#[derive(Clone, Debug, Default)]
+pub struct RomDb {
+    platforms: Vec<Platform>,
+    programs: Vec<Program>,
+    /// Lowercase SHA-1 to index into `programs`
+    by_hash: HashMap<String, usize>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Platform {
+    pub id: String,
+    pub name: String,
+    /// Instructions per 60 Hz frame
+    pub default_tickrate: Option<u32>,
+    #[serde(default)]
+    pub quirks: DbQuirks,
+}
+
+/// Quirk flags as the database names them. Unset flags keep the value they are merged onto.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DbQuirks {
+    /// Shifts operate on VX in place
+    pub shift: Option<bool>,
+    /// `FX55`/`FX65` advance I by X; treated like the usual X + 1, so it only ever turns I advancing on
+    pub memory_increment_by_x: Option<bool>,
+    /// `FX55`/`FX65` leave I unchanged
+    pub memory_leave_i_unchanged: Option<bool>,
+    /// Sprites wrap around the screen edges
+    pub wrap: Option<bool>,
+    /// `BXNN` jumps to XNN + VX
+    pub jump: Option<bool>,
+    /// Drawing waits for the display interrupt; not emulated
+    pub vblank: Option<bool>,
+    /// The logic opcodes reset VF
+    pub logic: Option<bool>,
+}
+
+impl DbQuirks {
+    fn apply(&self, quirks: &mut Quirks) {
+        if let Some(shift) = self.shift {
+            quirks.shift_vy = !shift;
+        }
+        if self.memory_increment_by_x == Some(true) {
+            quirks.increment_index = true;
+        }
+        if let Some(leave) = self.memory_leave_i_unchanged {
+            quirks.increment_index = !leave;
+        }
+        if let Some(wrap) = self.wrap {
+            quirks.clip_sprites = !wrap;
+        }
+        if let Some(jump) = self.jump {
+            quirks.jump_vx = jump;
+        }
+        if let Some(logic) = self.logic {
+            quirks.vf_reset = logic;
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Program {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(default)]
+    pub roms: HashMap<String, RomInfo>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RomInfo {
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Platforms the ROM runs on, preferred first
+    #[serde(default)]
+    pub platforms: Vec<String>,
+    /// Quirk flags that differ from the platform defaults when running this ROM
+    #[serde(default)]
+    pub quirky_platforms: HashMap<String, DbQuirks>,
+    /// Instructions per 60 Hz frame, overriding the platform default
+    pub tickrate: Option<u32>,
+    /// What the ROM uses each CHIP-8 key for, e.g. `"up": 5`
+    #[serde(default)]
+    pub keys: BTreeMap<String, u8>,
+}
+
+/// Settings picked for a ROM found in the database.
+#[derive(Clone, Debug)]
+pub struct RomMatch<'a> {
+    pub program: &'a Program,
+    pub rom: &'a RomInfo,
+    /// The ROM's preferred platform, if the database knows it
+    pub platform: Option<&'a Platform>,
+    pub quirks: Quirks,
+    /// The instruction set of the preferred platform
+    pub variant: Variant,
+    /// Instructions per 60 Hz frame
+    pub tickrate: Option<u32>,
+}
+
+impl RomMatch<'_> {
+    /// `title by author, author`, or just the title.
+    #[must_use]
+    pub fn describe(&self) -> String {
+        if self.program.authors.is_empty() {
+            self.program.title.clone()
+        } else {
+            format!("{} by {}", self.program.title, self.program.authors.join(", "))
+        }
+    }
+}
+
+impl RomDb {
+    /// The database compiled into the library.
+    #[must_use]
+    pub fn embedded() -> Self {
+        Self::parse(EMBEDDED_PLATFORMS, EMBEDDED_PROGRAMS).expect("embedded ROM database is valid")
+    }
+
+    /// # Errors
+    ///
+    /// Fails if either document does not match the database layout.
+    pub fn parse(platforms: &str, programs: &str) -> serde_json::Result<Self> {
+        let platforms: Vec<Platform> = serde_json::from_str(platforms)?;
+        let programs: Vec<Program> = serde_json::from_str(programs)?;
+
+        let by_hash = programs.iter().enumerate()
+            .flat_map(|(i, program)| program.roms.keys().map(move |hash| (hash.to_ascii_lowercase(), i)))
+            .collect();
+
+        Ok(Self { platforms, programs, by_hash })
+    }
+
+    /// Loads `platforms.json` and `programs.json` from a copy of the database's `database` directory.
+    ///
+    /// # Errors
+    ///
+    /// Fails if either file cannot be read or parsed.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let platforms = fs::read_to_string(dir.as_ref().join("platforms.json"))?;
+        let programs = fs::read_to_string(dir.as_ref().join("programs.json"))?;
+        Self::parse(&platforms, &programs).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    #[must_use]
+    pub fn platform(&self, id: &str) -> Option<&Platform> {
+        self.platforms.iter().find(|platform| platform.id == id)
+    }
+
+    #[must_use]
+    pub fn lookup(&self, rom: &[u8]) -> Option<RomMatch<'_>> {
+        self.lookup_hash(&sha1_hex(rom))
+    }
+
+    #[must_use]
+    pub fn lookup_hash(&self, sha1: &str) -> Option<RomMatch<'_>> {
+        let sha1 = sha1.to_ascii_lowercase();
+        let program = &self.programs[*self.by_hash.get(&sha1)?];
+        let rom = program.roms.iter().find(|(hash, _)| hash.eq_ignore_ascii_case(&sha1))?.1;
+
+        let platform_id = rom.platforms.first();
+        let platform = platform_id.and_then(|id| self.platform(id));
+
+        // the platforms are real interpreters, which all set VF after the result of a shift
+        let mut quirks = Quirks { shift_vf_first: false, ..Quirks::default() };
+        if let Some(platform) = platform {
+            platform.quirks.apply(&mut quirks);
+        }
+        if let Some(overrides) = platform_id.and_then(|id| rom.quirky_platforms.get(id)) {
+            overrides.apply(&mut quirks);
+        }
+
+        Some(RomMatch {
+            program,
+            rom,
+            platform,
+            quirks,
+            variant: platform_id.and_then(|id| Variant::from_platform(id)).unwrap_or_default(),
+            tickrate: rom.tickrate.or(platform.and_then(|platform| platform.default_tickrate)),
+        })
+    }
+}
+
+/// Lowercase hex SHA-1 digest, as the database keys ROMs.
+#[must_use]
+pub fn sha1_hex(bytes: &[u8]) -> String {
+    sha1_smol::Sha1::from(bytes).digest().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAMS: &str = r#"[{
+        "title": "Test Game",
+        "authors": ["A. Author", "B. Author"],
+        "roms": {
+            "86f7e437faa5a7fce15d1ddcb9eaeaea377667b8": {
+                "file": "a.ch8",
+                "platforms": ["originalChip8", "modernChip8"],
+                "quirkyPlatforms": { "originalChip8": { "wrap": true } },
+                "keys": { "up": 5, "down": 8 },
+                "images": ["ignored.png"]
+            }
+        }
+    }]"#;
+
+    /// Maze by David Winter
+    const MAZE: [u8; 34] = [
+        0xA2, 0x1E, 0xC2, 0x01, 0x32, 0x01, 0xA2, 0x1A, 0xD0, 0x14, 0x70, 0x04, 0x30, 0x40, 0x12, 0x00, 0x60, 0x00,
+        0x71, 0x04, 0x31, 0x20, 0x12, 0x00, 0x12, 0x18, 0x80, 0x40, 0x20, 0x10, 0x20, 0x40, 0x80, 0x10,
+    ];
+
+    #[test]
+    fn test_embedded() {
+        let db = RomDb::embedded();
+
+        assert_eq!(db.platform("superchip").and_then(|platform| platform.default_tickrate), Some(30));
+        assert!(db.lookup(&[0x12, 0x00]).is_none());
+
+        let found = db.lookup(&MAZE).unwrap();
+        assert_eq!(found.describe(), "Maze by David Winter");
+        assert_eq!(found.quirks, Quirks::VIP);
+        assert_eq!(found.tickrate, Some(15))
+    }
+
+    #[test]
+    fn test_lookup() {
+        let db = RomDb::parse(EMBEDDED_PLATFORMS, PROGRAMS).unwrap();
+
+        // SHA-1 of "a"
+        let found = db.lookup(b"a").unwrap();
+        assert_eq!(found.describe(), "Test Game by A. Author, B. Author");
+        assert_eq!(found.platform.map(|platform| platform.id.as_str()), Some("originalChip8"));
+        assert_eq!(found.quirks, Quirks { clip_sprites: false, ..Quirks::VIP });
+        assert_eq!(found.tickrate, Some(15));
+        assert_eq!(found.rom.keys.get("up"), Some(&5));
+
+        assert!(db.lookup_hash("86F7E437FAA5A7FCE15D1DDCB9EAEAEA377667B8").is_some());
+        assert!(db.lookup(b"b").is_none())
+    }
+
+    #[test]
+    fn test_memory_quirks() {
+        let mut quirks = Quirks::SCHIP;
+        DbQuirks { memory_increment_by_x: Some(false), ..DbQuirks::default() }.apply(&mut quirks);
+        assert!(!quirks.increment_index);
+        DbQuirks { memory_increment_by_x: Some(true), ..DbQuirks::default() }.apply(&mut quirks);
+        assert!(quirks.increment_index);
+
+        let db = RomDb::embedded();
+        let mut quirks = Quirks::SCHIP;
+        db.platform("superchip1").unwrap().quirks.apply(&mut quirks);
+        assert!(quirks.increment_index)
+    }
+}
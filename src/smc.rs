@@ -0,0 +1,120 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::INSTR_SIZE;
+
+/// Events a `SmcDetector` keeps; older ones are dropped, but still counted
+pub const EVENT_HISTORY: usize = 256;
+
+/// A write to memory holding code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SmcEvent {
+    /// Address of the instruction that wrote
+    pub pc: u16,
+    /// Address written to
+    pub address: u16,
+    pub old_value: u8,
+    pub new_value: u8,
+}
+
+/// Detects self-modifying code: writes to addresses that were already executed, or that hold the next instruction.
+/// Enabled by setting `Chip8State::smc`; cleared on reset, since the memory it describes is.
+#[derive(Clone, Debug)]
+pub struct SmcDetector {
+    executed: Vec<bool>,
+    events: VecDeque<SmcEvent>,
+    count: u64,
+    /// Modified address to the address of the instruction that last wrote to it
+    modified: BTreeMap<u16, u16>,
+}
+
+impl Default for SmcDetector {
+    fn default() -> Self {
+        Self { executed: vec![false; 4096], events: VecDeque::new(), count: 0, modified: BTreeMap::new() }
+    }
+}
+
+impl SmcDetector {
+    /// Notes the instruction at `address` being executed.
+    pub fn record_execution(&mut self, address: u16) {
+        for offset in 0..u16::from(INSTR_SIZE) {
+            self.executed[usize::from(address.wrapping_add(offset) & 0x0FFF)] = true;
+        }
+    }
+
+    /// Checks a write made by the instruction at `pc`, recording an event if it changes code.
+    pub fn check_write(&mut self, pc: u16, address: u16, old_value: u8, new_value: u8) {
+        let address = address & 0x0FFF;
+        let next = pc.wrapping_add(u16::from(INSTR_SIZE)) & 0x0FFF;
+        let is_next = address.wrapping_sub(next) < u16::from(INSTR_SIZE);
+
+        if old_value != new_value && (self.executed[usize::from(address)] || is_next) {
+            if self.events.len() == EVENT_HISTORY {
+                self.events.pop_front();
+            }
+            self.events.push_back(SmcEvent { pc, address, old_value, new_value });
+            self.count += 1;
+            self.modified.insert(address, pc);
+        }
+    }
+
+    /// The last `EVENT_HISTORY` events, oldest first.
+    #[must_use]
+    pub fn events(&self) -> &VecDeque<SmcEvent> {
+        &self.events
+    }
+
+    /// How many events there were since the detector was enabled or cleared, including ones dropped from `events`.
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The address of the instruction that last modified `address`, if any did.
+    #[must_use]
+    pub fn modified_by(&self, address: u16) -> Option<u16> {
+        self.modified.get(&(address & 0x0FFF)).copied()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let mut smc = SmcDetector::default();
+        smc.record_execution(0x200);
+
+        smc.check_write(0x300, 0x201, 0x05, 0x06);
+        smc.check_write(0x300, 0x302, 0x00, 0x12);
+        smc.check_write(0x300, 0x304, 0x00, 0x12);
+        smc.check_write(0x300, 0x200, 0x6A, 0x6A);
+
+        assert_eq!(smc.events(), &[
+            SmcEvent { pc: 0x300, address: 0x201, old_value: 0x05, new_value: 0x06 },
+            SmcEvent { pc: 0x300, address: 0x302, old_value: 0x00, new_value: 0x12 },
+        ]);
+        assert_eq!(smc.modified_by(0x201), Some(0x300));
+        assert_eq!(smc.modified_by(0x200), None)
+    }
+
+    #[test]
+    fn test_history() {
+        let mut smc = SmcDetector::default();
+        smc.record_execution(0x200);
+
+        for value in 1..=300 {
+            #[allow(clippy::cast_possible_truncation)]
+            smc.check_write(0x300, 0x200, (value - 1) as u8, value as u8);
+        }
+
+        assert_eq!(smc.count(), 300);
+        assert_eq!(smc.events().len(), EVENT_HISTORY);
+        assert_eq!(smc.events().front(), Some(&SmcEvent { pc: 0x300, address: 0x200, old_value: 44, new_value: 45 }));
+        assert_eq!(smc.events().back(), Some(&SmcEvent { pc: 0x300, address: 0x200, old_value: 43, new_value: 44 }))
+    }
+}
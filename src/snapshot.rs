@@ -0,0 +1,203 @@
+use std::{error::Error, fmt};
+
+use crate::{Chip8State, layout::MemoryLayout, quirks::Quirks, stack::{StackConfig, StackError}, variant::{IoPort, Variant}};
+
+const MAGIC: &[u8; 4] = b"C8SS";
+/// Format version written by `save_state`; older or newer snapshots are refused
+pub const SNAPSHOT_VERSION: u8 = 4;
+const SNAPSHOT_SIZE: usize = 4725;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SnapshotError {
+    /// The data does not start like a snapshot
+    NotASnapshot,
+    /// The snapshot was written in another format version
+    Version(u8),
+    /// The snapshot has the wrong length or impossible values
+    Corrupt,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotASnapshot => write!(f, "not a CHIP-8 state snapshot"),
+            Self::Version(version) => write!(f, "snapshot format version {version} is not supported, expected {SNAPSHOT_VERSION}"),
+            Self::Corrupt => write!(f, "snapshot is corrupt"),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl Chip8State {
+    /// Captures the machine: registers, stack, timers, display, memory, held keys, quirks, stack configuration, memory layout,
+    /// and the instruction set variant with its colors and I/O port.
+    /// The random number generator, the VIP and the analysis tools are left out.
+    #[must_use]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(SNAPSHOT_SIZE);
+        data.extend_from_slice(MAGIC);
+        data.push(SNAPSHOT_VERSION);
+        data.extend_from_slice(&self.registers);
+        data.extend_from_slice(&self.index.to_be_bytes());
+        data.extend_from_slice(&self.stack);
+        data.push(self.sp);
+        data.extend_from_slice(&self.pc.to_be_bytes());
+        data.extend_from_slice(&[self.dt, self.st]);
+        data.extend_from_slice(&self.framebuffer);
+        data.extend_from_slice(&self.memory);
+        data.extend_from_slice(&self.input.to_be_bytes());
+        let quirks = self.quirks;
+        data.extend(quirks.flags().map(u8::from));
+        data.extend_from_slice(&[self.stack_config.max_depth, u8::from(self.stack_config.in_memory)]);
+        for addr in [self.layout.font_address, self.layout.program_start, self.layout.initial_pc] {
+            data.extend_from_slice(&addr.to_be_bytes());
+        }
+        data.extend_from_slice(&match self.stack_error {
+            None => [0, 0],
+            Some(StackError::Overflow { depth }) => [1, depth],
+            Some(StackError::Underflow) => [2, 0],
+        });
+        data.push(self.variant as u8);
+        data.extend_from_slice(&self.colors);
+        data.push(self.background);
+        let io = self.io;
+        data.extend_from_slice(&[u8::from(io.output.is_some()), io.output.unwrap_or(0), io.input, u8::from(io.strobe)]);
+        data.push(u8::from(self.delay_wait));
+
+        data
+    }
+
+    /// A short hash of everything `save_state` captures, for checking that two runs of a program stayed in step.
+    #[must_use]
+    pub fn state_hash(&self) -> u64 {
+        let digest = sha1_smol::Sha1::from(self.save_state()).digest().bytes();
+        u64::from_be_bytes([digest[0], digest[1], digest[2], digest[3], digest[4], digest[5], digest[6], digest[7]])
+    }
+
+    /// Puts the machine back as `save_state` captured it.
+    ///
+    /// # Errors
+    ///
+    /// Fails without changing the state if `data` is not a snapshot of the current version.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        if !data.starts_with(MAGIC) || data.len() < MAGIC.len() + 1 {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        if data[MAGIC.len()] != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version(data[MAGIC.len()]));
+        }
+        if data.len() != SNAPSHOT_SIZE {
+            return Err(SnapshotError::Corrupt);
+        }
+
+        let mut reader = Reader(&data[MAGIC.len() + 1..]);
+        let registers = reader.array();
+        let index = u16::from_be_bytes(reader.array());
+        let stack = reader.array();
+        let [sp] = reader.array();
+        let pc = u16::from_be_bytes(reader.array());
+        let [dt, st] = reader.array();
+        let framebuffer = reader.array();
+        let memory = reader.array();
+        let input = u16::from_be_bytes(reader.array());
+        let quirks = Quirks::from_flags(reader.array().map(|flag: u8| flag != 0));
+        let [max_depth, in_memory] = reader.array();
+        let stack_config = StackConfig { max_depth, in_memory: in_memory != 0 };
+        let [font_address, program_start, initial_pc] = [(); 3].map(|()| u16::from_be_bytes(reader.array()));
+        let layout = MemoryLayout { font_address, program_start, initial_pc };
+        let stack_error = match reader.array() {
+            [0, _] => None,
+            [1, depth] => Some(StackError::Overflow { depth }),
+            [2, _] => Some(StackError::Underflow),
+            _ => return Err(SnapshotError::Corrupt),
+        };
+        let [variant] = reader.array();
+        let variant = *Variant::ALL.get(usize::from(variant)).ok_or(SnapshotError::Corrupt)?;
+        let colors = reader.array();
+        let [background] = reader.array();
+        let [has_output, output, input_byte, strobe] = reader.array();
+        let io = IoPort { output: (has_output != 0).then_some(output), input: input_byte, strobe: strobe != 0 };
+        let [delay_wait] = reader.array();
+        // the stack pointer indexes the stack, so it has to stay inside it, and the layout has to fit in memory
+        if sp % 2 != 0 || sp > 2 * stack_config.depth() || layout.validate().is_err() {
+            return Err(SnapshotError::Corrupt);
+        }
+
+        self.registers = registers;
+        self.index = index;
+        self.stack = stack;
+        self.sp = sp;
+        self.pc = pc & 0x0FFF;
+        self.dt = dt;
+        self.st = st;
+        self.framebuffer = framebuffer;
+        self.memory = memory;
+        self.input = input;
+        self.quirks = quirks;
+        self.stack_config = stack_config;
+        self.layout = layout;
+        self.stack_error = stack_error;
+        self.variant = variant;
+        self.colors = colors;
+        self.background = background;
+        self.io = io;
+        self.delay_wait = delay_wait != 0;
+        Ok(())
+    }
+}
+
+/// Takes fixed-size fields off the front of a snapshot whose length was already checked
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn array<const N: usize>(&mut self) -> [u8; N] {
+        let (field, rest) = self.0.split_at(N);
+        self.0 = rest;
+        field.try_into().unwrap_or([0; N])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load() {
+        let mut state = Chip8State::default();
+        // 200: LDVB(V3, 42)  202: CALL(208)  ...  208: DRW(V0, V0, 5)
+        state.load_program(&[0x63, 0x42, 0x22, 0x08, 0x00, 0x00, 0x00, 0x00, 0xD0, 0x05]).unwrap();
+        state.quirks = Quirks::VIP;
+        state.layout.font_address = 0x050;
+        state.variant = Variant::Chip8X;
+        state.colors[17] = 6;
+        state.io.send(0x42);
+        state.run_frame(3);
+        let saved = state.save_state();
+        assert_eq!(saved.len(), SNAPSHOT_SIZE);
+
+        let mut restored = Chip8State::default();
+        restored.load_state(&saved).unwrap();
+
+        assert_eq!(restored.registers[3], 0x42);
+        assert_eq!(restored.call_stack(), state.call_stack());
+        assert_eq!(restored.framebuffer, state.framebuffer);
+        assert_eq!(restored.quirks, Quirks::VIP);
+        assert_eq!(restored.layout.font_address, 0x050);
+        assert_eq!(restored.variant, Variant::Chip8X);
+        assert_eq!(restored.colors[17], 6);
+        assert_eq!(restored.io, state.io);
+        assert_eq!(restored.save_state(), saved)
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let mut state = Chip8State::default();
+        let mut saved = state.save_state();
+
+        assert_eq!(state.load_state(b"PNG"), Err(SnapshotError::NotASnapshot));
+        assert_eq!(state.load_state(&saved[..100]), Err(SnapshotError::Corrupt));
+        saved[4] = 9;
+        assert_eq!(state.load_state(&saved), Err(SnapshotError::Version(9)))
+    }
+}
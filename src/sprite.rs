@@ -154,10 +154,12 @@ pub static DEFAULT_SPRITES: &[DefaultSize; 16] = &[
     },
 ];
 
+/// Stores the font at the memory layout's font address.
 pub fn store_default_sprites(state: &mut Chip8State) {
+    let font_address = usize::from(state.layout.font_address);
     for (s, sprite) in DEFAULT_SPRITES.iter().enumerate() {
         for i in 0..DEF_SPRITE_HEIGHT {
-            state.memory[s * DEF_SPRITE_HEIGHT + i] = sprite.rows[i];
+            state.memory[(font_address + s * DEF_SPRITE_HEIGHT + i) % 4096] = sprite.rows[i];
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,58 @@
+use std::{error::Error, fmt};
+
+/// Where the COSMAC VIP interpreter's stack starts; its return addresses grow down from here, high byte first
+pub const VIP_STACK_TOP: u16 = 0xECF;
+/// Entries that fit in `Chip8State::stack`
+pub const MAX_DEPTH: u8 = 32;
+/// Entries that fit in the VIP's reserved area, 0xEA0 to 0xECF
+pub const MAX_MEMORY_DEPTH: u8 = 24;
+
+/// How deep calls may nest, and where return addresses are kept. The default allows `MAX_DEPTH` calls, kept in
+/// `Chip8State::stack` out of programs' reach.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StackConfig {
+    /// Nested calls allowed; capped at `MAX_DEPTH`, or `MAX_MEMORY_DEPTH` in memory
+    pub max_depth: u8,
+    /// Keep return addresses in emulated memory below `VIP_STACK_TOP` instead of the `CALL` sites in `Chip8State::stack`,
+    /// so programs that read or overwrite them see what they would on the VIP
+    pub in_memory: bool,
+}
+
+impl Default for StackConfig {
+    fn default() -> Self {
+        Self { max_depth: MAX_DEPTH, in_memory: false }
+    }
+}
+
+impl StackConfig {
+    /// The original COSMAC VIP interpreter
+    pub const VIP: Self = Self { max_depth: 12, in_memory: true };
+    /// SUPER-CHIP on the HP 48
+    pub const SCHIP: Self = Self { max_depth: 16, in_memory: false };
+
+    /// Nested calls that actually fit.
+    #[must_use]
+    pub fn depth(&self) -> u8 {
+        self.max_depth.min(if self.in_memory { MAX_MEMORY_DEPTH } else { MAX_DEPTH })
+    }
+}
+
+/// A `CALL` or `RET` the stack could not take. Execution halts on the failing instruction until reset.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StackError {
+    /// A `CALL` nested deeper than `depth` calls
+    Overflow { depth: u8 },
+    /// A `RET` with no call to return from
+    Underflow,
+}
+
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overflow { depth } => write!(f, "stack overflow: more than {depth} nested calls"),
+            Self::Underflow => write!(f, "stack underflow: return without a call"),
+        }
+    }
+}
+
+impl Error for StackError {}
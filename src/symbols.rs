@@ -0,0 +1,272 @@
+use std::{collections::{BTreeMap, HashMap}, error::Error, fmt, fs, io, path::Path};
+
+/// Label addresses exported by an assembler, for annotating addresses while debugging.
+///
+/// Two line formats are understood, and may be mixed:
+/// * `label = 0x2a4` (the value may also be decimal)
+/// * Octo-style directives: `:label name 0x2a4`, `:const name 0x2a4` and `:breakpoint name 0x2a4`
+///
+/// Constants are not addresses, so they never name one; they may be any 16-bit value, and only `resolve` reads them.
+/// Blank lines and lines starting with `#` or `;` are ignored.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Symbols {
+    by_name: HashMap<String, u16>,
+    by_addr: BTreeMap<u16, String>,
+    constants: HashMap<String, u16>,
+    breakpoints: Vec<u16>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SymbolError {
+    /// 1-based line number of the offending line
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl Error for SymbolError {}
+
+impl Symbols {
+    /// # Errors
+    ///
+    /// Returns the first line that is neither a recognized symbol definition nor a comment.
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        let mut symbols = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let error = |reason| SymbolError { line: i + 1, reason };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with(":alias") {
+                // register aliases carry no address
+                continue;
+            }
+
+            let (directive, name, value) = if let Some((name, value)) = line.split_once('=') {
+                (":label", name.trim(), value.trim())
+            } else {
+                let mut words = line.split_whitespace();
+                match (words.next(), words.next(), words.next(), words.next()) {
+                    (Some(directive), Some(name), Some(value), None) => (directive, name, value),
+                    _ => return Err(error("expected `label = address` or an Octo directive")),
+                }
+            };
+
+            if !is_valid_name(name) {
+                return Err(error("invalid label name"));
+            }
+            if directive == ":const" {
+                let value = parse_number(value).ok_or(error("invalid constant"))?;
+                symbols.constants.insert(String::from(name), value);
+                continue;
+            }
+            let address = parse_number(value).filter(|addr| *addr < 0x1000).ok_or(error("invalid address"))?;
+
+            match directive {
+                ":label" => symbols.insert(name, address),
+                ":breakpoint" => {
+                    symbols.insert(name, address);
+                    symbols.breakpoints.push(address);
+                },
+                _ => return Err(error("unknown directive")),
+            }
+        }
+
+        Ok(symbols)
+    }
+
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read or does not parse.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Defines or moves the label `name`. An address with several labels keeps the name it has while that label stays.
+    pub fn insert(&mut self, name: &str, address: u16) {
+        if let Some(old) = self.by_name.insert(String::from(name), address) {
+            if self.by_addr.get(&old).is_some_and(|named| named == name) {
+                self.by_addr.remove(&old);
+                // another label still at the old address names it instead
+                let other = self.by_name.iter().filter(|(_, addr)| **addr == old).map(|(other, _)| other).min();
+                if let Some(other) = other.cloned() {
+                    self.by_addr.insert(old, other);
+                }
+            }
+        }
+        self.by_addr.entry(address).or_insert_with(|| String::from(name));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty() && self.constants.is_empty()
+    }
+
+    /// The label defined exactly at `address`, if any.
+    #[must_use]
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.by_addr.get(&address).map(String::as_str)
+    }
+
+    #[must_use]
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.by_name.get(name).copied()
+    }
+
+    /// The value of a `:const`.
+    #[must_use]
+    pub fn constant(&self, name: &str) -> Option<u16> {
+        self.constants.get(name).copied()
+    }
+
+    /// Addresses marked with `:breakpoint` directives.
+    #[must_use]
+    pub fn breakpoints(&self) -> &[u16] {
+        &self.breakpoints
+    }
+
+    /// The first label within `start..end`.
+    #[must_use]
+    pub fn first_in(&self, start: u16, end: u16) -> Option<(u16, &str)> {
+        self.by_addr.range(start..end).next().map(|(addr, name)| (*addr, name.as_str()))
+    }
+
+    /// Names `address` relative to the closest label at or below it, e.g. `draw+4`, with the offset in hex.
+    #[must_use]
+    pub fn describe(&self, address: u16) -> Option<String> {
+        self.by_addr.range(..=address).next_back().map(|(addr, name)| match address - addr {
+            0 => name.clone(),
+            offset => format!("{name}+{offset:X}"),
+        })
+    }
+
+    /// Resolves user input naming an address: a label or constant, `label+offset`, or a number.
+    /// Unlike in symbol files, bare numbers are read as hex here; prefix decimal numbers with `#`.
+    #[must_use]
+    pub fn resolve(&self, text: &str) -> Option<u16> {
+        let text = text.trim();
+        let (base, offset) = match text.split_once('+') {
+            Some((base, offset)) => (base.trim(), parse_input_number(offset.trim())?),
+            None => (text, 0),
+        };
+
+        let base = self.address(base).or_else(|| self.constant(base)).or_else(|| parse_input_number(base))?;
+
+        base.checked_add(offset).filter(|addr| *addr < 0x1000)
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u16::from_str_radix(hex, 16).ok()
+    } else if let Some(dec) = text.strip_prefix('#') {
+        dec.parse().ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+fn parse_input_number(text: &str) -> Option<u16> {
+    match text.strip_prefix('#') {
+        Some(dec) => dec.parse().ok(),
+        None => u16::from_str_radix(text.trim_start_matches("0x").trim_start_matches("0X"), 16).ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let symbols = Symbols::parse("# comment\nmain = 0x200\n\ndraw = 676\n:const score 0x3F0\n:breakpoint loop 0x2B0\n").unwrap();
+
+        assert_eq!(symbols.address("main"), Some(0x200));
+        assert_eq!(symbols.address("draw"), Some(0x2A4));
+        assert_eq!(symbols.constant("score"), Some(0x3F0));
+        assert_eq!(symbols.name(0x3F0), None);
+        assert_eq!(symbols.name(0x2B0), Some("loop"));
+        assert_eq!(symbols.breakpoints(), &[0x2B0])
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Symbols::parse("main = 0x200\nbad line"), Err(SymbolError { line: 2, reason: "expected `label = address` or an Octo directive" }));
+        assert_eq!(Symbols::parse("main = 0x1000"), Err(SymbolError { line: 1, reason: "invalid address" }));
+        assert_eq!(Symbols::parse("2main = 0x200"), Err(SymbolError { line: 1, reason: "invalid label name" }));
+        assert_eq!(Symbols::parse(":unpack a 0x200"), Err(SymbolError { line: 1, reason: "unknown directive" }));
+        assert_eq!(Symbols::parse(":alias x v0").map(|symbols| symbols.is_empty()), Ok(true));
+        assert_eq!(Symbols::parse(":const big 0x10000"), Err(SymbolError { line: 1, reason: "invalid constant" }))
+    }
+
+    #[test]
+    fn test_constants() {
+        // constants past memory, like a score limit or a key mask, leave the labels alone
+        let symbols = Symbols::parse(":const limit 0x1388
+:const sprite 0x300
+main = 0x200
+").unwrap();
+
+        assert_eq!(symbols.constant("limit"), Some(5000));
+        assert_eq!(symbols.address("limit"), None);
+        assert_eq!(symbols.describe(0x300), Some(String::from("main+100")));
+        assert_eq!(symbols.resolve("sprite+2"), Some(0x302));
+        assert_eq!(symbols.resolve("limit"), None)
+    }
+
+    #[test]
+    fn test_redefine() {
+        let mut symbols = Symbols::parse("main = 0x200\nstart = 0x200\n").unwrap();
+        assert_eq!(symbols.name(0x200), Some("main"));
+
+        // moving the label that does not name the address leaves the name alone
+        symbols.insert("start", 0x210);
+        assert_eq!(symbols.name(0x200), Some("main"));
+        assert_eq!(symbols.name(0x210), Some("start"));
+
+        // moving the one that does hands the name to a label left there
+        symbols.insert("start", 0x200);
+        symbols.insert("main", 0x220);
+        assert_eq!(symbols.name(0x200), Some("start"));
+        assert_eq!(symbols.name(0x220), Some("main"));
+        assert_eq!(symbols.address("start"), Some(0x200))
+    }
+
+    #[test]
+    fn test_describe() {
+        let symbols = Symbols::parse("main = 0x200\ndraw = 0x2A4").unwrap();
+
+        assert_eq!(symbols.describe(0x1FF), None);
+        assert_eq!(symbols.describe(0x200), Some(String::from("main")));
+        assert_eq!(symbols.describe(0x2A0), Some(String::from("main+A0")));
+        assert_eq!(symbols.describe(0x2A6), Some(String::from("draw+2")))
+    }
+
+    #[test]
+    fn test_resolve() {
+        let symbols = Symbols::parse("draw = 0x2A4").unwrap();
+
+        assert_eq!(symbols.resolve("draw"), Some(0x2A4));
+        assert_eq!(symbols.resolve("draw+4"), Some(0x2A8));
+        assert_eq!(symbols.resolve("draw + 0x10"), Some(0x2B4));
+        assert_eq!(symbols.resolve("0x300"), Some(0x300));
+        assert_eq!(symbols.resolve("200"), Some(0x200));
+        assert_eq!(symbols.resolve("2A6"), Some(0x2A6));
+        assert_eq!(symbols.resolve("#512"), Some(0x200));
+        assert_eq!(symbols.resolve("FFFF"), None);
+        assert_eq!(symbols.resolve("missing"), None)
+    }
+}
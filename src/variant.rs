@@ -0,0 +1,99 @@
+use crate::PROGRAM_START;
+
+/// Instruction sets extending the original CHIP-8. Each decodes the CHIP-8 instructions it keeps as CHIP-8 does;
+/// see `Opcode::decode` for what each adds and replaces.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Variant {
+    #[default]
+    Chip8,
+    /// RCA's color extension for the VP-590 color board: background and foreground colors, and an I/O port
+    Chip8X,
+    /// Gilles Detillieux's extension: relative branches, register ranges, delays and an I/O port
+    Chip8E,
+}
+
+impl Variant {
+    pub const ALL: [Self; 3] = [Self::Chip8, Self::Chip8X, Self::Chip8E];
+    pub const NAMES: [&'static str; 3] = ["chip8", "chip8x", "chip8e"];
+
+    /// Looks up a variant by one of the names in `NAMES`, also accepting them with a dash.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase().replace('-', "");
+        Self::NAMES.iter().position(|known| *known == name).map(|i| Self::ALL[i])
+    }
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+
+    /// The variant of a platform in the ROM database, for the platforms with instructions of their own.
+    #[must_use]
+    pub fn from_platform(id: &str) -> Option<Self> {
+        match id {
+            "chip8x" => Some(Self::Chip8X),
+            "chip8e" => Some(Self::Chip8E),
+            _ => None,
+        }
+    }
+
+    /// Where programs for the variant are loaded: the CHIP-8X interpreter takes up memory up to 0x300.
+    #[must_use]
+    pub fn program_start(self) -> u16 {
+        match self {
+            Self::Chip8X => 0x300,
+            Self::Chip8 | Self::Chip8E => PROGRAM_START,
+        }
+    }
+}
+
+/// CHIP-8X colors as the VP-590 numbers them: bit 0 is red, bit 1 blue and bit 2 green
+pub mod color {
+    pub const BLACK: u8 = 0;
+    pub const RED: u8 = 1;
+    pub const BLUE: u8 = 2;
+    pub const MAGENTA: u8 = 3;
+    pub const GREEN: u8 = 4;
+    pub const YELLOW: u8 = 5;
+    pub const CYAN: u8 = 6;
+    pub const WHITE: u8 = 7;
+
+    /// The background colors `02A0` steps through, starting from the first after a reset
+    pub const BACKGROUNDS: [u8; 4] = [BLUE, BLACK, GREEN, RED];
+    /// The foreground color of the whole display after a reset
+    pub const DEFAULT_FOREGROUND: u8 = RED;
+}
+
+/// The byte-wide I/O port of CHIP-8X (`FXF8`, `FXFB`) and CHIP-8E (`FX03`, `FXE3`, `FXE7`), which frontends connect to
+/// whatever they emulate on the other side.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct IoPort {
+    /// The last byte the program wrote, until the frontend takes it
+    pub output: Option<u8>,
+    /// The byte the frontend presents to the program
+    pub input: u8,
+    /// Set when the frontend presents a new input byte, and cleared by the instructions that wait for one
+    pub strobe: bool,
+}
+
+impl IoPort {
+    /// Presents a byte to the program, releasing an instruction waiting for input.
+    pub fn send(&mut self, byte: u8) {
+        self.input = byte;
+        self.strobe = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Variant::from_name("CHIP-8X"), Some(Variant::Chip8X));
+        assert_eq!(Variant::from_name("chip8e").map(Variant::name), Some("chip8e"));
+        assert_eq!(Variant::from_name("schip"), None)
+    }
+}
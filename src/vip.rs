@@ -0,0 +1,343 @@
+use crate::{Chip8State, INSTR_SIZE, ProgramTooLarge, cdp1802::{Bus, Cdp1802}, opcode::WaitStatus, stack::VIP_STACK_TOP};
+
+/// Machine cycles in a frame of the CDP1861 video chip: 262 lines of 14 cycles
+pub const FRAME_CYCLES: u32 = 3668;
+const LINE_CYCLES: u32 = 14;
+/// The 128 lines the 1861 displays, taking 8 bytes by DMA at the start of each
+const FIRST_DISPLAY_LINE: u32 = 64;
+const END_DISPLAY_LINE: u32 = 192;
+/// The 1861 interrupts 29 cycles before its first DMA, time for the interrupt routine to point R0 at the display
+const INTERRUPT_CYCLE: u32 = FIRST_DISPLAY_LINE * LINE_CYCLES - 29;
+/// Display lines showing each row of the 64x32 display
+const LINES_PER_ROW: u32 = 4;
+
+/// Where the VIP interpreter keeps V0 to VF, and the display it draws on, in a 4 KB machine
+pub const VARIABLES: u16 = 0x0EF0;
+pub const DISPLAY: u16 = 0x0F00;
+/// The register the VIP interpreter returns to after each instruction, and machine code subroutines return with `SEP 4`
+const RETURN_REGISTER: u8 = 4;
+
+/// A COSMAC VIP around its CDP1802, sharing `Chip8State::memory` as its 4 KB of RAM. While `Chip8State::vip` holds one,
+/// `0NNN` calls the 1802 machine code at NNN, which returns with `SEP 4` like on the VIP; with an interpreter image, the
+/// 1802 runs the whole program through that interpreter instead of the emulator executing CHIP-8 itself.
+#[derive(Clone, Debug, Default)]
+pub struct Vip {
+    pub cpu: Cdp1802,
+    interpreter: Option<Vec<u8>>,
+    monitor: Option<Vec<u8>>,
+    /// Machine cycles into the current 1861 frame
+    frame_cycle: u32,
+    /// The display line the next DMA fills
+    next_line: u32,
+    /// Set once the frame's interrupt was requested
+    interrupted: bool,
+    /// The 1861 is turned on by `INP 1` and off by `OUT 1`
+    display_on: bool,
+    /// The key `OUT 2` selected, whose state the keypad puts on EF3
+    key_latch: u8,
+    /// Set while a `0NNN` call runs
+    in_call: bool,
+}
+
+impl Vip {
+    /// A VIP for `0NNN` calls in programs the emulator runs.
+    #[must_use]
+    pub fn new() -> Self {
+        Self { cpu: Cdp1802::new(), next_line: FIRST_DISPLAY_LINE, ..Self::default() }
+    }
+
+    /// A VIP running a CHIP-8 interpreter image, like the original 512-byte VIP interpreter, which is loaded at 0 on every
+    /// reset. The program is loaded as usual, at 0x200 for the VIP interpreter.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the image does not fit in memory.
+    pub fn with_interpreter(image: &[u8]) -> Result<Self, ProgramTooLarge> {
+        if image.len() > 0x1000 {
+            return Err(ProgramTooLarge { size: image.len(), capacity: 0x1000 });
+        }
+        Ok(Self { interpreter: Some(image.to_vec()), ..Self::new() })
+    }
+
+    /// Maps the VIP's 512-byte operating system ROM at 0x8000. The VIP interpreter draws the `FX29` digits from it.
+    #[must_use]
+    pub fn with_monitor(self, rom: &[u8]) -> Self {
+        Self { monitor: Some(rom.to_vec()), ..self }
+    }
+
+    /// Whether the 1802 runs the program through an interpreter image
+    #[must_use]
+    pub fn runs_interpreter(&self) -> bool {
+        self.interpreter.is_some()
+    }
+
+    /// Resets the 1802 and the 1861 and loads the interpreter image.
+    pub(crate) fn reset(&mut self, memory: &mut [u8; 4096]) {
+        self.cpu.reset();
+        self.frame_cycle = 0;
+        self.next_line = FIRST_DISPLAY_LINE;
+        self.interrupted = false;
+        self.display_on = false;
+        self.key_latch = 0;
+        self.in_call = false;
+        if let Some(image) = &self.interpreter {
+            memory[..image.len()].copy_from_slice(image);
+            // the VIP's operating system starts the interpreter with the last page of memory in R1.1
+            self.cpu.r[1] = DISPLAY & 0xFF00;
+        }
+    }
+
+    /// Runs the machine code subroutine at `addr` for a `0NNN`, at most a frame's worth of it each time. The registers,
+    /// I, the timers and the display are put where the VIP interpreter keeps them for the call, and taken back once it
+    /// returns; until then, the instruction keeps waiting.
+    pub(crate) fn call(&mut self, state: &mut Chip8State, addr: u16) -> WaitStatus {
+        if !self.in_call {
+            self.in_call = true;
+            let variables = usize::from(VARIABLES);
+            state.memory[variables..variables + 16].copy_from_slice(&state.registers);
+            state.memory[usize::from(DISPLAY)..].copy_from_slice(&state.framebuffer);
+            let cpu = &mut self.cpu;
+            cpu.p = 3;
+            cpu.x = 2;
+            cpu.ie = false;
+            cpu.idle = false;
+            cpu.r[2] = VIP_STACK_TOP;
+            cpu.r[3] = addr;
+            cpu.r[5] = state.pc.wrapping_add(u16::from(INSTR_SIZE));
+            cpu.r[6] = VARIABLES | ((addr >> 8) & 0x0F);
+            cpu.r[7] = VARIABLES | ((addr >> 4) & 0x0F);
+            cpu.r[8] = u16::from_be_bytes([state.dt, state.st]);
+            cpu.r[0xA] = state.index;
+            cpu.r[0xB] = DISPLAY;
+        }
+
+        let mut cycles = 0;
+        while self.cpu.p != RETURN_REGISTER {
+            if cycles >= FRAME_CYCLES {
+                return WaitStatus::Waiting;
+            }
+            cycles += self.step(state);
+        }
+
+        self.in_call = false;
+        let variables = usize::from(VARIABLES);
+        state.registers.copy_from_slice(&state.memory[variables..variables + 16]);
+        state.framebuffer.copy_from_slice(&state.memory[usize::from(DISPLAY)..]);
+        [state.dt, state.st] = self.cpu.r[8].to_be_bytes();
+        state.index = self.cpu.r[0xA] & 0x0FFF;
+        // the subroutine may have moved the CHIP-8 program counter on; the instruction finishing moves it past the call
+        state.pc = self.cpu.r[5].wrapping_sub(u16::from(INSTR_SIZE)) & 0x0FFF;
+        WaitStatus::Running
+    }
+
+    /// Runs the interpreter until it has executed the next CHIP-8 instruction, when it returns to its fetch loop with
+    /// `SEP 4`, or for a frame at most. The registers, I, the program counter, the timers and the display are then read
+    /// from where the VIP interpreter keeps them.
+    pub(crate) fn run_instruction(&mut self, state: &mut Chip8State) {
+        let mut cycles = 0;
+        while cycles < FRAME_CYCLES {
+            // an interrupt returning to the fetch loop does not finish an instruction
+            let returning = self.returns_next(&state.memory);
+            cycles += self.step(state);
+            if returning && self.cpu.p == RETURN_REGISTER {
+                break;
+            }
+        }
+
+        let variables = usize::from(VARIABLES);
+        state.registers.copy_from_slice(&state.memory[variables..variables + 16]);
+        state.index = self.cpu.r[0xA] & 0x0FFF;
+        state.pc = self.cpu.r[5] & 0x0FFF;
+        [state.dt, state.st] = self.cpu.r[8].to_be_bytes();
+    }
+
+    /// Whether the next instruction is the `SEP 4` returning to the interpreter
+    fn returns_next(&self, memory: &[u8; 4096]) -> bool {
+        let pc = self.cpu.r[usize::from(self.cpu.p)];
+        self.cpu.p != RETURN_REGISTER && pc & 0x8000 == 0 && memory[usize::from(pc & 0x0FFF)] == 0xD0 | RETURN_REGISTER
+    }
+
+    /// Runs an instruction, or lets the 1861 interrupt or take the bytes of a display line first. Returns the machine
+    /// cycles taken.
+    fn step(&mut self, state: &mut Chip8State) -> u32 {
+        let mut io = VipIo { display_on: self.display_on, key_latch: self.key_latch, frame_cycle: self.frame_cycle };
+        let mut bus = VipBus { memory: &mut state.memory, monitor: self.monitor.as_deref(), keys: state.input, io: &mut io };
+        let cycles = if self.display_on && !self.interrupted && self.frame_cycle >= INTERRUPT_CYCLE {
+            self.interrupted = true;
+            u32::from(self.cpu.interrupt())
+        } else if self.next_line < END_DISPLAY_LINE && self.frame_cycle >= self.next_line * LINE_CYCLES {
+            let line = self.next_line - FIRST_DISPLAY_LINE;
+            self.next_line += 1;
+            if self.display_on {
+                let bytes: [u8; 8] = std::array::from_fn(|_| self.cpu.dma_out(&mut bus));
+                // the framebuffer takes the first of the lines showing each row
+                if line.is_multiple_of(LINES_PER_ROW) {
+                    let row = usize::try_from(line / LINES_PER_ROW).unwrap_or(0) * 8;
+                    state.framebuffer[row..row + 8].copy_from_slice(&bytes);
+                }
+                8
+            } else {
+                0
+            }
+        } else {
+            self.cpu.step(&mut bus)
+        };
+        self.display_on = io.display_on;
+        self.key_latch = io.key_latch;
+
+        self.frame_cycle += cycles;
+        if self.frame_cycle >= FRAME_CYCLES {
+            self.frame_cycle -= FRAME_CYCLES;
+            self.next_line = FIRST_DISPLAY_LINE;
+            self.interrupted = false;
+        }
+        cycles
+    }
+}
+
+/// The VIP hardware an instruction can reach besides memory
+#[derive(Default)]
+struct VipIo {
+    display_on: bool,
+    key_latch: u8,
+    frame_cycle: u32,
+}
+
+/// The VIP's address space: RAM repeated up to 0x7FFF, and the operating system ROM from 0x8000
+struct VipBus<'a> {
+    memory: &'a mut [u8; 4096],
+    monitor: Option<&'a [u8]>,
+    /// Keys held, one bit per key
+    keys: u16,
+    io: &'a mut VipIo,
+}
+
+impl Bus for VipBus<'_> {
+    fn read(&mut self, addr: u16) -> u8 {
+        if addr & 0x8000 == 0 {
+            self.memory[usize::from(addr & 0x0FFF)]
+        } else {
+            self.monitor.filter(|rom| !rom.is_empty()).map_or(0, |rom| rom[usize::from(addr & 0x7FFF) % rom.len()])
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if addr & 0x8000 == 0 {
+            self.memory[usize::from(addr & 0x0FFF)] = value;
+        }
+    }
+
+    /// `OUT 1` turns the display off, and `OUT 2` selects the key EF3 reports
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.io.display_on = false,
+            2 => self.io.key_latch = value & 0x0F,
+            _ => {},
+        }
+    }
+
+    /// `INP 1` turns the display on
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.io.display_on = true;
+        }
+        0
+    }
+
+    /// EF1 is the 1861 marking the 4 lines before the display starts and ends; EF3 is the selected key being held
+    fn flag(&self, flag: u8) -> bool {
+        let line = self.io.frame_cycle / LINE_CYCLES;
+        match flag {
+            1 => (FIRST_DISPLAY_LINE - 4..FIRST_DISPLAY_LINE).contains(&line) || (END_DISPLAY_LINE - 4..END_DISPLAY_LINE).contains(&line),
+            3 => self.keys & (1 << self.io.key_latch) != 0,
+            _ => false,
+        }
+    }
+}
+
+impl Chip8State {
+    /// Runs a `0NNN` call on the VIP, if there is one; otherwise the instruction does nothing.
+    pub(crate) fn call_machine_code(&mut self, addr: u16) -> WaitStatus {
+        let Some(mut vip) = self.vip.take() else {
+            return WaitStatus::Running;
+        };
+        let status = vip.call(self, addr);
+        self.vip = Some(vip);
+        status
+    }
+
+    /// Runs the next instruction through the VIP's interpreter image.
+    pub(crate) fn run_interpreter(&mut self) {
+        if let Some(mut vip) = self.vip.take() {
+            vip.run_instruction(self);
+            self.vip = Some(vip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_machine_code_call() {
+        let mut state = Chip8State { vip: Some(Box::new(Vip::new())), ..Chip8State::default() };
+        // 200: LDVB(V0, 5)  202: SYS(300)  204: JP(204)
+        state.load_program(&[0x60, 0x05, 0x03, 0x00, 0x12, 0x04]).unwrap();
+        // 300: LDN 6 (V3 is in R6, as the X of 0300)  ADI 3  STR 6  SEQ  REQ  SEP 4
+        state.memory[0x300..0x307].copy_from_slice(&[0x06, 0xFC, 0x03, 0x56, 0x7B, 0x7A, 0xD4]);
+        state.registers[3] = 7;
+        for _ in 0..3 {
+            state.tick(0);
+        }
+        assert_eq!((state.registers[0], state.registers[3], state.pc), (5, 10, 0x204));
+
+        // without a VIP, the call does nothing
+        state.vip = None;
+        state.reset();
+        state.load_program(&[0x60, 0x05, 0x03, 0x00, 0x12, 0x04]).unwrap();
+        for _ in 0..3 {
+            state.tick(0);
+        }
+        assert_eq!((state.registers[3], state.pc), (0, 0x204))
+    }
+
+    #[test]
+    fn test_interpreter() {
+        // a CHIP-8 interpreter reduced to its VIP skeleton: it sets up the registers, turns the display on, and then
+        // takes each instruction by adding 1 to V0; its interrupt routine shows the display and counts the timer down
+        let image = [
+            // 00: RB = F00 (display, from R1.1)  R2 = ECF (stack)  R1 = 80 (interrupt)  R4 = 20 (fetch)  R5 = 200  R8.1 = 40
+            0x91, 0xBB, 0xFF, 0x01, 0xB2, 0xF8, 0xCF, 0xA2, 0xF8, 0x00, 0xB1, 0xF8, 0x80, 0xA1, 0xF8, 0x00,
+            // 10: ...  SEX 2  INP 1  SEP 4
+            0xB4, 0xF8, 0x20, 0xA4, 0xF8, 0x02, 0xB5, 0xF8, 0x00, 0xA5, 0xF8, 0x40, 0xB8, 0xE2, 0x69, 0xD4,
+            // 20: LDA 5  LDA 5  R3 = 30  SEP 3  BR 20
+            0x45, 0x45, 0xF8, 0x30, 0xA3, 0xD3, 0x30, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // 30: R6 = EF0  LDN 6  ADI 1  STR 6  SEP 4
+            0xF8, 0x0E, 0xB6, 0xF8, 0xF0, 0xA6, 0x06, 0xFC, 0x01, 0x56, 0xD4, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // 7E: LDXA  RET
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x72, 0x70,
+            // 80: save T and D  R0 = RB  8C: each line shown 4 times, as R0 is set back 3 times, until EF1  R8.1 - 1  return
+            0x22, 0x78, 0x22, 0x52, 0x19, 0xF8, 0x00, 0xA0, 0x9B, 0xB0, 0xE2, 0xE2,
+            0x80, 0xE2, 0xE2, 0x20, 0xA0, 0xE2, 0x20, 0xA0, 0xE2, 0x20, 0xA0, 0x3C, 0x8C,
+            0x98, 0x32, 0x7E, 0xFF, 0x01, 0xB8, 0x30, 0x7E,
+        ];
+        let mut state = Chip8State { vip: Some(Box::new(Vip::with_interpreter(&image).unwrap())), ..Chip8State::default() };
+        state.reset();
+        state.load_program(&[0x12, 0x00]).unwrap();
+        for (i, byte) in state.memory[usize::from(DISPLAY)..].iter_mut().enumerate() {
+            *byte = u8::try_from(i / 8).unwrap();
+        }
+
+        for _ in 0..1000 {
+            state.tick(1);
+        }
+        assert_eq!(state.registers[0], u8::try_from(999 % 256).unwrap());
+        assert_eq!(state.pc, 0x200 + 2 * 999);
+        assert_eq!(state.framebuffer, state.memory[usize::from(DISPLAY)..]);
+        assert!((0x20..0x40).contains(&state.dt), "{}", state.dt)
+    }
+}
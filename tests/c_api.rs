@@ -0,0 +1,53 @@
+#![cfg(unix)]
+
+use std::{env, fs, path::{Path, PathBuf}, process::{Command, Output}};
+
+// 200: LDI(206)  202: DRW(V0, V0, 1)  204: CALL(204)  206: sprite 11000011
+const DRAW_THEN_RECURSE: [u8; 7] = [0xA2, 0x06, 0xD0, 0x01, 0x22, 0x04, 0xC3];
+
+/// Compiles the C example against the library cargo built, returning the executable's path
+fn compile_example() -> PathBuf {
+    // integration tests run from target/<profile>/deps, and cargo puts the libraries one level up
+    let lib_dir = env::current_exe().unwrap().parent().unwrap().parent().unwrap().to_path_buf();
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let exe = env::temp_dir().join(format!("chip8_c_example_{}", std::process::id()));
+
+    let compiled = Command::new(env::var("CC").unwrap_or_else(|_| String::from("cc")))
+        .arg(manifest_dir.join("examples/c/run_rom.c"))
+        .arg("-I").arg(manifest_dir.join("include"))
+        .arg("-L").arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .args(["-lchip8exe", "-o"]).arg(&exe)
+        .status()
+        .unwrap();
+    assert!(compiled.success());
+    exe
+}
+
+fn run_example(exe: &Path, rom: &[u8]) -> Output {
+    let path = env::temp_dir().join(format!("chip8_c_example_{}.ch8", std::process::id()));
+    fs::write(&path, rom).unwrap();
+    let output = Command::new(exe).arg(&path).arg("5").output().unwrap();
+    fs::remove_file(path).unwrap();
+    output
+}
+
+#[test]
+fn test_c_example() {
+    let exe = compile_example();
+    let crashed = run_example(&exe, &DRAW_THEN_RECURSE);
+    let too_large = run_example(&exe, &[0; 4000]);
+    fs::remove_file(exe).unwrap();
+
+    // chip8_run_frame reports the stack overflow as CHIP8_ERROR, and the framebuffer keeps what was drawn before it
+    assert_eq!(crashed.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&crashed.stderr).starts_with("crashed in frame "), "{}", String::from_utf8_lossy(&crashed.stderr));
+    let screen = String::from_utf8(crashed.stdout).unwrap();
+    let rows: Vec<_> = screen.lines().map(|row| row.trim_end_matches('.')).collect();
+    assert_eq!(rows.len(), 32);
+    assert_eq!(rows[..2], ["##....##", ""]);
+
+    // chip8_load_rom refuses a ROM that does not fit
+    assert_eq!(too_large.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&too_large.stderr).ends_with("does not fit in memory\n"))
+}